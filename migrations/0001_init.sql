@@ -0,0 +1,52 @@
+-- Markets keyed by `platform:platform_id`, matching `StandardMarket::key`.
+CREATE TABLE IF NOT EXISTS markets (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    platform TEXT NOT NULL,
+    platform_id TEXT NOT NULL,
+    open_time TEXT NOT NULL,
+    close_time TEXT NOT NULL,
+    volume_usd REAL NOT NULL,
+    num_traders INTEGER NOT NULL,
+    category TEXT NOT NULL,
+    resolution REAL NOT NULL,
+    prob_midpoint REAL NOT NULL,
+    prob_close REAL NOT NULL,
+    prob_tma REAL NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS markets_by_close_time ON markets (close_time);
+CREATE INDEX IF NOT EXISTS markets_by_platform ON markets (platform);
+CREATE INDEX IF NOT EXISTS markets_by_category ON markets (category);
+
+CREATE TABLE IF NOT EXISTS probability_updates (
+    market_id TEXT NOT NULL REFERENCES markets (id) ON DELETE CASCADE,
+    time TEXT NOT NULL,
+    probability REAL NOT NULL,
+    PRIMARY KEY (market_id, time)
+);
+
+CREATE TABLE IF NOT EXISTS forecasts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    market_id TEXT NOT NULL REFERENCES markets (id) ON DELETE CASCADE,
+    created_time TEXT NOT NULL,
+    probability REAL NOT NULL,
+    model TEXT,
+    rationale TEXT
+);
+
+CREATE INDEX IF NOT EXISTS forecasts_by_market ON forecasts (market_id);
+
+CREATE TABLE IF NOT EXISTS orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    market_id TEXT NOT NULL REFERENCES markets (id) ON DELETE CASCADE,
+    platform_order_id TEXT,
+    created_time TEXT NOT NULL,
+    outcome TEXT NOT NULL,
+    side TEXT NOT NULL,
+    price REAL,
+    amount REAL NOT NULL,
+    status TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS orders_by_market ON orders (market_id);
@@ -0,0 +1,2 @@
+-- `MarketKind` of each market; rows from before it are binary.
+ALTER TABLE markets ADD COLUMN kind TEXT NOT NULL DEFAULT 'binary';
@@ -0,0 +1,363 @@
+use super::PlatformError;
+use tiny_keccak::{Hasher, Keccak};
+
+type Result<T> = std::result::Result<T, PlatformError>;
+
+const WORD: usize = 32;
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// First four bytes of the hashed signature, e.g. `isFinalized()`.
+pub fn selector(signature: &str) -> [u8; 4] {
+    let hash = keccak256(signature.as_bytes());
+    [hash[0], hash[1], hash[2], hash[3]]
+}
+
+/// `topics[0]` of logs emitted for the event `signature`.
+pub fn event_topic(signature: &str) -> String {
+    encode_hex(&keccak256(signature.as_bytes()))
+}
+
+pub fn encode_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(bytes))
+}
+
+pub fn decode_hex(value: &str) -> Result<Vec<u8>> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    hex::decode(digits)
+        .map_err(|err| PlatformError::Abi(format!("invalid hex `{}`: {}", value, err)))
+}
+
+/// `0x`-prefixed quantity as used for block numbers.
+pub fn decode_quantity(value: &str) -> Result<u64> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    u64::from_str_radix(digits, 16)
+        .map_err(|err| PlatformError::Abi(format!("invalid quantity `{}`: {}", value, err)))
+}
+
+/// Static argument of a contract call.
+#[derive(Debug, Clone)]
+pub enum Token {
+    Address(String),
+    Uint(u128),
+    Bytes32([u8; 32]),
+}
+
+impl Token {
+    fn encode(&self) -> Result<[u8; 32]> {
+        let mut word = [0u8; 32];
+        match self {
+            Token::Address(address) => {
+                let bytes = decode_hex(address)?;
+                if bytes.len() != 20 {
+                    return Err(PlatformError::Abi(format!("invalid address `{}`", address)));
+                }
+                word[12..].copy_from_slice(&bytes);
+            }
+            Token::Uint(value) => word[16..].copy_from_slice(&value.to_be_bytes()),
+            Token::Bytes32(bytes) => word = *bytes,
+        }
+        Ok(word)
+    }
+}
+
+/// Calldata for `signature` with static arguments only.
+pub fn encode_call(signature: &str, args: &[Token]) -> Result<Vec<u8>> {
+    let mut data = selector(signature).to_vec();
+    data.extend(encode_words(args)?);
+    Ok(data)
+}
+
+/// Static arguments one word each, as hashed for EIP-712 structs.
+pub fn encode_words(args: &[Token]) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(args.len() * WORD);
+    for arg in args {
+        data.extend_from_slice(&arg.encode()?);
+    }
+    Ok(data)
+}
+
+/// Left-aligned, zero-padded, as Solidity converts a short string literal to `bytes32`.
+pub fn bytes32_from_str(value: &str) -> Result<[u8; 32]> {
+    if value.len() > WORD {
+        return Err(PlatformError::Abi(format!(
+            "`{}` does not fit in bytes32",
+            value
+        )));
+    }
+    let mut word = [0u8; 32];
+    word[..value.len()].copy_from_slice(value.as_bytes());
+    Ok(word)
+}
+
+/// Reads ABI-encoded return data or log data, where `index` counts head words.
+///
+/// Integers wider than 128 bits are rejected rather than truncated; Augur amounts
+/// are atto-denominated but stay well inside `u128`.
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn word_at(&self, offset: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(WORD)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| PlatformError::Abi(format!("no word at byte {}", offset)))
+    }
+
+    fn word(&self, index: usize) -> Result<&'a [u8]> {
+        self.word_at(index * WORD)
+    }
+
+    pub fn uint(&self, index: usize) -> Result<u128> {
+        uint_from_word(self.word(index)?)
+    }
+
+    pub fn u64(&self, index: usize) -> Result<u64> {
+        let value = self.uint(index)?;
+        u64::try_from(value).map_err(|_| PlatformError::Abi(format!("{} overflows u64", value)))
+    }
+
+    pub fn int(&self, index: usize) -> Result<i128> {
+        int_from_word(self.word(index)?)
+    }
+
+    pub fn bool(&self, index: usize) -> Result<bool> {
+        Ok(self.uint(index)? != 0)
+    }
+
+    pub fn address(&self, index: usize) -> Result<String> {
+        Ok(address_from_word(self.word(index)?))
+    }
+
+    /// Dynamic `string` whose offset is stored in head word `index`.
+    pub fn string(&self, index: usize) -> Result<String> {
+        let (start, len) = self.dynamic(index)?;
+        let bytes = start
+            .checked_add(len)
+            .and_then(|end| self.data.get(start..end))
+            .ok_or_else(|| PlatformError::Abi(format!("string of {} bytes out of range", len)))?;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    pub fn bytes32_array(&self, index: usize) -> Result<Vec<[u8; 32]>> {
+        self.array(index, |word| {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(word);
+            Ok(bytes)
+        })
+    }
+
+    pub fn uint_array(&self, index: usize) -> Result<Vec<u128>> {
+        self.array(index, uint_from_word)
+    }
+
+    pub fn int_array(&self, index: usize) -> Result<Vec<i128>> {
+        self.array(index, int_from_word)
+    }
+
+    pub fn address_array(&self, index: usize) -> Result<Vec<String>> {
+        self.array(index, |word| Ok(address_from_word(word)))
+    }
+
+    /// Start of the payload and its length (in bytes or elements).
+    fn dynamic(&self, index: usize) -> Result<(usize, usize)> {
+        let offset = usize::try_from(self.uint(index)?)
+            .map_err(|_| PlatformError::Abi("offset overflows usize".to_string()))?;
+        let len = usize::try_from(uint_from_word(self.word_at(offset)?)?)
+            .map_err(|_| PlatformError::Abi("length overflows usize".to_string()))?;
+        Ok((offset + WORD, len))
+    }
+
+    fn array<T>(&self, index: usize, decode: impl Fn(&[u8]) -> Result<T>) -> Result<Vec<T>> {
+        let (start, len) = self.dynamic(index)?;
+        (0..len)
+            .map(|element| decode(self.word_at(start + element * WORD)?))
+            .collect()
+    }
+}
+
+fn uint_from_word(word: &[u8]) -> Result<u128> {
+    if word[..16].iter().any(|byte| *byte != 0) {
+        return Err(PlatformError::Abi(format!(
+            "{} overflows u128",
+            encode_hex(word)
+        )));
+    }
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&word[16..]);
+    Ok(u128::from_be_bytes(bytes))
+}
+
+fn int_from_word(word: &[u8]) -> Result<i128> {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&word[16..]);
+    let value = i128::from_be_bytes(bytes);
+    // Sign extension must fill the high half.
+    let fill = if value < 0 { 0xff } else { 0 };
+    if word[..16].iter().any(|byte| *byte != fill) {
+        return Err(PlatformError::Abi(format!(
+            "{} overflows i128",
+            encode_hex(word)
+        )));
+    }
+    Ok(value)
+}
+
+fn address_from_word(word: &[u8]) -> String {
+    encode_hex(&word[12..])
+}
+
+/// Address held in an indexed log topic.
+pub fn topic_address(topic: &str) -> Result<String> {
+    let bytes = decode_hex(topic)?;
+    if bytes.len() != WORD {
+        return Err(PlatformError::Abi(format!("invalid topic `{}`", topic)));
+    }
+    Ok(address_from_word(&bytes))
+}
+
+/// Indexed topic matching `address`, for `eth_getLogs` filters.
+pub fn address_topic(address: &str) -> Result<String> {
+    Ok(encode_hex(&Token::Address(address.to_string()).encode()?))
+}
+
+/// Indexed topic matching an integer or enum value.
+pub fn uint_topic(value: u128) -> String {
+    let mut word = [0u8; 32];
+    word[16..].copy_from_slice(&value.to_be_bytes());
+    encode_hex(&word)
+}
+
+/// Big-endian word of a decimal `uint256`, for ids too wide for `u128` such as CLOB token ids.
+pub fn decimal_word(value: &str) -> Result<[u8; 32]> {
+    let invalid = || PlatformError::Abi(format!("invalid uint256 `{}`", value));
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    let mut word = [0u8; 32];
+    for digit in value.chars() {
+        let mut carry = digit.to_digit(10).ok_or_else(invalid)?;
+        for byte in word.iter_mut().rev() {
+            let next = *byte as u32 * 10 + carry;
+            *byte = next as u8;
+            carry = next >> 8;
+        }
+        if carry != 0 {
+            return Err(invalid());
+        }
+    }
+    Ok(word)
+}
+
+/// EIP-55 mixed-case form of a 20-byte address.
+pub fn checksum_address(address: &str) -> Result<String> {
+    let bytes = decode_hex(address)?;
+    if bytes.len() != 20 {
+        return Err(PlatformError::Abi(format!("invalid address `{}`", address)));
+    }
+    let lower = hex::encode(bytes);
+    let hash = keccak256(lower.as_bytes());
+    let mixed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(index, digit)| {
+            let nibble = (hash[index / 2] >> (if index % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                digit.to_ascii_uppercase()
+            } else {
+                digit
+            }
+        })
+        .collect();
+    Ok(format!("0x{}", mixed))
+}
+
+/// `bytes32` label as text, dropping the zero padding.
+pub fn bytes32_to_string(bytes: &[u8; 32]) -> String {
+    let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(WORD);
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const U256_MAX: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639935";
+    const U256_OVERFLOW: &str =
+        "115792089237316195423570985008687907853269984665640564039457584007913129639936";
+
+    #[test]
+    fn test_selector() {
+        assert_eq!(
+            hex::encode(selector("transfer(address,uint256)")),
+            "a9059cbb"
+        );
+        assert_eq!(
+            event_topic("Transfer(address,address,uint256)"),
+            "0xddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+        );
+    }
+
+    #[test]
+    fn test_decode_dynamic() {
+        // (uint256 7, string "yes", int256[] [-1, 2])
+        let data = decode_hex(concat!(
+            "0000000000000000000000000000000000000000000000000000000000000007",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "00000000000000000000000000000000000000000000000000000000000000a0",
+            "0000000000000000000000000000000000000000000000000000000000000003",
+            "7965730000000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+        ))
+        .unwrap();
+        let decoder = Decoder::new(&data);
+        assert_eq!(decoder.u64(0).unwrap(), 7);
+        assert_eq!(decoder.string(1).unwrap(), "yes");
+        assert_eq!(decoder.int_array(2).unwrap(), vec![-1, 2]);
+        assert!(decoder.uint(8).is_err());
+    }
+
+    #[test]
+    fn test_encode_call() {
+        let address = "0x23916a8f5c3846e3100e5f587ff14f3098722f5d";
+        let data = encode_call("getWinningPayoutNumerator(uint256)", &[Token::Uint(2)]).unwrap();
+        assert_eq!(data.len(), 4 + 32);
+        assert_eq!(data[35], 2);
+        assert_eq!(
+            topic_address(&address_topic(address).unwrap()).unwrap(),
+            address
+        );
+        assert_eq!(
+            bytes32_to_string(&bytes32_from_str("AugurTrading").unwrap()),
+            "AugurTrading"
+        );
+    }
+
+    #[test]
+    fn test_decimal_word_and_checksum() {
+        let word = decimal_word(&u128::MAX.to_string()).unwrap();
+        assert_eq!(uint_from_word(&word).unwrap(), u128::MAX);
+        assert_eq!(decimal_word(U256_MAX).unwrap(), [0xff; 32]);
+        assert!(decimal_word(U256_OVERFLOW).is_err());
+        assert!(decimal_word("12a").is_err());
+        assert_eq!(
+            checksum_address("0x2c7536e3605d9c16a7a3d7b1898e529396a65c23").unwrap(),
+            "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23"
+        );
+    }
+}
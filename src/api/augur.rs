@@ -1 +1,527 @@
+use super::abi::{self, Decoder, Token};
+use super::Result;
+use super::{Page, PageCaps, Platform, PlatformBuilder, PlatformError, RateLimit};
+use crate::augur::{AugurExtraInfo, AugurFill, AugurMarket, AugurMarketType};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use jsonrpsee::core::client::ClientT;
+use jsonrpsee::http_client::{HttpClient, HttpClientBuilder};
+use jsonrpsee::rpc_params;
+use serde::Deserialize;
+use std::time::Duration;
 
+/// Augur v2 on Ethereum mainnet. Other contracts are resolved through its registry.
+pub const AUGUR_ADDRESS: &str = "0x23916a8f5c3846e3100e5f587ff14f3098722f5d";
+/// No Augur v2 market was created before this block.
+pub const FIRST_BLOCK: u64 = 10_000_000;
+/// Blocks covered by one `eth_getLogs` request; public nodes reject much wider ranges.
+const BLOCKS_PER_PAGE: u64 = 50_000;
+
+const MARKET_CREATED: &str = "MarketCreated(address,uint256,string,address,address,address,uint256,int256[],uint8,uint256,bytes32[],uint256,uint256)";
+const ORDER_EVENT: &str =
+    "OrderEvent(address,address,uint8,uint8,bytes32,bytes32,address[],uint256[])";
+/// `OrderEventType.Fill` on the trading contract.
+const FILL_EVENT: u128 = 3;
+
+/// Reads Augur v2 markets over Ethereum JSON-RPC; the builder's endpoint is the node url.
+///
+/// Markets come from the `MarketCreated` logs of the Augur contract, their state
+/// from `eth_call`s to each market and prices from `OrderEvent` fills. Requests go
+/// through a jsonrpsee client, so the builder's rate limit and cassette do not apply.
+pub struct AugurPlatform {
+    builder: PlatformBuilder<Self>,
+    augur: String,
+    from_block: u64,
+}
+
+impl From<PlatformBuilder<Self>> for AugurPlatform {
+    fn from(value: PlatformBuilder<Self>) -> Self {
+        Self {
+            builder: value,
+            augur: AUGUR_ADDRESS.to_string(),
+            from_block: FIRST_BLOCK,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Log {
+    topics: Vec<String>,
+    data: String,
+    block_number: String,
+}
+
+impl AugurPlatform {
+    /// Reads another Augur deployment, e.g. one on a local devnet, scanning from `from_block`.
+    pub fn deployment(&mut self, augur: &str, from_block: u64) {
+        self.augur = augur.to_lowercase();
+        self.from_block = from_block;
+    }
+
+    fn rpc(&self) -> Result<HttpClient> {
+        Ok(HttpClientBuilder::default().build(&self.builder.endpoint)?)
+    }
+
+    async fn eth_call(&self, rpc: &HttpClient, to: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        let call = serde_json::json!({ "to": to, "data": abi::encode_hex(&data) });
+        let result: String = rpc.request("eth_call", rpc_params![call, "latest"]).await?;
+        abi::decode_hex(&result)
+    }
+
+    async fn logs(&self, rpc: &HttpClient, filter: serde_json::Value) -> Result<Vec<Log>> {
+        Ok(rpc.request("eth_getLogs", rpc_params![filter]).await?)
+    }
+
+    async fn block_number(&self, rpc: &HttpClient) -> Result<u64> {
+        let block: String = rpc.request("eth_blockNumber", rpc_params![]).await?;
+        abi::decode_quantity(&block)
+    }
+
+    /// Address registered under `name` in the Augur registry, e.g. "AugurTrading".
+    pub async fn lookup(&self, name: &str) -> Result<String> {
+        self.lookup_with(&self.rpc()?, name).await
+    }
+
+    async fn lookup_with(&self, rpc: &HttpClient, name: &str) -> Result<String> {
+        let data = abi::encode_call(
+            "lookup(bytes32)",
+            &[Token::Bytes32(abi::bytes32_from_str(name)?)],
+        )?;
+        let result = self.eth_call(rpc, &self.augur, data).await?;
+        Decoder::new(&result).address(0)
+    }
+
+    /// Fills for a market, oldest first.
+    async fn fills(
+        &self,
+        rpc: &HttpClient,
+        market: &str,
+        from_block: u64,
+    ) -> Result<Vec<AugurFill>> {
+        let trading = self.lookup_with(rpc, "AugurTrading").await?;
+        let filter = serde_json::json!({
+            "address": trading,
+            "topics": [
+                abi::event_topic(ORDER_EVENT),
+                null,
+                abi::address_topic(market)?,
+                abi::uint_topic(FILL_EVENT),
+            ],
+            "fromBlock": quantity(from_block),
+            "toBlock": "latest",
+        });
+        self.logs(rpc, filter)
+            .await?
+            .iter()
+            .map(decode_fill)
+            .collect()
+    }
+
+    /// Finalization, payouts and fills, which the creation event does not carry.
+    async fn load_state(&self, rpc: &HttpClient, market: &mut AugurMarket) -> Result<()> {
+        let data = abi::encode_call("isFinalized()", &[])?;
+        let result = self.eth_call(rpc, &market.address, data).await?;
+        market.finalized = Decoder::new(&result).bool(0)?;
+        market.payout_numerators.clear();
+        if market.finalized {
+            for outcome in 0..market.outcomes.len() {
+                let data = abi::encode_call(
+                    "getWinningPayoutNumerator(uint256)",
+                    &[Token::Uint(outcome as u128)],
+                )?;
+                let result = self.eth_call(rpc, &market.address, data).await?;
+                market.payout_numerators.push(Decoder::new(&result).u64(0)?);
+            }
+        }
+        market.fills = self
+            .fills(rpc, &market.address, market.block_number)
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Platform for AugurPlatform {
+    const NAME: &'static str = "augur";
+    const ENDPOINT: &'static str = "https://cloudflare-eth.com";
+    const SORT: &'static str = "order:";
+    // Informational only, see `AugurPlatform`.
+    const RATE_LIMIT: RateLimit = RateLimit::new(10, Duration::from_secs(1));
+    type Market = AugurMarket;
+    type Event = AugurMarket;
+    type Position = AugurFill;
+
+    async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
+        Ok(self.fetch_questions_page(None).await?.items)
+    }
+    /// The cursor is the next block to scan. Block ranges without markets are
+    /// skipped so a page is only empty once the chain head is reached.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
+        let rpc = self.rpc()?;
+        let mut from = match cursor {
+            Some(cursor) => cursor
+                .parse::<u64>()
+                .map_err(|_| PlatformError::Abi(format!("invalid block cursor `{}`", cursor)))?,
+            None => self.from_block,
+        };
+        let latest = self.block_number(&rpc).await?;
+        while from <= latest {
+            let to = (from + BLOCKS_PER_PAGE - 1).min(latest);
+            let filter = serde_json::json!({
+                "address": self.augur,
+                "topics": [abi::event_topic(MARKET_CREATED)],
+                "fromBlock": quantity(from),
+                "toBlock": quantity(to),
+            });
+            let items = self
+                .logs(&rpc, filter)
+                .await?
+                .iter()
+                .map(decode_market_created)
+                .collect::<Result<Vec<_>>>()?;
+            let next = (to < latest).then(|| (to + 1).to_string());
+            if !items.is_empty() || next.is_none() {
+                return Ok(Page { items, next });
+            }
+            from = to + 1;
+        }
+        Ok(Page {
+            items: Vec::new(),
+            next: None,
+        })
+    }
+    /// Scans creation logs for the market, then reads its on-chain state.
+    async fn fetch_question_by_id(&self, id: &str) -> Result<Self::Market> {
+        let id = id.to_lowercase();
+        let mut markets = self.fetch_all_questions(PageCaps::default());
+        while let Some(mut market) = markets.try_next().await? {
+            if market.address == id {
+                self.load_state(&self.rpc()?, &mut market).await?;
+                return Ok(market);
+            }
+        }
+        Err(PlatformError::NotFound(id))
+    }
+    async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
+        let mut values = Vec::new();
+        for market in self.fetch_questions().await? {
+            values.push(self.incoming_market_to_value(market).await?);
+        }
+        Ok(values)
+    }
+    // Trading goes through signed 0x orders, which this platform does not create.
+    async fn build_order(
+        &self,
+        contract_id: &str,
+        amount: f64,
+        nonce: &str,
+        outcome: &str,
+    ) -> Result<()> {
+        Err(PlatformError::Unsupported("placing Augur orders"))
+    }
+    async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>> {
+        Err(PlatformError::Unsupported("listing Augur events"))
+    }
+    /// Fills for the market at address `id`.
+    async fn fetch_orderbook(&self, id: &str) -> Result<Vec<Self::Position>> {
+        self.fills(&self.rpc()?, &id.to_lowercase(), self.from_block)
+            .await
+    }
+    /// Matches `terms` against every market description; slow against mainnet.
+    async fn fetch_markets_by_terms(&self, terms: &str) -> Result<Vec<Self::Market>> {
+        let terms = terms.to_lowercase();
+        self.fetch_all_questions(PageCaps::default())
+            .try_filter(|market| {
+                let matches = market
+                    .extra_info
+                    .description
+                    .to_lowercase()
+                    .contains(&terms);
+                async move { matches }
+            })
+            .try_collect()
+            .await
+    }
+    async fn fetch_json_by_description(&self, description: &str) -> Result<Vec<serde_json::Value>> {
+        let mut values = Vec::new();
+        for market in self.fetch_markets_by_terms(description).await? {
+            values.push(self.incoming_market_to_value(market).await?);
+        }
+        Ok(values)
+    }
+    async fn incoming_market_to_value(&self, market: Self::Market) -> Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "question": market.extra_info.description,
+            "outcomes": market.outcomes,
+            "prices": market.last_prices(),
+            "end_time": market.end_time.to_rfc3339(),
+        }))
+    }
+    async fn incoming_position_to_value(
+        &self,
+        position: Self::Position,
+    ) -> Result<serde_json::Value> {
+        Ok(serde_json::json!({
+            "outcome": position.outcome,
+            "price": position.price,
+            "amount": position.amount.to_string(),
+            "time": position.time.to_rfc3339(),
+        }))
+    }
+}
+
+fn quantity(block: u64) -> String {
+    format!("0x{:x}", block)
+}
+
+fn timestamp(seconds: u64) -> Result<DateTime<Utc>> {
+    i64::try_from(seconds)
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+        .ok_or_else(|| PlatformError::Abi(format!("invalid timestamp {}", seconds)))
+}
+
+fn small(value: u128) -> Result<u64> {
+    u64::try_from(value).map_err(|_| PlatformError::Abi(format!("{} overflows u64", value)))
+}
+
+fn topic(log: &Log, index: usize) -> Result<&str> {
+    log.topics
+        .get(index)
+        .map(String::as_str)
+        .ok_or_else(|| PlatformError::Abi(format!("log is missing topic {}", index)))
+}
+
+/// Non-indexed fields: endTime, extraInfo, market, designatedReporter,
+/// feePerCashInAttoCash, prices, marketType, numTicks, outcomes, noShowBond, timestamp.
+fn decode_market_created(log: &Log) -> Result<AugurMarket> {
+    let data = abi::decode_hex(&log.data)?;
+    let decoder = Decoder::new(&data);
+    let raw_info = decoder.string(1)?;
+    // Some early markets carry plain text instead of JSON.
+    let extra_info = serde_json::from_str(&raw_info).unwrap_or_else(|_| AugurExtraInfo {
+        description: raw_info,
+        ..Default::default()
+    });
+    let market_type = AugurMarketType::try_from(decoder.u64(6)?)
+        .map_err(|kind| PlatformError::Abi(format!("unknown market type {}", kind)))?;
+    let named = decoder
+        .bytes32_array(8)?
+        .iter()
+        .map(abi::bytes32_to_string)
+        .collect();
+    Ok(AugurMarket {
+        address: decoder.address(2)?,
+        universe: abi::topic_address(topic(log, 1)?)?,
+        creator: abi::topic_address(topic(log, 2)?)?,
+        extra_info,
+        market_type,
+        num_ticks: decoder.u64(7)?,
+        outcomes: AugurMarket::outcome_labels(market_type, named),
+        created_time: timestamp(decoder.u64(10)?)?,
+        end_time: timestamp(decoder.u64(0)?)?,
+        block_number: abi::decode_quantity(&log.block_number)?,
+        finalized: false,
+        payout_numerators: Vec::new(),
+        fills: Vec::new(),
+    })
+}
+
+/// `uint256Data` holds price, amount, outcome, tokenRefund, sharesRefund, fees,
+/// amountFilled, timestamp, sharesEscrowed and tokensEscrowed, in that order.
+fn decode_fill(log: &Log) -> Result<AugurFill> {
+    let data = abi::decode_hex(&log.data)?;
+    let values = Decoder::new(&data).uint_array(4)?;
+    let value = |index: usize| {
+        values
+            .get(index)
+            .copied()
+            .ok_or_else(|| PlatformError::Abi(format!("OrderEvent has no uint256Data[{}]", index)))
+    };
+    Ok(AugurFill {
+        market: abi::topic_address(topic(log, 2)?)?,
+        outcome: small(value(2)?)? as usize,
+        price: small(value(0)?)?,
+        amount: value(6)?,
+        time: timestamp(small(value(7)?)?)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::model::Market;
+    use axum::{routing::post, Json, Router};
+    use serde_json::{json, Value};
+
+    const AUGUR: &str = "0x00000000000000000000000000000000000a0a0a";
+    const TRADING: &str = "0x00000000000000000000000000000000000b0b0b";
+    const MARKET: &str = "0x00000000000000000000000000000000000c0c0c";
+    const CREATOR: &str = "0x00000000000000000000000000000000000d0d0d";
+
+    fn word(value: u128) -> Vec<u8> {
+        let mut word = vec![0u8; 16];
+        word.extend(value.to_be_bytes());
+        word
+    }
+
+    fn address_word(address: &str) -> Vec<u8> {
+        let mut word = vec![0u8; 12];
+        word.extend(abi::decode_hex(address).unwrap());
+        word
+    }
+
+    fn market_created_data() -> String {
+        let info = r#"{"description":"Will it rain in Lisbon on 1 May?","categories":["weather"]}"#;
+        let head_len = 11 * 32;
+        let mut info_tail = word(info.len() as u128);
+        let mut bytes = info.as_bytes().to_vec();
+        bytes.resize(info.len().div_ceil(32) * 32, 0);
+        info_tail.extend(bytes);
+        let mut prices = word(2);
+        prices.extend(word(0));
+        prices.extend(word(10u128.pow(18)));
+        let head = [
+            word(1_700_000_000),
+            word(head_len),
+            address_word(MARKET),
+            address_word(CREATOR),
+            word(0),
+            word(head_len + info_tail.len() as u128),
+            word(0),
+            word(100),
+            word(head_len + (info_tail.len() + prices.len()) as u128),
+            word(0),
+            word(1_600_000_000),
+        ];
+        let mut data = head.concat();
+        data.extend(info_tail);
+        data.extend(prices);
+        data.extend(word(0));
+        abi::encode_hex(&data)
+    }
+
+    fn fill_data() -> String {
+        let mut data = [
+            word(1),
+            word(0),
+            word(0),
+            word(5 * 32),
+            word(6 * 32),
+            word(0),
+        ]
+        .concat();
+        let values = [62, 5, 2, 0, 0, 0, 5, 1_650_000_000, 0, 0];
+        data.extend(word(values.len() as u128));
+        for value in values {
+            data.extend(word(value));
+        }
+        abi::encode_hex(&data)
+    }
+
+    fn selector(signature: &str) -> String {
+        abi::encode_hex(&abi::selector(signature))
+    }
+
+    /// Answers the handful of calls `AugurPlatform` makes, like a node with one market.
+    async fn node(Json(request): Json<Value>) -> Json<Value> {
+        let params = &request["params"];
+        let universe = abi::address_topic(AUGUR).unwrap();
+        let result = match request["method"].as_str().unwrap() {
+            "eth_blockNumber" => json!(quantity(FIRST_BLOCK + 10)),
+            "eth_getLogs" => {
+                let topic = params[0]["topics"][0].as_str().unwrap();
+                if topic == abi::event_topic(MARKET_CREATED) {
+                    let creator = abi::address_topic(CREATOR).unwrap();
+                    json!([{
+                        "topics": [topic, universe, creator],
+                        "data": market_created_data(),
+                        "blockNumber": quantity(FIRST_BLOCK + 5),
+                    }])
+                } else {
+                    let market = abi::address_topic(MARKET).unwrap();
+                    json!([{
+                        "topics": [topic, universe, market, abi::uint_topic(FILL_EVENT)],
+                        "data": fill_data(),
+                        "blockNumber": quantity(FIRST_BLOCK + 8),
+                    }])
+                }
+            }
+            "eth_call" => {
+                let data = params[0]["data"].as_str().unwrap();
+                let result = if data.starts_with(&selector("lookup(bytes32)")) {
+                    address_word(TRADING)
+                } else if data.starts_with(&selector("isFinalized()")) {
+                    word(1)
+                } else {
+                    // getWinningPayoutNumerator: everything on Yes.
+                    word(if data.ends_with('2') { 100 } else { 0 })
+                };
+                json!(abi::encode_hex(&result))
+            }
+            method => panic!("unexpected method {}", method),
+        };
+        Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+    }
+
+    async fn platform() -> AugurPlatform {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, Router::new().route("/", post(node)))
+                .await
+                .unwrap();
+        });
+        let mut builder = PlatformBuilder::new();
+        builder.endpoint(&format!("http://{}", addr));
+        let mut platform = AugurPlatform::from(builder);
+        platform.deployment(AUGUR, FIRST_BLOCK);
+        platform
+    }
+
+    #[tokio::test]
+    async fn test_fetch_questions_page() {
+        let platform = platform().await;
+        let page = platform.fetch_questions_page(None).await.unwrap();
+        assert!(page.next.is_none());
+        let market = &page.items[0];
+        assert_eq!(market.address, MARKET);
+        assert_eq!(market.creator, CREATOR);
+        assert_eq!(market.market_type, AugurMarketType::YesNo);
+        assert_eq!(market.num_ticks, 100);
+        assert_eq!(market.outcomes, vec!["Invalid", "No", "Yes"]);
+        assert_eq!(market.category(), "weather");
+        assert_eq!(market.block_number, FIRST_BLOCK + 5);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_question_by_id() {
+        let platform = platform().await;
+        let market = platform.fetch_question_by_id(MARKET).await.unwrap();
+        assert!(market.finalized);
+        assert_eq!(market.payout_numerators, vec![0, 0, 100]);
+        assert_eq!(market.fills.len(), 1);
+        assert_eq!(market.prob_latest(), 0.62);
+        assert_eq!(market.resolution(), 1.0);
+
+        assert!(matches!(
+            platform.fetch_question_by_id(TRADING).await,
+            Err(PlatformError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unsupported() {
+        let platform = platform().await;
+        assert!(matches!(
+            platform.build_order(MARKET, 1.0, "", "Yes").await,
+            Err(PlatformError::Unsupported(_))
+        ));
+        assert!(matches!(
+            platform.fetch_events(None, 0).await,
+            Err(PlatformError::Unsupported(_))
+        ));
+    }
+}
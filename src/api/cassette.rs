@@ -0,0 +1,211 @@
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CassetteMode {
+    /// Sends requests to the network and saves every response.
+    Record,
+    /// Answers from the file only; a request that was never recorded fails.
+    Replay,
+    /// Sends requests to the network without touching the file.
+    Passthrough,
+}
+
+/// One recorded response, stored under its request key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interaction {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+/// Request/response pairs on disk, keyed by method, URL with query and a hash of the body.
+///
+/// The file is a JSON object sorted by key, rewritten after every recorded
+/// response so a crashed run still leaves what it saw.
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    interactions: Mutex<BTreeMap<String, Interaction>>,
+}
+
+impl Cassette {
+    /// Loads `path` if it exists. Replay requires the file; record starts empty without it.
+    pub fn open(path: impl AsRef<Path>, mode: CassetteMode) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let interactions = match std::fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound && mode != CassetteMode::Replay =>
+            {
+                BTreeMap::new()
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            path,
+            mode,
+            interactions: Mutex::new(interactions),
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn len(&self) -> usize {
+        self.interactions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lookup(&self, key: &str) -> Option<Interaction> {
+        self.interactions.lock().unwrap().get(key).cloned()
+    }
+
+    fn record(&self, key: String, interaction: Interaction) -> std::io::Result<()> {
+        let mut interactions = self.interactions.lock().unwrap();
+        interactions.insert(key, interaction);
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_vec_pretty(&*interactions)?)
+    }
+}
+
+/// `METHOD url sha256(body)`; the URL already carries the query string.
+pub fn request_key(request: &Request) -> String {
+    let body = request
+        .body()
+        .and_then(|body| body.as_bytes())
+        .unwrap_or_default();
+    format!(
+        "{} {} {}",
+        request.method(),
+        request.url(),
+        hex::encode(Sha256::digest(body))
+    )
+}
+
+fn replay(interaction: Interaction) -> reqwest_middleware::Result<Response> {
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        builder = builder.header(name, value);
+    }
+    let response = builder
+        .body(interaction.body)
+        .map_err(reqwest_middleware::Error::middleware)?;
+    Ok(Response::from(response))
+}
+
+#[async_trait]
+impl Middleware for Cassette {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        match self.mode {
+            CassetteMode::Passthrough => next.run(req, extensions).await,
+            CassetteMode::Replay => {
+                let key = request_key(&req);
+                match self.lookup(&key) {
+                    Some(interaction) => replay(interaction),
+                    None => Err(reqwest_middleware::Error::Middleware(anyhow::anyhow!(
+                        "no recorded response for `{}` in {}",
+                        key,
+                        self.path.display()
+                    ))),
+                }
+            }
+            CassetteMode::Record => {
+                let key = request_key(&req);
+                let response = next.run(req, extensions).await?;
+                let status = response.status().as_u16();
+                let headers = response
+                    .headers()
+                    .iter()
+                    .filter_map(|(name, value)| {
+                        Some((name.to_string(), value.to_str().ok()?.to_string()))
+                    })
+                    .collect();
+                let body = response.text().await?;
+                let interaction = Interaction {
+                    status,
+                    headers,
+                    body,
+                };
+                self.record(key, interaction.clone())
+                    .map_err(reqwest_middleware::Error::middleware)?;
+                replay(interaction)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::manifold::ManifoldPlatform;
+    use crate::api::{Platform, PlatformBuilder};
+    use crate::server::mock::MockServer;
+
+    #[test]
+    fn test_request_key_includes_query_and_body() {
+        let client = reqwest::Client::new();
+        let get = client
+            .get("http://localhost/markets")
+            .query(&[("limit", "5")])
+            .build()
+            .unwrap();
+        let post = |body: &str| {
+            client
+                .post("http://localhost/bet")
+                .body(body.to_string())
+                .build()
+                .unwrap()
+        };
+
+        assert!(request_key(&get).starts_with("GET http://localhost/markets?limit=5 "));
+        assert_eq!(request_key(&post("a")), request_key(&post("a")));
+        assert_ne!(request_key(&post("a")), request_key(&post("b")));
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("cassette-{}.json", uuid::Uuid::new_v4()));
+        let server = MockServer::start().await.unwrap();
+        let url = server.url();
+
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&url);
+        builder.cassette(&path, CassetteMode::Record).unwrap();
+        let recorded = ManifoldPlatform::from(builder)
+            .fetch_questions()
+            .await
+            .unwrap();
+        drop(server);
+
+        // Same base url, but nothing is listening any more.
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&url);
+        builder.cassette(&path, CassetteMode::Replay).unwrap();
+        let platform = ManifoldPlatform::from(builder);
+        let replayed = platform.fetch_questions().await.unwrap();
+        assert_eq!(replayed.len(), recorded.len());
+        assert_eq!(replayed[0].id, recorded[0].id);
+        assert!(platform.fetch_markets_by_terms("unrecorded").await.is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+use super::abi::{checksum_address, decimal_word, encode_hex, encode_words, keccak256, Token};
+use super::error::credential;
+use super::{PlatformError, Result};
+use crate::polymarket::BookSide;
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use k256::ecdsa::SigningKey;
+use k256::elliptic_curve::sec1::ToEncodedPoint;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+
+//https://github.com/Polymarket/clob-order-utils
+pub const POLYGON_CHAIN_ID: u64 = 137;
+/// CTF exchange contracts on Polygon; neg-risk markets settle through their own.
+pub const EXCHANGE: &str = "0x4bFb41d5B3570DeFd03C39a9A4D8dE6Bd8B8982E";
+pub const NEG_RISK_EXCHANGE: &str = "0xC5d563A36AE78145C45a50134d48A1215220f80a";
+/// Taker of a public order, matchable by anyone.
+const ZERO_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+
+const DOMAIN_TYPE: &str =
+    "EIP712Domain(string name,string version,uint256 chainId,address verifyingContract)";
+const ORDER_TYPE: &str = concat!(
+    "Order(uint256 salt,address maker,address signer,address taker,uint256 tokenId,",
+    "uint256 makerAmount,uint256 takerAmount,uint256 expiration,uint256 nonce,",
+    "uint256 feeRateBps,uint8 side,uint8 signatureType)"
+);
+const DOMAIN_NAME: &str = "Polymarket CTF Exchange";
+const DOMAIN_VERSION: &str = "1";
+/// USDC and outcome shares both have six decimals.
+const UNITS: f64 = 1e6;
+/// Sizes are in hundredths of a share (or of a USDC for market buys).
+const SIZE_DECIMALS: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum OrderType {
+    /// Good till cancelled.
+    Gtc,
+    /// Good till the order's expiration.
+    Gtd,
+    /// Fill or kill.
+    Fok,
+    /// Fill and kill: whatever does not fill right away is cancelled.
+    Fak,
+}
+
+/// Who holds the funds the signing key trades with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureType {
+    /// The key's own address.
+    Eoa = 0,
+    /// A Polymarket email or Magic proxy wallet.
+    PolyProxy = 1,
+    /// A Gnosis Safe owned by the key.
+    PolyGnosisSafe = 2,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderKind {
+    /// `size` shares at `price`, resting until filled, cancelled or `expiration` (unix seconds).
+    Limit {
+        price: f64,
+        size: f64,
+        expiration: Option<u64>,
+    },
+    /// Crosses the book at once up to the worst `price`. `amount` is the USDC to
+    /// spend on a buy and the shares to sell on a sell.
+    Market { amount: f64, price: f64 },
+}
+
+/// An order before its amounts are rounded and it is signed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderArgs {
+    pub token_id: String,
+    pub side: BookSide,
+    pub kind: OrderKind,
+    /// The market's `order_price_min_tick_size`.
+    pub tick_size: f64,
+    pub neg_risk: bool,
+    pub fee_rate_bps: u64,
+    /// Exchange nonce; raising it on-chain voids every order signed with a lower one.
+    pub nonce: u64,
+}
+
+impl OrderArgs {
+    pub fn limit(token_id: &str, side: BookSide, price: f64, size: f64, tick_size: f64) -> Self {
+        Self::new(
+            token_id,
+            side,
+            OrderKind::Limit {
+                price,
+                size,
+                expiration: None,
+            },
+            tick_size,
+        )
+    }
+
+    pub fn market(token_id: &str, side: BookSide, amount: f64, price: f64, tick_size: f64) -> Self {
+        Self::new(
+            token_id,
+            side,
+            OrderKind::Market { amount, price },
+            tick_size,
+        )
+    }
+
+    fn new(token_id: &str, side: BookSide, kind: OrderKind, tick_size: f64) -> Self {
+        Self {
+            token_id: token_id.to_string(),
+            side,
+            kind,
+            tick_size,
+            neg_risk: false,
+            fee_rate_bps: 0,
+            nonce: 0,
+        }
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        match self.kind {
+            OrderKind::Limit {
+                expiration: Some(_),
+                ..
+            } => OrderType::Gtd,
+            OrderKind::Limit { .. } => OrderType::Gtc,
+            OrderKind::Market { .. } => OrderType::Fok,
+        }
+    }
+
+    /// Maker and taker amounts in six-decimal units, rounded for the tick size.
+    ///
+    /// The maker gives USDC on a buy and shares on a sell; the taker side is the
+    /// other asset, so its amount is the size times the price.
+    pub fn amounts(&self) -> Result<(u128, u128)> {
+        let price_decimals = tick_decimals(self.tick_size)?;
+        let amount_decimals = price_decimals + SIZE_DECIMALS;
+        let (price, size) = match self.kind {
+            OrderKind::Limit { price, size, .. } => (price, size),
+            OrderKind::Market { amount, price } => (price, amount),
+        };
+        let price = round_normal(price, price_decimals);
+        if price < self.tick_size || price > 1.0 - self.tick_size {
+            return Err(PlatformError::InvalidOrder(format!(
+                "price {} outside [{}, {}]",
+                price,
+                self.tick_size,
+                1.0 - self.tick_size
+            )));
+        }
+        let size = round_down(size, SIZE_DECIMALS);
+        if size <= 0.0 {
+            return Err(PlatformError::InvalidOrder(
+                "size rounds to zero".to_string(),
+            ));
+        }
+        let amounts = match (&self.kind, self.side) {
+            // A market buy is sized in USDC, so the shares it gets are the quotient.
+            (OrderKind::Market { .. }, BookSide::Buy) => {
+                (size, round_down(size / price, amount_decimals))
+            }
+            (_, BookSide::Buy) => (round_down(size * price, amount_decimals), size),
+            (_, BookSide::Sell) => (size, round_down(size * price, amount_decimals)),
+        };
+        Ok((units(amounts.0), units(amounts.1)))
+    }
+
+    fn expiration(&self) -> u64 {
+        match self.kind {
+            OrderKind::Limit {
+                expiration: Some(expiration),
+                ..
+            } => expiration,
+            _ => 0,
+        }
+    }
+
+    fn exchange(&self) -> &'static str {
+        if self.neg_risk {
+            NEG_RISK_EXCHANGE
+        } else {
+            EXCHANGE
+        }
+    }
+}
+
+/// Decimals of a tick size such as `0.01`; tick sizes are powers of ten.
+fn tick_decimals(tick_size: f64) -> Result<i32> {
+    let decimals = -tick_size.log10().round();
+    let in_range = tick_size > 0.0 && tick_size < 1.0;
+    if !in_range || (10f64.powf(-decimals) - tick_size).abs() > 1e-12 {
+        return Err(PlatformError::InvalidOrder(format!(
+            "invalid tick size {}",
+            tick_size
+        )));
+    }
+    Ok(decimals as i32)
+}
+
+fn round_normal(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+fn round_down(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    // Absorb float error such as 0.62 * 100 = 61.99999999999999.
+    (value * scale + 1e-6).floor() / scale
+}
+
+fn units(value: f64) -> u128 {
+    (value * UNITS).round() as u128
+}
+
+/// Random salt that keeps otherwise identical orders distinct; 53 bits so it
+/// survives JSON number parsing on the server.
+pub fn salt() -> u64 {
+    (uuid::Uuid::new_v4().as_u128() as u64) >> 11
+}
+
+/// The EIP-712 `Order` struct of the CTF exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub salt: u64,
+    /// Address whose funds are traded.
+    pub maker: String,
+    /// Address of the signing key.
+    pub signer: String,
+    pub taker: String,
+    pub token_id: String,
+    pub maker_amount: u128,
+    pub taker_amount: u128,
+    pub expiration: u64,
+    pub nonce: u64,
+    pub fee_rate_bps: u64,
+    pub side: BookSide,
+    pub signature_type: SignatureType,
+}
+
+impl Order {
+    pub fn struct_hash(&self) -> Result<[u8; 32]> {
+        let side = match self.side {
+            BookSide::Buy => 0,
+            BookSide::Sell => 1,
+        };
+        let data = encode_words(&[
+            Token::Bytes32(keccak256(ORDER_TYPE.as_bytes())),
+            Token::Uint(self.salt as u128),
+            Token::Address(self.maker.clone()),
+            Token::Address(self.signer.clone()),
+            Token::Address(self.taker.clone()),
+            Token::Bytes32(decimal_word(&self.token_id)?),
+            Token::Uint(self.maker_amount),
+            Token::Uint(self.taker_amount),
+            Token::Uint(self.expiration as u128),
+            Token::Uint(self.nonce as u128),
+            Token::Uint(self.fee_rate_bps as u128),
+            Token::Uint(side),
+            Token::Uint(self.signature_type as u128),
+        ])?;
+        Ok(keccak256(&data))
+    }
+
+    /// Hash the signer signs: `keccak256(0x1901 ‖ domainSeparator ‖ structHash)`.
+    pub fn digest(&self, exchange: &str, chain_id: u64) -> Result<[u8; 32]> {
+        let mut data = vec![0x19, 0x01];
+        data.extend(domain_separator(exchange, chain_id)?);
+        data.extend(self.struct_hash()?);
+        Ok(keccak256(&data))
+    }
+
+    fn signed(self, signature: String) -> SignedOrder {
+        SignedOrder {
+            salt: self.salt,
+            maker: self.maker,
+            signer: self.signer,
+            taker: self.taker,
+            token_id: self.token_id,
+            maker_amount: self.maker_amount.to_string(),
+            taker_amount: self.taker_amount.to_string(),
+            expiration: self.expiration.to_string(),
+            nonce: self.nonce.to_string(),
+            fee_rate_bps: self.fee_rate_bps.to_string(),
+            side: self.side,
+            signature_type: self.signature_type as u8,
+            signature,
+        }
+    }
+}
+
+pub fn domain_separator(exchange: &str, chain_id: u64) -> Result<[u8; 32]> {
+    let data = encode_words(&[
+        Token::Bytes32(keccak256(DOMAIN_TYPE.as_bytes())),
+        Token::Bytes32(keccak256(DOMAIN_NAME.as_bytes())),
+        Token::Bytes32(keccak256(DOMAIN_VERSION.as_bytes())),
+        Token::Uint(chain_id as u128),
+        Token::Address(exchange.to_string()),
+    ])?;
+    Ok(keccak256(&data))
+}
+
+/// An order as `POST /order` expects it, amounts as decimal strings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedOrder {
+    pub salt: u64,
+    pub maker: String,
+    pub signer: String,
+    pub taker: String,
+    pub token_id: String,
+    pub maker_amount: String,
+    pub taker_amount: String,
+    pub expiration: String,
+    pub nonce: String,
+    pub fee_rate_bps: String,
+    pub side: BookSide,
+    pub signature_type: u8,
+    pub signature: String,
+}
+
+/// Body of `POST /order`; `owner` is the API key placing it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderPayload {
+    pub order: SignedOrder,
+    pub owner: String,
+    pub order_type: OrderType,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderResponse {
+    pub success: bool,
+    #[serde(default)]
+    pub error_msg: String,
+    #[serde(rename = "orderID", default)]
+    pub order_id: String,
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CancelResponse {
+    #[serde(default)]
+    pub canceled: Vec<String>,
+    /// Reason by order id.
+    #[serde(default)]
+    pub not_canceled: HashMap<String, String>,
+}
+
+/// A signed order and the exchange's answer, which is `None` in dry-run mode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlacedOrder {
+    pub payload: OrderPayload,
+    pub response: Option<OrderResponse>,
+}
+
+/// Signs orders with a local secp256k1 key.
+pub struct ClobSigner {
+    key: SigningKey,
+    address: String,
+    funder: String,
+    signature_type: SignatureType,
+    chain_id: u64,
+}
+
+impl ClobSigner {
+    /// Trades from the key's own address on Polygon.
+    pub fn new(private_key: &str) -> Result<Self> {
+        // Keep the key itself out of error messages.
+        let invalid = || PlatformError::InvalidOrder("invalid private key".to_string());
+        let bytes = hex::decode(private_key.strip_prefix("0x").unwrap_or(private_key))
+            .map_err(|_| invalid())?;
+        let key = SigningKey::from_slice(&bytes).map_err(|_| invalid())?;
+        let point = key.verifying_key().to_encoded_point(false);
+        let address = checksum_address(&encode_hex(&keccak256(&point.as_bytes()[1..])[12..]))?;
+        Ok(Self {
+            key,
+            funder: address.clone(),
+            address,
+            signature_type: SignatureType::Eoa,
+            chain_id: POLYGON_CHAIN_ID,
+        })
+    }
+
+    /// Reads `POLYMARKET_PRIVATE_KEY`, and `POLYMARKET_FUNDER` when trading from a
+    /// Polymarket proxy wallet.
+    pub fn from_env() -> Result<Self> {
+        let signer = Self::new(&credential("POLYMARKET_PRIVATE_KEY")?)?;
+        match std::env::var("POLYMARKET_FUNDER") {
+            Ok(funder) => signer.with_funder(&funder, SignatureType::PolyProxy),
+            Err(_) => Ok(signer),
+        }
+    }
+
+    pub fn with_funder(mut self, funder: &str, signature_type: SignatureType) -> Result<Self> {
+        self.funder = checksum_address(funder)?;
+        self.signature_type = signature_type;
+        Ok(self)
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Rounds and signs `args` for the API key `owner`. Nothing is sent.
+    pub fn sign_order(&self, args: &OrderArgs, owner: &str, salt: u64) -> Result<OrderPayload> {
+        let (maker_amount, taker_amount) = args.amounts()?;
+        let order = Order {
+            salt,
+            maker: self.funder.clone(),
+            signer: self.address.clone(),
+            taker: ZERO_ADDRESS.to_string(),
+            token_id: args.token_id.clone(),
+            maker_amount,
+            taker_amount,
+            expiration: args.expiration(),
+            nonce: args.nonce,
+            fee_rate_bps: args.fee_rate_bps,
+            side: args.side,
+            signature_type: self.signature_type,
+        };
+        let signature = self.sign_digest(&order.digest(args.exchange(), self.chain_id)?)?;
+        Ok(OrderPayload {
+            order: order.signed(signature),
+            owner: owner.to_string(),
+            order_type: args.order_type(),
+        })
+    }
+
+    /// 65-byte `r ‖ s ‖ v` signature with `v` in {27, 28}.
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<String> {
+        let (signature, recovery_id) = self
+            .key
+            .sign_prehash_recoverable(digest)
+            .map_err(|err| PlatformError::InvalidOrder(err.to_string()))?;
+        let mut bytes = signature.to_bytes().to_vec();
+        bytes.push(27 + recovery_id.to_byte());
+        Ok(encode_hex(&bytes))
+    }
+}
+
+/// Level 2 credentials of the CLOB, issued for a signing key by `/auth/api-key`.
+#[derive(Clone)]
+pub struct ApiCreds {
+    pub api_key: String,
+    /// URL-safe base64 HMAC key.
+    pub secret: String,
+    pub passphrase: String,
+}
+
+impl ApiCreds {
+    pub fn from_env() -> Result<Self> {
+        Ok(Self {
+            api_key: credential("POLYMARKET_API_KEY")?,
+            secret: credential("POLYMARKET_API_SECRET")?,
+            passphrase: credential("POLYMARKET_API_PASSPHRASE")?,
+        })
+    }
+
+    /// URL-safe base64 HMAC-SHA256 of `timestamp ‖ method ‖ path ‖ body`.
+    pub fn signature(
+        &self,
+        timestamp: i64,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> Result<String> {
+        let secret = URL_SAFE
+            .decode(&self.secret)
+            .map_err(|_| PlatformError::InvalidOrder("API secret is not base64".to_string()))?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(&secret)
+            .map_err(|err| PlatformError::InvalidOrder(err.to_string()))?;
+        mac.update(format!("{}{}{}{}", timestamp, method, path, body).as_bytes());
+        Ok(URL_SAFE.encode(mac.finalize().into_bytes()))
+    }
+
+    /// `POLY_*` headers authenticating one trading request. Header names are
+    /// case-insensitive; `http` only stores them lowercased.
+    pub fn headers(
+        &self,
+        address: &str,
+        timestamp: i64,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> Result<HeaderMap> {
+        let signature = self.signature(timestamp, method, path, body)?;
+        let timestamp = timestamp.to_string();
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            ("poly_address", address),
+            ("poly_signature", signature.as_str()),
+            ("poly_timestamp", timestamp.as_str()),
+            ("poly_api_key", self.api_key.as_str()),
+            ("poly_passphrase", self.passphrase.as_str()),
+        ] {
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| PlatformError::InvalidOrder(format!("invalid {} header", name)))?;
+            headers.insert(name, value);
+        }
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Private key of the web3.js documentation examples.
+    const PRIVATE_KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+    const TOKEN_ID: &str =
+        "21742633143463906290569050155826241533067272736897614950488156847949938836455";
+
+    #[test]
+    fn test_sign_order_vector() {
+        let signer = ClobSigner::new(PRIVATE_KEY).unwrap();
+        assert_eq!(
+            signer.address(),
+            "0x2c7536E3605D9C16a7a3D7b1898e529396a65c23"
+        );
+        assert_eq!(
+            hex::encode(domain_separator(EXCHANGE, POLYGON_CHAIN_ID).unwrap()),
+            "1a573e3617c78403b5b4b892827992f027b03d4eaf570048b8ee8cdd84d151be"
+        );
+
+        let args = OrderArgs::limit(TOKEN_ID, BookSide::Buy, 0.62, 100.0, 0.01);
+        let payload = signer
+            .sign_order(&args, "api-key", 479_249_096_354)
+            .unwrap();
+        assert_eq!(payload.order_type, OrderType::Gtc);
+        assert_eq!(payload.order.maker_amount, "62000000");
+        assert_eq!(payload.order.taker_amount, "100000000");
+        // Reference signature computed independently of this crate.
+        assert_eq!(
+            payload.order.signature,
+            concat!(
+                "0x2fe3be68af0149bc20501c3cad051f030565c507ec0efa56aa66a7676d0b21c3",
+                "7f1b95b6cfa3498e9c40d869525c8fad02d0d1703ca7d540a85e1decaa9850a01c"
+            )
+        );
+        let json = serde_json::to_value(&payload).unwrap();
+        assert_eq!(json["orderType"], "GTC");
+        assert_eq!(json["order"]["side"], "BUY");
+        assert_eq!(json["order"]["tokenId"], TOKEN_ID);
+    }
+
+    #[test]
+    fn test_amount_rounding() {
+        let sell = OrderArgs::limit(TOKEN_ID, BookSide::Sell, 0.6249, 10.555, 0.01);
+        assert_eq!(sell.amounts().unwrap(), (10_550_000, 6_541_000));
+        let buy = OrderArgs::market(TOKEN_ID, BookSide::Buy, 25.0, 0.64, 0.01);
+        assert_eq!(buy.order_type(), OrderType::Fok);
+        assert_eq!(buy.amounts().unwrap(), (25_000_000, 39_062_500));
+
+        let outside = OrderArgs::limit(TOKEN_ID, BookSide::Buy, 0.0004, 10.0, 0.001);
+        assert!(matches!(
+            outside.amounts(),
+            Err(PlatformError::InvalidOrder(_))
+        ));
+        let bad_tick = OrderArgs::limit(TOKEN_ID, BookSide::Buy, 0.5, 10.0, 0.03);
+        assert!(bad_tick.amounts().is_err());
+    }
+
+    #[test]
+    fn test_l2_headers() {
+        let creds = ApiCreds {
+            api_key: "api-key".to_string(),
+            secret: "cnByZWRpY3QtdGVzdC1zZWNyZXQtMzItYnl0ZXMhISE=".to_string(),
+            passphrase: "passphrase".to_string(),
+        };
+        let body = r#"{"orderID":"0xabc"}"#;
+        let headers = creds
+            .headers("0xabc", 1_730_419_200, "POST", "/order", body)
+            .unwrap();
+        assert_eq!(
+            headers["poly_signature"],
+            "BpALuLYiHBDvXHAv-iRb_AsfiMwzgFkgf_eOPLBmIRc="
+        );
+        assert_eq!(headers["poly_timestamp"], "1730419200");
+    }
+}
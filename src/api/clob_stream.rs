@@ -0,0 +1,271 @@
+use crate::context::Collector;
+use crate::polymarket::{ClobEvent, OrderBook, PolymarketToken};
+use crate::types::{Market, OutcomeTick, Tick};
+use async_trait::async_trait;
+use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::Sender;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+//https://docs.polymarket.com/#market-channel
+pub const CLOB_WS_ENDPOINT: &str = "wss://ws-subscriptions-clob.polymarket.com/ws/market";
+// The server drops connections that stay silent for longer.
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+pub type Books = Arc<RwLock<HashMap<String, OrderBook>>>;
+
+/// Subscribes to the CLOB market channel for a set of outcome tokens, keeps a
+/// live `OrderBook` per token and publishes a tick on every book change or trade.
+///
+/// Book ticks carry the midpoint and no volume; trade ticks carry the trade price
+/// and its notional in USD. Dropped connections are retried with exponential
+/// backoff, and the snapshot sent on resubscribe replaces the stale books.
+pub struct ClobCollector {
+    endpoint: String,
+    /// Outcome label by token id.
+    outcomes: HashMap<String, String>,
+    books: Books,
+}
+
+impl ClobCollector {
+    pub fn new(tokens: &[PolymarketToken]) -> Self {
+        Self {
+            endpoint: CLOB_WS_ENDPOINT.to_string(),
+            outcomes: tokens
+                .iter()
+                .map(|token| (token.token_id.clone(), token.outcome.to_uppercase()))
+                .collect(),
+            books: Books::default(),
+        }
+    }
+
+    pub fn with_endpoint(mut self, endpoint: &str) -> Self {
+        self.endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Live books by token id, shared with the running collector.
+    pub fn books(&self) -> Books {
+        self.books.clone()
+    }
+
+    async fn stream(
+        &self,
+        mut socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        tx: &Sender<Market>,
+    ) -> anyhow::Result<()> {
+        let subscribe = serde_json::json!({
+            "assets_ids": self.outcomes.keys().collect::<Vec<_>>(),
+            "type": "market",
+        });
+        socket.send(Message::Text(subscribe.to_string())).await?;
+        let mut ping = tokio::time::interval(PING_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = ping.tick() => socket.send(Message::Text("PING".to_string())).await?,
+                message = socket.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        for tick in self.handle(&text) {
+                            // Sending only fails while nothing is subscribed.
+                            let _ = tx.send(Market::MarketPosition(tick));
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => return Ok(()),
+                    // Protocol pings are answered by tungstenite.
+                    Some(Ok(_)) => {}
+                    Some(Err(err)) => return Err(err.into()),
+                },
+            }
+        }
+    }
+
+    /// Applies one text frame to the books and returns the ticks it produces.
+    fn handle(&self, text: &str) -> Vec<OutcomeTick> {
+        if text == "PONG" {
+            return Vec::new();
+        }
+        // The subscribe snapshot arrives as an array, updates one at a time.
+        let events = if text.trim_start().starts_with('[') {
+            serde_json::from_str::<Vec<ClobEvent>>(text)
+        } else {
+            serde_json::from_str::<ClobEvent>(text).map(|event| vec![event])
+        };
+        match events {
+            Ok(events) => events
+                .into_iter()
+                .filter_map(|event| self.apply(event))
+                .collect(),
+            Err(err) => {
+                tracing::warn!("skipping CLOB message: {}", err);
+                Vec::new()
+            }
+        }
+    }
+
+    fn apply(&self, event: ClobEvent) -> Option<OutcomeTick> {
+        // A panic mid-update leaves at worst one stale book, which the next
+        // snapshot replaces, so a poisoned lock is still usable.
+        let mut books = self.books.write().unwrap_or_else(PoisonError::into_inner);
+        match event {
+            ClobEvent::Book(book) => {
+                let asset_id = book.asset_id.clone();
+                books.insert(asset_id.clone(), book);
+                let book = &books[&asset_id];
+                self.tick(&book.market, &asset_id, book.midpoint()?, 0.0)
+            }
+            ClobEvent::PriceChange(change) => {
+                // Changes before the first snapshot have nothing to apply to.
+                let book = books.get_mut(&change.asset_id)?;
+                for level in &change.changes {
+                    book.apply(level.side, level.price, level.size);
+                }
+                self.tick(&change.market, &change.asset_id, book.midpoint()?, 0.0)
+            }
+            ClobEvent::LastTradePrice(trade) => self.tick(
+                &trade.market,
+                &trade.asset_id,
+                trade.price,
+                trade.price * trade.size,
+            ),
+            ClobEvent::Other => None,
+        }
+    }
+
+    fn tick(
+        &self,
+        market: &str,
+        asset_id: &str,
+        probability: f64,
+        volume: f64,
+    ) -> Option<OutcomeTick> {
+        Some(OutcomeTick {
+            platform: "polymarket".to_string(),
+            market_id: market.to_string(),
+            outcome: self.outcomes.get(asset_id)?.clone(),
+            tick: Tick {
+                timestamp: chrono::Utc::now().timestamp(),
+                probability,
+                volume,
+            },
+        })
+    }
+}
+
+#[async_trait]
+impl Collector<Market> for ClobCollector {
+    async fn collect(&self, tx: Sender<Market>) -> anyhow::Result<()> {
+        let mut backoff = MIN_BACKOFF;
+        loop {
+            match tokio_tungstenite::connect_async(self.endpoint.as_str()).await {
+                Ok((socket, _)) => {
+                    backoff = MIN_BACKOFF;
+                    match self.stream(socket, &tx).await {
+                        Ok(()) => tracing::warn!("CLOB websocket closed"),
+                        Err(e) => tracing::error!("CLOB websocket failed: {}", e),
+                    }
+                }
+                Err(e) => tracing::error!("error connecting to the CLOB websocket: {}", e),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::sync::broadcast;
+
+    const BOOK: &str = include_str!("../../tests/fixtures/polymarket_book.json");
+    const YES_TOKEN: &str =
+        "21742633143463906290569050155826241533067272736897614950488156847949938836455";
+
+    fn messages() -> Vec<String> {
+        let mut book: serde_json::Value = serde_json::from_str(BOOK).unwrap();
+        book["event_type"] = "book".into();
+        let market = book["market"].clone();
+        vec![
+            serde_json::json!([book]).to_string(),
+            "PONG".to_string(),
+            serde_json::json!({
+                "event_type": "price_change",
+                "asset_id": YES_TOKEN,
+                "market": market,
+                "changes": [{ "price": "0.63", "side": "SELL", "size": "0" }],
+            })
+            .to_string(),
+            serde_json::json!({
+                "event_type": "last_trade_price",
+                "asset_id": YES_TOKEN,
+                "market": market,
+                "price": "0.64",
+                "side": "BUY",
+                "size": "50",
+            })
+            .to_string(),
+        ]
+    }
+
+    async fn next_tick(rx: &mut broadcast::Receiver<Market>) -> OutcomeTick {
+        let market = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        match market {
+            Market::MarketPosition(tick) => tick,
+            other => panic!("expected a position, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_reconnects_and_tracks_book() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // Refuse the handshake once so the collector has to back off and retry.
+            drop(listener.accept().await.unwrap());
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let subscribe = socket.next().await.unwrap().unwrap();
+            assert!(subscribe.to_text().unwrap().contains(YES_TOKEN));
+            for message in messages() {
+                socket.send(Message::Text(message)).await.unwrap();
+            }
+            while socket.next().await.is_some() {}
+        });
+
+        let token = PolymarketToken {
+            token_id: YES_TOKEN.to_string(),
+            outcome: "Yes".to_string(),
+            winner: false,
+        };
+        let collector = Arc::new(ClobCollector::new(&[token]).with_endpoint(&url));
+        let books = collector.books();
+        let (tx, mut rx) = broadcast::channel(16);
+        let task = tokio::spawn({
+            let collector = collector.clone();
+            async move { collector.collect(tx).await }
+        });
+
+        let snapshot = next_tick(&mut rx).await;
+        assert_eq!(snapshot.outcome, "YES");
+        assert!((snapshot.tick.probability - 0.62).abs() < 1e-9);
+        // Emptying the 0.63 ask moves the best ask to 0.64.
+        let change = next_tick(&mut rx).await;
+        assert!((change.tick.probability - 0.625).abs() < 1e-9);
+        let trade = next_tick(&mut rx).await;
+        assert_eq!(trade.tick.probability, 0.64);
+        assert!((trade.tick.volume - 32.0).abs() < 1e-9);
+
+        assert_eq!(books.read().unwrap()[YES_TOKEN].best_ask(), Some(0.64));
+        task.abort();
+    }
+}
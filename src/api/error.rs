@@ -0,0 +1,166 @@
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// Longest slice of a response body kept in `PlatformError::Decode`.
+const BODY_SNIPPET_LEN: usize = 256;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PlatformError {
+    #[error("http error: {0}")]
+    Http(#[from] reqwest_middleware::Error),
+    #[error("rate limited (retry after {retry_after:?})")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("could not decode response: {source}; body: {body}")]
+    Decode {
+        #[source]
+        source: serde_json::Error,
+        body: String,
+    },
+    #[error("missing credential `{0}`")]
+    MissingCredential(&'static str),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("json-rpc error: {0}")]
+    Rpc(#[from] jsonrpsee::core::Error),
+    #[error("could not decode contract data: {0}")]
+    Abi(String),
+    #[error("invalid order: {0}")]
+    InvalidOrder(String),
+    #[error("not supported: {0}")]
+    Unsupported(&'static str),
+}
+
+impl PlatformError {
+    /// Whether the same request may succeed if sent again later.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            PlatformError::RateLimited { .. } => true,
+            PlatformError::Http(reqwest_middleware::Error::Reqwest(err)) => {
+                err.is_timeout()
+                    || err.is_connect()
+                    || err.status().is_some_and(|status| status.is_server_error())
+            }
+            PlatformError::Http(reqwest_middleware::Error::Middleware(_)) => false,
+            PlatformError::Rpc(err) => matches!(
+                err,
+                jsonrpsee::core::Error::Transport(_) | jsonrpsee::core::Error::RequestTimeout
+            ),
+            PlatformError::Decode { .. }
+            | PlatformError::MissingCredential(_)
+            | PlatformError::NotFound(_)
+            | PlatformError::Abi(_)
+            | PlatformError::InvalidOrder(_)
+            | PlatformError::Unsupported(_) => false,
+        }
+    }
+
+    fn decode(source: serde_json::Error, body: &str) -> Self {
+        let end = body
+            .char_indices()
+            .nth(BODY_SNIPPET_LEN)
+            .map_or(body.len(), |(end, _)| end);
+        PlatformError::Decode {
+            source,
+            body: body[..end].to_string(),
+        }
+    }
+}
+
+impl From<reqwest::Error> for PlatformError {
+    fn from(value: reqwest::Error) -> Self {
+        PlatformError::Http(reqwest_middleware::Error::Reqwest(value))
+    }
+}
+
+/// Checks the status, then parses the body as JSON.
+pub async fn decode<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, PlatformError> {
+    let body = check_status(response)?.text().await?;
+    parse(&body)
+}
+
+/// Maps 404 and 429 to their own variants and any other failure status to `Http`.
+pub fn check_status(response: reqwest::Response) -> Result<reqwest::Response, PlatformError> {
+    match response.status() {
+        StatusCode::NOT_FOUND => return Err(PlatformError::NotFound(response.url().to_string())),
+        StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            return Err(PlatformError::RateLimited { retry_after });
+        }
+        _ => {}
+    }
+    Ok(response.error_for_status()?)
+}
+
+pub fn parse<T: DeserializeOwned>(body: &str) -> Result<T, PlatformError> {
+    serde_json::from_str(body).map_err(|err| PlatformError::decode(err, body))
+}
+
+/// Reads an API key from the environment.
+pub fn credential(var: &'static str) -> Result<String, PlatformError> {
+    std::env::var(var).map_err(|_| PlatformError::MissingCredential(var))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, body: &str) -> reqwest::Response {
+        let response = http::Response::builder()
+            .status(status)
+            .header("retry-after", "7")
+            .body(body.to_string())
+            .unwrap();
+        reqwest::Response::from(response)
+    }
+
+    #[tokio::test]
+    async fn test_decode_statuses() {
+        let ok: Vec<u32> = decode(response(200, "[1, 2]")).await.unwrap();
+        assert_eq!(ok, vec![1, 2]);
+
+        let err = decode::<Vec<u32>>(response(429, "")).await.unwrap_err();
+        assert!(matches!(
+            err,
+            PlatformError::RateLimited {
+                retry_after: Some(wait)
+            } if wait == Duration::from_secs(7)
+        ));
+        assert!(err.is_retryable());
+
+        let err = decode::<Vec<u32>>(response(404, "")).await.unwrap_err();
+        assert!(matches!(err, PlatformError::NotFound(_)));
+        assert!(!err.is_retryable());
+
+        let err = decode::<Vec<u32>>(response(502, "")).await.unwrap_err();
+        assert!(matches!(err, PlatformError::Http(_)));
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_decode_keeps_body_snippet() {
+        let body = format!("<html>{}</html>", "x".repeat(1000));
+        let err = parse::<Vec<u32>>(&body).unwrap_err();
+        match err {
+            PlatformError::Decode { body: snippet, .. } => {
+                assert_eq!(snippet.len(), BODY_SNIPPET_LEN);
+                assert!(snippet.starts_with("<html>"));
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_missing_credential() {
+        let err = credential("RPREDICT_TEST_UNSET_KEY").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "missing credential `RPREDICT_TEST_UNSET_KEY`"
+        );
+    }
+}
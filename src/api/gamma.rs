@@ -1,10 +1,12 @@
+use super::error::decode;
 use super::Result;
-use super::{Platform, PlatformBuilder};
-use crate::gamma::{GammaMarket, GammaPosition};
+use super::{Page, Platform, PlatformBuilder, RateLimit};
+use crate::gamma::{GammaEvent, GammaMarket, GammaPosition};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use chrono::{DateTime, Utc};
+use std::time::Duration;
 
-//https://github.com/Polymarket/py-clob-client
+//https://docs.polymarket.com/#gamma-markets-api
 pub struct GammaPlatform(PlatformBuilder<Self>);
 
 impl From<PlatformBuilder<Self>> for GammaPlatform {
@@ -22,76 +24,178 @@ pub fn get_headers() -> reqwest::header::HeaderMap {
     headers
 }
 
-#[async_trait]
-impl Platform for GammaPlatform {
-    // const ENDPOINT: &'static str = "https://clob.polymarket.com/markets";
-    const ENDPOINT: &'static str = "https://gamma-api.polymarket.com/";
-    const SORT: &'static str = "order:";
+/// Server-side filters for Gamma `/markets` and `/events`; `None` leaves a filter off.
+///
+/// Volume and liquidity bounds are in USD. `tag_slug` is only understood by `/events`.
+#[derive(Debug, Clone, Default)]
+pub struct GammaFilter {
+    pub active: Option<bool>,
+    pub closed: Option<bool>,
+    pub archived: Option<bool>,
+    pub tag_id: Option<String>,
+    pub tag_slug: Option<String>,
+    pub volume_min: Option<f64>,
+    pub volume_max: Option<f64>,
+    pub liquidity_min: Option<f64>,
+    pub end_date_min: Option<DateTime<Utc>>,
+    pub end_date_max: Option<DateTime<Utc>>,
+    /// Field to sort by, e.g. `volume24hr`.
+    pub order: Option<String>,
+    pub ascending: Option<bool>,
+    pub limit: Option<u32>,
+    pub offset: Option<u64>,
+}
 
-    type Market = GammaMarket;
-    type Event = crate::db::gamma::GammaEvent;
-    type Position = GammaPosition;
-    async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
+impl GammaFilter {
+    /// Open markets only, the default for discovery.
+    pub fn open() -> Self {
+        Self {
+            active: Some(true),
+            closed: Some(false),
+            archived: Some(false),
+            ..Default::default()
+        }
+    }
+
+    pub fn markets_query(&self) -> Vec<(&'static str, String)> {
+        let mut query = self.common_query();
+        push(&mut query, "volume_num_min", self.volume_min);
+        push(&mut query, "volume_num_max", self.volume_max);
+        push(&mut query, "liquidity_num_min", self.liquidity_min);
+        query
+    }
+
+    pub fn events_query(&self) -> Vec<(&'static str, String)> {
+        let mut query = self.common_query();
+        push(&mut query, "tag_slug", self.tag_slug.as_ref());
+        push(&mut query, "volume_min", self.volume_min);
+        push(&mut query, "volume_max", self.volume_max);
+        push(&mut query, "liquidity_min", self.liquidity_min);
+        query
+    }
+
+    fn common_query(&self) -> Vec<(&'static str, String)> {
+        let mut query = Vec::new();
+        push(&mut query, "active", self.active);
+        push(&mut query, "closed", self.closed);
+        push(&mut query, "archived", self.archived);
+        push(&mut query, "tag_id", self.tag_id.as_ref());
+        push(
+            &mut query,
+            "end_date_min",
+            self.end_date_min.map(|date| date.to_rfc3339()),
+        );
+        push(
+            &mut query,
+            "end_date_max",
+            self.end_date_max.map(|date| date.to_rfc3339()),
+        );
+        push(&mut query, "order", self.order.as_ref());
+        push(&mut query, "ascending", self.ascending);
+        push(&mut query, "limit", self.limit);
+        push(&mut query, "offset", self.offset);
+        query
+    }
+}
+
+fn push<T: ToString>(query: &mut Vec<(&'static str, String)>, key: &'static str, value: Option<T>) {
+    if let Some(value) = value {
+        query.push((key, value.to_string()));
+    }
+}
+
+impl GammaPlatform {
+    pub async fn fetch_markets(&self, filter: &GammaFilter) -> Result<Vec<GammaMarket>> {
         let builder = &self.0;
-        let url = builder.endpoint.to_owned() + "/markets";
+        let response = builder
+            .client
+            .get(format!("{}/markets", builder.endpoint))
+            .headers(get_headers())
+            .query(&filter.markets_query())
+            .send()
+            .await?;
+        decode(response).await
+    }
 
-        let limit = builder.limit;
-        let markets: Vec<Self::Market> = vec![];
+    pub async fn fetch_events_filtered(&self, filter: &GammaFilter) -> Result<Vec<GammaEvent>> {
+        let builder = &self.0;
         let response = builder
             .client
-            .get(url.to_string())
+            .get(format!("{}/events", builder.endpoint))
             .headers(get_headers())
-            // .query(&("limit", builder.limit.to_string().as_str()))
-            // .query(&["limit", builder.limit.to])
+            .query(&filter.events_query())
             .send()
-            // .await?
-            // .json::<Vec<Self::Market>>()
             .await?;
+        decode(response).await
+    }
+}
 
-        let markets_text = response.text().await.unwrap();
-        // let markets = parse_polymarket_text(&markets_text);
-        tracing::info!("Market: {:?}", markets_text);
+#[async_trait]
+impl Platform for GammaPlatform {
+    const NAME: &'static str = "gamma";
+    const ENDPOINT: &'static str = "https://gamma-api.polymarket.com";
+    const SORT: &'static str = "order:";
+    // Gamma `/markets` limit.
+    const RATE_LIMIT: RateLimit = RateLimit::new(125, Duration::from_secs(10));
 
-        Ok(markets)
+    type Market = GammaMarket;
+    type Event = GammaEvent;
+    type Position = GammaPosition;
+    async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
+        Ok(self.fetch_questions_page(None).await?.items)
+    }
+    /// Open markets by 24h volume; the cursor is the offset of the next page.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
+        let limit = self.0.limit;
+        let offset = cursor
+            .and_then(|cursor| cursor.parse::<u64>().ok())
+            .unwrap_or(0);
+        let filter = GammaFilter {
+            order: Some("volume24hr".to_string()),
+            ascending: Some(false),
+            limit: Some(limit),
+            offset: Some(offset),
+            ..GammaFilter::open()
+        };
+        let markets = self.fetch_markets(&filter).await?;
+        // A short page is the last one.
+        let next =
+            (markets.len() >= limit as usize).then(|| (offset + markets.len() as u64).to_string());
+        Ok(Page {
+            items: markets,
+            next,
+        })
     }
     async fn incoming_market_to_value(&self, market: Self::Market) -> Result<serde_json::Value> {
-        unimplemented!()
+        Ok(serde_json::json!({
+            "question": market.question,
+            "outcomes": market.outcomes,
+            "prices": market.outcome_prices,
+            "volume": market.volume_num,
+            "liquidity": market.liquidity_num,
+            "end_date": market.end_date,
+        }))
     }
 
-    async fn fetch_question_by_id(&self, token_id: &str) -> Result<Self::Market> {
-        let mut args = HashMap::new();
-        args.insert("token_id", token_id);
+    async fn fetch_question_by_id(&self, id: &str) -> Result<Self::Market> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str().to_owned() + "/" + token_id;
-        let market: Self::Market = GammaMarket {};
-
+        let url = format!("{}/markets/{}", builder.endpoint, id);
         let response = builder
             .client
             .get(url)
-            // .query(&args)
+            .headers(get_headers())
             .send()
             .await?;
-        // .json::<Self::Market>()
-        // .await?;
-        let market_text = response.text().await.unwrap();
-        tracing::info!("Market: {:?}", market_text);
-
-        Ok(market)
+        decode(response).await
     }
     async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str();
-        let response = builder
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+        let url = format!("{}/markets", builder.endpoint);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
     }
 
+    // Gamma is read-only; orders go to the CLOB.
     async fn build_order(
         &self,
         token: &str,
@@ -104,53 +208,104 @@ impl Platform for GammaPlatform {
 
     async fn incoming_position_to_value(
         &self,
-        market: Self::Position,
+        position: Self::Position,
     ) -> Result<serde_json::Value> {
-        unimplemented!()
-    }
-
-    async fn fetch_markets_by_terms(&self, term: &str) -> Result<Vec<Self::Market>> {
-        unimplemented!()
+        Ok(serde_json::json!({
+            "outcome": position.outcome,
+            "price": position.price,
+        }))
     }
 
-    async fn fetch_ratelimited(
-        request_count: usize,
-        interval_ms: Option<u64>,
-    ) -> PlatformBuilder<Self> {
-        unimplemented!()
+    /// Open markets of the events tagged `terms`.
+    async fn fetch_markets_by_terms(&self, terms: &str) -> Result<Vec<Self::Market>> {
+        let filter = GammaFilter {
+            tag_slug: Some(terms.to_string()),
+            ..GammaFilter::open()
+        };
+        let events = self.fetch_events_filtered(&filter).await?;
+        Ok(events.into_iter().flat_map(|event| event.markets).collect())
     }
 
     async fn fetch_json_by_description(&self, description: &str) -> Result<Vec<serde_json::Value>> {
-        unimplemented!()
+        let mut values = Vec::new();
+        for market in self.fetch_markets_by_terms(description).await? {
+            values.push(self.incoming_market_to_value(market).await?);
+        }
+        Ok(values)
     }
     async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>> {
-        unimplemented!()
+        let filter = GammaFilter {
+            order: Some("volume24hr".to_string()),
+            ascending: Some(false),
+            limit: Some(limit.unwrap_or(30) as u32),
+            offset: Some(offset),
+            ..GammaFilter::open()
+        };
+        self.fetch_events_filtered(&filter).await
     }
+    /// Outcomes of the market with their current prices and CLOB token ids.
     async fn fetch_orderbook(&self, id: &str) -> Result<Vec<Self::Position>> {
-        unimplemented!()
+        Ok(self.fetch_question_by_id(id).await?.positions())
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    // #[tokio::test]
-    // async fn test_fetch_questions() {
-    //     let platform = GammaPlatform::from(PlatformBuilder::default());
-    //     let questions = platform.fetch_json().await.unwrap();
-    //     println!("Questions: {:?}", questions);
-
-    //     // assert!(questions.len() > 0);
-    // }
+    use crate::server::mock::MockServer;
+
+    async fn platform() -> (MockServer, GammaPlatform) {
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        (server, GammaPlatform::from(builder))
+    }
+
+    #[tokio::test]
+    async fn test_fetch_questions() {
+        let (_server, platform) = platform().await;
+        let questions = platform.fetch_json().await.unwrap();
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0]["id"], "253591");
+
+        let page = platform.fetch_questions_page(None).await.unwrap();
+        assert_eq!(page.items[0].outcome_prices, vec![0.62, 0.38]);
+        // One market against the default limit of three: the last page.
+        assert!(page.next.is_none());
+    }
     #[tokio::test]
     async fn test_fetch_question_by_id() {
-        let platform = GammaPlatform::from(PlatformBuilder::default());
-        let question = platform.fetch_question_by_id("506962").await.unwrap();
-        println!("Question: {:?}", question);
-        // assert!(question.len() > 0);
+        let (_server, platform) = platform().await;
+        let market = platform.fetch_question_by_id("253591").await.unwrap();
+        assert_eq!(market.id, "253591");
+        let positions = platform.fetch_orderbook("253591").await.unwrap();
+        assert_eq!(positions[0].price, Some(0.62));
     }
     #[tokio::test]
     async fn test_fetch_events_by_tag() {
-        unimplemented!()
+        let (_server, platform) = platform().await;
+        let events = platform.fetch_events(Some(5), 0).await.unwrap();
+        assert_eq!(events[0].markets.len(), 1);
+        let markets = platform.fetch_markets_by_terms("crypto").await.unwrap();
+        assert_eq!(markets[0].id, "253591");
+    }
+
+    #[test]
+    fn test_filter_query() {
+        let filter = GammaFilter {
+            tag_slug: Some("crypto".to_string()),
+            volume_min: Some(1000.0),
+            end_date_max: DateTime::from_timestamp(1_735_689_600, 0),
+            ..GammaFilter::open()
+        };
+        let markets = filter.markets_query();
+        assert!(markets.contains(&("volume_num_min", "1000".to_string())));
+        assert!(markets.contains(&("end_date_max", "2025-01-01T00:00:00+00:00".to_string())));
+        assert!(!markets.iter().any(|(key, _)| *key == "tag_slug"));
+
+        let events = filter.events_query();
+        assert!(events.contains(&("tag_slug", "crypto".to_string())));
+        assert!(events.contains(&("volume_min", "1000".to_string())));
+        assert!(events.contains(&("active", "true".to_string())));
     }
 }
@@ -1,57 +1,263 @@
+use super::error::decode;
 use super::Result;
-use super::{Platform, PlatformBuilder};
+use super::{Page, Platform, PlatformBuilder, PlatformError, RateLimit};
 use crate::manifold::ManifoldEvent;
+use crate::model::manifold::ManifoldBet;
 use crate::model::manifold::ManifoldMarket;
 use crate::model::manifold::ManifoldPosition;
+use crate::model::manifold::{GroupIndex, ManifoldGroup};
 
 use async_trait::async_trait;
+use serde::Serialize;
+use std::time::Duration;
 pub struct ManifoldPlatform(PlatformBuilder<Self>);
 
-//TODO: use this to grab tags
-const GROUP_URL: &str = "https://api.manifold.markets/v0/groups";
+/// Body of `POST /bet`. A market order unless `limit_prob` is set.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BetRequest {
+    pub contract_id: String,
+    /// Mana to spend.
+    pub amount: f64,
+    /// `YES` or `NO`; for multiple choice, the side of `answer_id`.
+    pub outcome: String,
+    /// Price in `[0.01, 0.99]` the order may not cross; the rest rests in the book.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit_prob: Option<f64>,
+    /// Milliseconds since the epoch at which an unfilled limit order is cancelled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_id: Option<String>,
+    /// Have Manifold simulate the bet and answer with what it would have filled.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+}
+
+impl BetRequest {
+    pub fn new(contract_id: &str, amount: f64, outcome: &str) -> Self {
+        Self {
+            contract_id: contract_id.to_string(),
+            amount,
+            outcome: outcome.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Body of `POST /market/:id/sell`; `shares: None` sells the whole position.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SellRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shares: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer_id: Option<String>,
+}
+
+const GROUP_PATH: &str = "/groups";
 impl From<PlatformBuilder<Self>> for ManifoldPlatform {
     fn from(value: PlatformBuilder<Self>) -> Self {
         Self(value)
     }
 }
 
+impl ManifoldPlatform {
+    /// Bet history of a market, newest first as returned by the API.
+    pub async fn fetch_bets(&self, contract_id: &str) -> Result<Vec<ManifoldBet>> {
+        let builder = &self.0;
+        let url = format!("{}/bets", builder.endpoint);
+        let response = builder
+            .client
+            .get(url)
+            .query(&[("contractId", contract_id), ("limit", "1000")])
+            .send()
+            .await?;
+        decode::<Vec<ManifoldBet>>(response).await
+    }
+
+    /// Places a bet or limit order and returns what filled. In dry-run mode the
+    /// bet is only simulated by Manifold.
+    pub async fn place_bet(&self, request: &BetRequest) -> Result<ManifoldBet> {
+        let request = BetRequest {
+            dry_run: request.dry_run || self.0.dry_run,
+            ..request.clone()
+        };
+        self.post("/bet", &request).await
+    }
+
+    /// Sells shares back to the market. Not sent in dry-run mode, as Manifold
+    /// cannot simulate sales.
+    pub async fn sell_shares(
+        &self,
+        contract_id: &str,
+        request: &SellRequest,
+    ) -> Result<ManifoldBet> {
+        self.refuse_dry_run("sell")?;
+        self.post(&format!("/market/{}/sell", contract_id), request)
+            .await
+    }
+
+    /// Cancels the unfilled part of a limit order and returns the order.
+    pub async fn cancel_bet(&self, bet_id: &str) -> Result<ManifoldBet> {
+        self.refuse_dry_run("cancel")?;
+        self.post(&format!("/bet/cancel/{}", bet_id), &serde_json::json!({}))
+            .await
+    }
+
+    fn refuse_dry_run(&self, action: &str) -> Result<()> {
+        if self.0.dry_run {
+            return Err(PlatformError::InvalidOrder(format!(
+                "{} is not sent in dry-run mode",
+                action
+            )));
+        }
+        Ok(())
+    }
+
+    async fn post<B: Serialize>(&self, path: &str, body: &B) -> Result<ManifoldBet> {
+        let builder = &self.0;
+        let key = builder.credential("MANIFOLD_API_KEY")?;
+        let response = builder
+            .client
+            .post(format!("{}{}", builder.endpoint, path))
+            .header("Authorization", format!("Key {}", key))
+            .json(body)
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Every public group (topic), paging back through `beforeTime`.
+    pub async fn fetch_groups(&self) -> Result<Vec<ManifoldGroup>> {
+        let builder = &self.0;
+        let url = format!("{}{}", builder.endpoint, GROUP_PATH);
+        let mut groups: Vec<ManifoldGroup> = Vec::new();
+        let mut before: Option<i64> = None;
+        loop {
+            let mut request = builder.client.get(&url);
+            if let Some(before) = before {
+                request = request.query(&[("beforeTime", before)]);
+            }
+            let page: Vec<ManifoldGroup> = decode(request.send().await?).await?;
+            // Pages run newest first, so the last group's creation time is the next cursor.
+            let next = page.last().and_then(|group| group.createdTime);
+            groups.extend(page);
+            match next {
+                Some(next) if before.is_none_or(|before| next < before) => before = Some(next),
+                _ => return Ok(groups),
+            }
+        }
+    }
+
+    pub async fn fetch_group(&self, slug: &str) -> Result<ManifoldGroup> {
+        let builder = &self.0;
+        let url = format!("{}/group/{}", builder.endpoint, slug);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
+    }
+
+    /// Index of every public group, for resolving tags.
+    pub async fn fetch_group_index(&self) -> Result<GroupIndex> {
+        Ok(GroupIndex::new(self.fetch_groups().await?))
+    }
+
+    /// Open markets in the group `slug`.
+    pub async fn fetch_markets_by_group(&self, slug: &str) -> Result<Vec<ManifoldMarket>> {
+        let builder = &self.0;
+        let url = format!("{}/search-markets", builder.endpoint);
+        let response = builder
+            .client
+            .get(url)
+            .query(&[("topicSlug", slug), ("filter", "open")])
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Open markets of the groups `tags` resolve to in `index`. Tags naming no
+    /// group fall back to a text search. Each market is listed once.
+    pub async fn fetch_markets_by_tags(
+        &self,
+        index: &GroupIndex,
+        tags: &[String],
+    ) -> Result<Vec<ManifoldMarket>> {
+        let mut markets: Vec<ManifoldMarket> = Vec::new();
+        for tag in tags {
+            let found = match index.resolve(tag) {
+                Some(group) => self.fetch_markets_by_group(&group.slug).await?,
+                None => {
+                    tracing::debug!("tag {:?} matches no Manifold group, searching", tag);
+                    self.fetch_markets_by_terms(tag).await?
+                }
+            };
+            for market in found {
+                if !markets.iter().any(|known| known.id == market.id) {
+                    markets.push(market);
+                }
+            }
+        }
+        Ok(markets)
+    }
+
+    /// Fetches a market together with its bet history so `events()` is populated.
+    pub async fn fetch_market_with_bets(&self, id: &str) -> Result<ManifoldMarket> {
+        let mut market = self.fetch_question_by_id(id).await?;
+        market.bets = self.fetch_bets(id).await?;
+        Ok(market)
+    }
+}
+
 #[async_trait]
 impl Platform for ManifoldPlatform {
-    const ENDPOINT: &'static str = "https://api.manifold.markets/v0/markets";
+    const NAME: &'static str = "manifold";
+    const ENDPOINT: &'static str = "https://api.manifold.markets/v0";
     const SORT: &'static str = "order:";
+    // 500 requests per minute per IP.
+    const RATE_LIMIT: RateLimit = RateLimit::new(500, Duration::from_secs(60));
     type Market = ManifoldMarket;
     type Event = ManifoldEvent;
     type Position = ManifoldPosition;
     async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
+        Ok(self.fetch_questions_page(None).await?.items)
+    }
+    /// Pages backwards from the newest market; the cursor is the last id seen, sent as `before`.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str();
-        let limit = builder.limit;
+        let mut query = vec![("limit", builder.limit.to_string())];
+        if let Some(before) = cursor {
+            query.push(("before", before));
+        }
         let response = builder
             .client
-            .get(format!("{url}?limit={}", limit.to_string().as_str()))
-            // .query(&("limit", builder.limit.to_string().as_str()))
-            // .query(&["limit", builder.limit.to])
+            .get(format!("{}/markets", builder.endpoint))
+            .query(&query)
             .send()
             .await?;
-
-        let text = response.text().await?;
-        let markets: Vec<Self::Market> = serde_json::from_str(&text).unwrap();
-        Ok(markets)
+        let markets = decode::<Vec<Self::Market>>(response).await?;
+        // A short page is the last one.
+        let next = if markets.len() < builder.limit as usize {
+            None
+        } else {
+            markets.last().map(|market| market.id.clone())
+        };
+        Ok(Page {
+            items: markets,
+            next,
+        })
     }
     async fn fetch_markets_by_terms(&self, terms: &str) -> Result<Vec<Self::Market>> {
         let builder = &self.0;
-        let url = "https://api.manifold.markets/v0/search-markets";
+        let url = format!("{}/search-markets", builder.endpoint);
         let response = builder
             .client
-            .get(format!("{url}?term={terms}", terms = terms))
+            .get(url)
+            .query(&[("term", terms)])
             .send()
-            // .await?
-            // .json::<Vec<Self::Market>>()
             .await?;
-        let text = response.text().await?;
-        let markets: Vec<Self::Market> = serde_json::from_str(&text).unwrap();
-
-        Ok(markets)
+        decode(response).await
     }
     async fn incoming_market_to_value(&self, market: Self::Market) -> Result<serde_json::Value> {
         let probability: String = if let Some(probability) = market.probability {
@@ -80,20 +286,16 @@ impl Platform for ManifoldPlatform {
     }
 
     async fn fetch_question_by_id(&self, id: &str) -> Result<Self::Market> {
-        unimplemented!()
+        let builder = &self.0;
+        let url = format!("{}/market/{}", builder.endpoint, id);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
     }
     async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str();
-        let response = builder
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+        let url = format!("{}/markets", builder.endpoint);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
     }
     async fn build_order(
         &self,
@@ -102,63 +304,32 @@ impl Platform for ManifoldPlatform {
         nonce: &str,
         outcome: &str,
     ) -> Result<()> {
-        let builder = &self.0;
-        let url = "https://api.manifold.markets/v0/bet".to_string();
-        let key: String = std::env::var("MANIFOLD_API_KEY").unwrap();
-        let prepped_order = serde_json::json!(
-            {
-            "contractId": contract_id,
-            "amount": amount,
-            "outcome": outcome
-            }
-        );
-
-        let response = builder
-            .client
-            .post(url)
-            .header("Authorization: Key {}", key)
-            .json(&prepped_order)
-            .send()
+        let bet = self
+            .place_bet(&BetRequest::new(contract_id, amount, outcome))
             .await?;
-        tracing::debug!("Bet: {:?}", response);
+        tracing::debug!("Bet: {:?}", bet);
         Ok(())
     }
-    async fn fetch_ratelimited(
-        request_count: usize,
-        interval_ms: Option<u64>,
-    ) -> PlatformBuilder<Self> {
-        unimplemented!()
-    }
     async fn fetch_json_by_description(&self, term: &str) -> Result<Vec<serde_json::Value>> {
         let builder = &self.0;
-        let url = format!(
-            "https://api.manifold.markets/v0/search-markets?term={}&filter=open",
-            term
-        );
+        let url = format!("{}/search-markets", builder.endpoint);
         let response = builder
             .client
             .get(url)
+            .query(&[("term", term), ("filter", "open")])
             .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+            .await?;
+        decode(response).await
     }
     async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>> {
         unimplemented!()
     }
     async fn fetch_orderbook(&self, id: &str) -> Result<Vec<Self::Position>> {
         let builder = &self.0;
-        let url = format!("https://api.manifold.markets/v0/market/{}/positions", id);
+        let url = format!("{}/market/{}/positions", builder.endpoint, id);
         tracing::debug!("URL: {:?}", url);
-        let response = builder
-            .client
-            .get(url)
-            .send()
-            .await?
-            .json::<Vec<Self::Position>>()
-            .await?;
+        let response = builder.client.get(url).send().await?;
+        let response = decode::<Vec<Self::Position>>(response).await?;
         // .json::<Vec<serde_json::Value>>()
         // .await?;
 
@@ -170,86 +341,132 @@ impl Platform for ManifoldPlatform {
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::api::{PageCaps, PlatformError};
+    use crate::server::mock::MockServer;
+    use futures_util::TryStreamExt;
+
+    async fn platform() -> (MockServer, ManifoldPlatform) {
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        builder.limit(1);
+        builder.api_key("mock-key");
+        (server, ManifoldPlatform::from(builder))
+    }
 
     #[tokio::test]
     async fn test_manifold_markets() {
-        // tracing_subscriber::registry()
-        //     .with(
-        //         tracing_subscriber::EnvFilter::try_from_default_env()
-        //             .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-        //     )
-        //     .with(tracing_subscriber::fmt::layer())
-        //     .init();
-
-        let mut manifold = ManifoldPlatform::from(PlatformBuilder::new());
-        manifold.0.limit(15);
+        let (_server, manifold) = platform().await;
         let questions = manifold.fetch_questions().await.unwrap();
-        tracing::debug!("Questions: {:?}", questions);
+        assert_eq!(questions.len(), 1);
+        assert_eq!(questions[0].id, "9Ccsjc0fmbIb9g50p7SB");
+
+        // The mock's second page is empty, which ends the crawl.
+        let all: Vec<_> = manifold
+            .fetch_all_questions(PageCaps::default())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 1);
     }
     #[tokio::test]
     async fn test_manifold_search() {
-        // tracing_subscriber::registry()
-        //     .with(
-        //         tracing_subscriber::EnvFilter::try_from_default_env()
-        //             .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-        //     )
-        //     .with(tracing_subscriber::fmt::layer())
-        //     .init();
-        let mut manifold = ManifoldPlatform::from(PlatformBuilder::new());
-        manifold.0.limit(15);
+        let (_server, manifold) = platform().await;
         let questions = manifold.fetch_json_by_description("crispr").await.unwrap();
-        tracing::debug!("Questions: {:?}", questions);
+        assert_eq!(questions.len(), 1);
     }
     #[tokio::test]
     async fn test_manifold_search_markets() {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
-        let mut manifold = ManifoldPlatform::from(PlatformBuilder::new());
-        manifold.0.limit(15);
-        let questions = manifold.fetch_markets_by_terms("crispr").await.unwrap();
-        tracing::debug!("Questions: {:?}", questions);
+        let (_server, manifold) = platform().await;
+        let questions = manifold.fetch_markets_by_terms("treasury").await.unwrap();
+        assert_eq!(questions[0].groupSlugs.as_ref().unwrap()[0], "us-economics");
     }
     #[tokio::test]
     async fn test_build_order() {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
-        let mut manifold = ManifoldPlatform::from(PlatformBuilder::new());
-        let bet = manifold.build_order("9Ccsjc0fmbIb9g50p7SB", 10., "", "YES");
-        // let bets = manifold
-        //     .fetch_orderbook("9Ccsjc0fmbIb9g50p7SB")
-        //     .await
-        //     .unwrap();
-        // let trimmed_bets: Vec<_> = bets.iter().take(5).collect();
-        // tracing::debug!("Orderbook for 'Will the 10 Year Treasury Yield at closing on 12/31/2024 be 4% or higher?': {:?}", trimmed_bets);
+        let (_server, manifold) = platform().await;
+        manifold
+            .build_order("9Ccsjc0fmbIb9g50p7SB", 10., "", "YES")
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_limit_bet_sell_and_cancel() {
+        let (_server, manifold) = platform().await;
+        let request = BetRequest {
+            limit_prob: Some(0.5),
+            ..BetRequest::new("9Ccsjc0fmbIb9g50p7SB", 10., "YES")
+        };
+        let bet = manifold.place_bet(&request).await.unwrap();
+        assert_eq!(bet.id, "mock-bet");
+        assert!(!bet.isFilled);
+        assert_eq!(bet.filled_amount(), 5.);
+        assert_eq!(bet.unfilled_amount(), 5.);
+        assert_eq!(bet.probAfter, 0.65);
+
+        let cancelled = manifold.cancel_bet(&bet.id).await.unwrap();
+        assert!(cancelled.isCancelled);
+        assert_eq!(cancelled.unfilled_amount(), 0.);
+
+        let sale = SellRequest {
+            outcome: Some("YES".to_string()),
+            shares: Some(80.),
+            ..Default::default()
+        };
+        let sold = manifold
+            .sell_shares("9Ccsjc0fmbIb9g50p7SB", &sale)
+            .await
+            .unwrap();
+        assert!(sold.amount < 0.);
+    }
+
+    #[tokio::test]
+    async fn test_manifold_groups() {
+        let (_server, manifold) = platform().await;
+        let group = manifold.fetch_group("finance").await.unwrap();
+        assert_eq!(group.name, "Finance");
+
+        // Two pages of groups on the mock server.
+        let mut index = manifold.fetch_group_index().await.unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.resolve("US Economics").unwrap().slug, "us-economics");
+        assert_eq!(index.resolve("US-economics").unwrap().slug, "us-economics");
+        assert_eq!(index.resolve("artificial_intelligence").unwrap().slug, "ai");
+        assert!(index.resolve("treasury").is_none());
+        index.alias("Treasury", "finance");
+        assert_eq!(index.resolve("treasury").unwrap().slug, "finance");
+
+        assert!(manifold
+            .fetch_markets_by_group("ai")
+            .await
+            .unwrap()
+            .is_empty());
+        // The group and the text search both find the fixture market.
+        let tags = ["US-economics".to_string(), "crispr".to_string()];
+        let markets = manifold.fetch_markets_by_tags(&index, &tags).await.unwrap();
+        assert_eq!(markets.len(), 1);
     }
 
     #[tokio::test]
     async fn test_manifold_search_bets() {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
-        let mut manifold = ManifoldPlatform::from(PlatformBuilder::new());
-        manifold.0.limit(15);
-        let bets = manifold
+        let (_server, manifold) = platform().await;
+        let positions = manifold
             .fetch_orderbook("9Ccsjc0fmbIb9g50p7SB")
             .await
             .unwrap();
-        let trimmed_bets: Vec<_> = bets.iter().take(5).collect();
-        tracing::debug!("Orderbook for 'Will the 10 Year Treasury Yield at closing on 12/31/2024 be 4% or higher?': {:?}", trimmed_bets);
+        assert_eq!(positions[0].maxSharesOutcome.as_deref(), Some("YES"));
+
+        let market = manifold
+            .fetch_market_with_bets("9Ccsjc0fmbIb9g50p7SB")
+            .await
+            .unwrap();
+        assert_eq!(market.bets.len(), 2);
+        assert!(matches!(
+            manifold.fetch_market_with_bets("missing").await,
+            Err(PlatformError::NotFound(_))
+        ));
     }
 }
@@ -1,11 +1,23 @@
+use super::error::{check_status, decode};
 use super::Result;
-use super::{Platform, PlatformBuilder};
+use super::{Page, Platform, PlatformBuilder, PlatformError, RateLimit};
 
 use crate::model::metaculus::{
-    MetaculusEvent, MetaculusMarket, MetaculusPosition, MetaculusResponse,
+    MetaculusEvent, MetaculusMarket, MetaculusPosition, MetaculusResponse, CDF_SIZE,
 };
 
 use async_trait::async_trait;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const FORECAST_PATH: &str = "/questions/forecast/";
+// Metaculus rejects probabilities closer to 0 or 1.
+const MIN_PROBABILITY: f64 = 0.001;
+const MAX_PROBABILITY: f64 = 0.999;
+/// Smallest rise Metaculus accepts between neighbouring CDF points.
+const MIN_CDF_STEP: f64 = 5e-5;
+
 pub struct MetaculusPlatform(PlatformBuilder<Self>);
 
 impl From<PlatformBuilder<Self>> for MetaculusPlatform {
@@ -14,53 +26,172 @@ impl From<PlatformBuilder<Self>> for MetaculusPlatform {
     }
 }
 
+/// A prediction on one question; Metaculus' equivalent of an order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Prediction {
+    /// Probability of yes.
+    Binary(f64),
+    /// Probability per option, summing to one.
+    MultipleChoice(BTreeMap<String, f64>),
+    /// CDF of `CDF_SIZE` points over the question's range.
+    Continuous(Vec<f64>),
+}
+
+impl Prediction {
+    /// Checks the bounds Metaculus enforces, so a bad forecast fails before it is sent.
+    pub fn validate(&self) -> Result<()> {
+        let in_bounds = |p: f64| (MIN_PROBABILITY..=MAX_PROBABILITY).contains(&p);
+        match self {
+            Prediction::Binary(p) if !in_bounds(*p) => Err(invalid(format!(
+                "probability {} is outside [{}, {}]",
+                p, MIN_PROBABILITY, MAX_PROBABILITY
+            ))),
+            Prediction::MultipleChoice(options) => {
+                if let Some((option, p)) = options.iter().find(|(_, p)| !in_bounds(**p)) {
+                    return Err(invalid(format!(
+                        "probability {} of {:?} is outside [{}, {}]",
+                        p, option, MIN_PROBABILITY, MAX_PROBABILITY
+                    )));
+                }
+                let total: f64 = options.values().sum();
+                if (total - 1.0).abs() > 1e-6 {
+                    return Err(invalid(format!("probabilities sum to {}, not 1", total)));
+                }
+                Ok(())
+            }
+            Prediction::Continuous(cdf) => {
+                if cdf.len() != CDF_SIZE {
+                    return Err(invalid(format!(
+                        "CDF has {} points, not {}",
+                        cdf.len(),
+                        CDF_SIZE
+                    )));
+                }
+                if cdf.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                    return Err(invalid("CDF leaves [0, 1]".to_string()));
+                }
+                if cdf.windows(2).any(|pair| pair[1] - pair[0] < MIN_CDF_STEP) {
+                    return Err(invalid(format!(
+                        "CDF must rise by at least {} per point",
+                        MIN_CDF_STEP
+                    )));
+                }
+                Ok(())
+            }
+            Prediction::Binary(_) => Ok(()),
+        }
+    }
+}
+
+fn invalid(reason: String) -> PlatformError {
+    PlatformError::InvalidOrder(reason)
+}
+
+/// One entry of the `/questions/forecast/` body; unused kinds stay `null`.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ForecastPayload {
+    pub question: u32,
+    pub probability_yes: Option<f64>,
+    pub probability_yes_per_category: Option<BTreeMap<String, f64>>,
+    pub continuous_cdf: Option<Vec<f64>>,
+}
+
+impl ForecastPayload {
+    pub fn new(question_id: u32, prediction: &Prediction) -> Self {
+        let mut payload = Self {
+            question: question_id,
+            probability_yes: None,
+            probability_yes_per_category: None,
+            continuous_cdf: None,
+        };
+        match prediction {
+            Prediction::Binary(p) => payload.probability_yes = Some(*p),
+            Prediction::MultipleChoice(options) => {
+                payload.probability_yes_per_category = Some(options.clone())
+            }
+            Prediction::Continuous(cdf) => payload.continuous_cdf = Some(cdf.clone()),
+        }
+        payload
+    }
+}
+
+/// What was, or in dry-run mode would have been, submitted.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SubmittedForecast {
+    pub payload: ForecastPayload,
+    pub sent: bool,
+}
+
+impl MetaculusPlatform {
+    /// Posts `prediction` as our forecast on the question (not the post) `question_id`.
+    /// In dry-run mode the forecast is validated and returned without being sent.
+    pub async fn submit_forecast(
+        &self,
+        question_id: u32,
+        prediction: &Prediction,
+    ) -> Result<SubmittedForecast> {
+        prediction.validate()?;
+        let payload = ForecastPayload::new(question_id, prediction);
+        let builder = &self.0;
+        if builder.dry_run {
+            tracing::info!("dry run, not submitting {:?}", payload);
+            return Ok(SubmittedForecast {
+                payload,
+                sent: false,
+            });
+        }
+        let token = builder.credential("METACULUS_TOKEN")?;
+        let response = builder
+            .client
+            .post(format!("{}{}", builder.endpoint, FORECAST_PATH))
+            .header("Authorization", format!("Token {}", token))
+            .json(&[&payload])
+            .send()
+            .await?;
+        // Accepted forecasts come back as an empty 201.
+        check_status(response)?;
+        Ok(SubmittedForecast {
+            payload,
+            sent: true,
+        })
+    }
+}
+
 #[async_trait]
 impl Platform for MetaculusPlatform {
-    const ENDPOINT: &'static str = "https://www.metaculus.com/api/posts/";
+    const NAME: &'static str = "metaculus";
+    const ENDPOINT: &'static str = "https://www.metaculus.com/api";
     const SORT: &'static str = "order:";
+    // No published limit; stay well clear of throttling.
+    const RATE_LIMIT: RateLimit = RateLimit::new(60, Duration::from_secs(60));
 
     type Market = MetaculusMarket;
     type Event = MetaculusEvent;
     type Position = MetaculusPosition;
     async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
-        let token: String = std::env::var("METACULUS_TOKEN").expect("METACULUS_TOKEN not set");
-        let web_token = format!("Token {}", token);
+        Ok(self.fetch_questions_page(None).await?.items)
+    }
+    /// The cursor is the absolute `next` url returned with each page.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str();
-        let limit = builder.limit;
-        let response = builder
-            .client
-            .get(url)
-            // .header("Authorization", web_token)
-            // .query(&("limit", limit.to_string().as_str()))
-            .send()
-            .await?
-            .json::<MetaculusResponse>()
-            .await?;
+        let request = match cursor {
+            Some(next_url) => builder.client.get(next_url),
+            None => builder
+                .client
+                .get(format!("{}/posts/", builder.endpoint))
+                .query(&[("limit", builder.limit)]),
+        };
+        let response = decode::<MetaculusResponse>(request.send().await?).await?;
         tracing::debug!("response: {:?}", response);
-        let mut questions = Vec::new();
-        questions.extend(response.results.into_iter());
-        tracing::debug!("questions: {:?}", questions);
-        // let mut next = response.next;
-        //  while let Some(next_url) = next {
-        //     tracing::debug!("next_url: {:?}", next_url);
-        //     let response: MetaculusResponse = builder
-        //         .client
-        //         .get(next_url.as_str())
-        //         // .header("Authorization", web_token.clone())
-        //         .send()
-        //         .await?
-        //         .json()
-        //         .await?;
-        //     questions.extend(response.results.into_iter());
-        //     next = response.next;
-        //  }
-        Ok(questions)
+        Ok(Page {
+            items: response.results,
+            next: response.next,
+        })
     }
 
     async fn fetch_json_by_description(&self, description: &str) -> Result<Vec<serde_json::Value>> {
         let builder = &self.0;
-        let url = format!("{}?categories={}", builder.endpoint.as_str(), description);
+        let url = format!("{}/posts/?categories={}", builder.endpoint, description);
         let limit = builder.limit;
         let response = builder
             .client
@@ -68,11 +199,8 @@ impl Platform for MetaculusPlatform {
             // .header("Authorization", web_token)
             // .query(&("limit", limit.to_string().as_str()))
             .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+            .await?;
+        decode(response).await
 
         // tracing::debug!("response: {:?}", response);
         // let mut questions: Vec<serde_json::Value> = Vec::new();
@@ -90,22 +218,25 @@ impl Platform for MetaculusPlatform {
         unimplemented!()
     }
 
+    /// A post with its question, scaling and community forecast history.
     async fn fetch_question_by_id(&self, id: &str) -> Result<Self::Market> {
-        unimplemented!()
-    }
-    async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
         let builder = &self.0;
-        let url = builder.endpoint.as_str();
+        let token = builder.credential("METACULUS_TOKEN")?;
         let response = builder
             .client
-            .get(url)
+            .get(format!("{}/posts/{}/", builder.endpoint, id))
+            .header("Authorization", format!("Token {}", token))
             .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+            .await?;
+        decode(response).await
+    }
+    async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
+        let builder = &self.0;
+        let url = format!("{}/posts/", builder.endpoint);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
     }
+    // Nothing is traded on Metaculus; forecasts go through `submit_forecast`.
     async fn build_order(
         &self,
         token: &str,
@@ -113,13 +244,9 @@ impl Platform for MetaculusPlatform {
         nonce: &str,
         outcome: &str,
     ) -> Result<()> {
-        unimplemented!()
-    }
-    async fn fetch_ratelimited(
-        request_count: usize,
-        interval_ms: Option<u64>,
-    ) -> PlatformBuilder<Self> {
-        unimplemented!()
+        Err(PlatformError::InvalidOrder(
+            "Metaculus takes forecasts, not orders".to_string(),
+        ))
     }
     async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>> {
         unimplemented!()
@@ -130,16 +257,15 @@ impl Platform for MetaculusPlatform {
     }
     async fn fetch_markets_by_terms(&self, term: &str) -> Result<Vec<Self::Market>> {
         let builder = &self.0;
-        let url = format!("{}?categories={}", builder.endpoint.as_str(), term);
+        let url = format!("{}/posts/?categories={}", builder.endpoint, term);
         let response = builder
             .client
             .get(url)
             // .header("Authorization", web_token)
             // // .query(&("limit", limit.to_string().as_str()))
             .send()
-            .await?
-            .json::<MetaculusResponse>()
             .await?;
+        let response = decode::<MetaculusResponse>(response).await?;
         tracing::debug!("response: {:?}", response);
         let mut questions = Vec::new();
         questions.extend(response.results.into_iter());
@@ -151,20 +277,76 @@ impl Platform for MetaculusPlatform {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tracing_subscriber::prelude::*;
+    use crate::server::mock::MockServer;
+
     #[tokio::test]
     async fn test_metaculus_fetch_questions() {
-        tracing_subscriber::registry()
-            .with(
-                tracing_subscriber::EnvFilter::try_from_default_env()
-                    .unwrap_or_else(|_| format!("{}=debug", env!("CARGO_CRATE_NAME")).into()),
-            )
-            .with(tracing_subscriber::fmt::layer())
-            .init();
-
-        let platform = MetaculusPlatform::from(PlatformBuilder::new());
-        let questions = platform.fetch_questions().await.unwrap();
-
-        assert!(!questions.is_empty());
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        let platform = MetaculusPlatform::from(builder);
+
+        let page = platform.fetch_questions_page(None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert!(page.next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_metaculus_fetch_question_by_id() {
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        builder.api_key("mock-token");
+        let platform = MetaculusPlatform::from(builder);
+
+        let binary = platform.fetch_question_by_id("28072").await.unwrap();
+        assert_eq!(binary.community_probability(), Some(0.42));
+        let numeric = platform.fetch_question_by_id("31342").await.unwrap();
+        assert!((numeric.community_percentile(0.5).unwrap() - 50.0).abs() < 1e-6);
+        assert!(platform.fetch_question_by_id("1").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metaculus_submit_forecast() {
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        builder.api_key("mock-token");
+        let platform = MetaculusPlatform::from(builder);
+
+        let submitted = platform
+            .submit_forecast(27948, &Prediction::Binary(0.4))
+            .await
+            .unwrap();
+        assert!(submitted.sent);
+        assert_eq!(submitted.payload.probability_yes, Some(0.4));
+        let err = platform
+            .submit_forecast(27948, &Prediction::Binary(1.0))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PlatformError::InvalidOrder(_)));
+
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        builder.dry_run(true);
+        let platform = MetaculusPlatform::from(builder);
+        let cdf: Vec<f64> = (0..CDF_SIZE).map(|i| i as f64 / 200.0).collect();
+        let submitted = platform
+            .submit_forecast(31001, &Prediction::Continuous(cdf))
+            .await
+            .unwrap();
+        assert!(!submitted.sent);
+    }
+
+    #[test]
+    fn test_validate_prediction() {
+        let options =
+            |yes: f64| BTreeMap::from([("Labour".to_string(), yes), ("Other".to_string(), 0.3)]);
+        assert!(Prediction::MultipleChoice(options(0.7)).validate().is_ok());
+        assert!(Prediction::MultipleChoice(options(0.6)).validate().is_err());
+        assert!(Prediction::Continuous(vec![0.5; CDF_SIZE])
+            .validate()
+            .is_err());
+        assert!(Prediction::Continuous(vec![0.0, 1.0]).validate().is_err());
     }
 }
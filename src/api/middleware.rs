@@ -0,0 +1,125 @@
+use super::cassette::Cassette;
+use async_trait::async_trait;
+use http::Extensions;
+use reqwest::{Request, Response, StatusCode};
+use reqwest_leaky_bucket::leaky_bucket::RateLimiter;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Requests allowed per `interval`, refilled continuously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: usize,
+    pub interval: Duration,
+}
+
+impl RateLimit {
+    pub const fn new(requests: usize, interval: Duration) -> Self {
+        Self { requests, interval }
+    }
+}
+
+/// Longest `Retry-After` we are willing to sleep for before handing the response back.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Client used by every platform: leaky-bucket rate limit, `Retry-After` on 429/503,
+/// and exponential backoff on remaining transient failures. A cassette sits closest
+/// to the network so it records and replays the final response of each attempt.
+pub fn build_client(
+    rate_limit: RateLimit,
+    max_retries: u32,
+    cassette: Option<Arc<Cassette>>,
+) -> ClientWithMiddleware {
+    let requests = rate_limit.requests.max(1);
+    let limiter = RateLimiter::builder()
+        .max(requests)
+        .initial(requests)
+        .refill(1)
+        .interval(rate_limit.interval / requests as u32)
+        .build();
+    let backoff = ExponentialBackoff::builder().build_with_max_retries(max_retries);
+    let builder = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(backoff))
+        .with(RetryAfter)
+        .with(reqwest_leaky_bucket::rate_limit_all(limiter));
+    match cassette {
+        Some(cassette) => builder.with_arc(cassette).build(),
+        None => builder.build(),
+    }
+}
+
+/// Waits out a server-sent `Retry-After` once before the backoff policy sees the response.
+pub struct RetryAfter;
+
+#[async_trait]
+impl Middleware for RetryAfter {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let retry = req.try_clone();
+        let response = next.clone().run(req, extensions).await?;
+        let (Some(retry), Some(wait)) = (retry, retry_after(&response)) else {
+            return Ok(response);
+        };
+        tracing::debug!("rate limited by {}, retrying in {:?}", retry.url(), wait);
+        tokio::time::sleep(wait).await;
+        next.run(retry, extensions).await
+    }
+}
+
+fn retry_after(response: &Response) -> Option<Duration> {
+    if !matches!(
+        response.status(),
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        return None;
+    }
+    let seconds = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(Duration::from_secs(seconds)).filter(|wait| *wait <= MAX_RETRY_AFTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, retry_after: Option<&str>) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        if let Some(retry_after) = retry_after {
+            builder = builder.header("retry-after", retry_after);
+        }
+        Response::from(builder.body("").unwrap())
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(
+            retry_after(&response(429, Some("3"))),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            retry_after(&response(503, Some("1"))),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(retry_after(&response(429, None)), None);
+        assert_eq!(retry_after(&response(200, Some("3"))), None);
+        // Too long to wait inline; left to the caller.
+        assert_eq!(retry_after(&response(429, Some("3600"))), None);
+        // HTTP dates are not supported.
+        assert_eq!(
+            retry_after(&response(429, Some("Wed, 21 Oct 2015 07:28:00 GMT"))),
+            None
+        );
+    }
+}
@@ -1,6 +1,11 @@
 use async_trait::async_trait;
 use clap::Parser;
+use futures_util::stream::{self, BoxStream, StreamExt, TryStreamExt};
+use reqwest_middleware::ClientWithMiddleware;
 use std::any::Any;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
 pub use tokio::sync::{broadcast, mpsc, watch};
 pub mod index;
 pub mod platform;
@@ -10,19 +15,50 @@ use super::types::Indicators;
 
 // type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+pub mod abi;
 pub mod augur;
+pub mod cassette;
+pub mod clob_order;
+pub mod clob_stream;
+pub mod error;
 pub mod gamma;
 pub mod manifold;
 pub mod metaculus;
+pub mod middleware;
 pub mod polymarket;
-// pub trait PlatformResult {}
-// impl<T> PlatformResult for Result<T, Box<dyn std::error::Error + Send + Sync>> {}
-type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+pub use cassette::{Cassette, CassetteMode};
+pub use error::PlatformError;
+pub use middleware::RateLimit;
+pub type Result<T> = std::result::Result<T, PlatformError>;
 pub struct PlatformBuilder<T: Platform> {
-    marker: std::marker::PhantomData<T>,
-    client: reqwest::Client,
+    // `fn() -> T` keeps the builder `Send` and `Sync` whatever `T` is.
+    marker: std::marker::PhantomData<fn() -> T>,
+    client: ClientWithMiddleware,
     limit: u32,
     endpoint: String,
+    clob_endpoint: String,
+    rate_limit: RateLimit,
+    max_retries: u32,
+    cassette: Option<Arc<Cassette>>,
+    dry_run: bool,
+    api_key: Option<String>,
+}
+
+/// Polymarket's order book API, used alongside the Gamma endpoint.
+pub const CLOB_ENDPOINT: &str = "https://clob.polymarket.com";
+
+/// One page of a listing. `next` is the cursor for the following page, `None` on the last.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next: Option<String>,
+}
+
+/// Limits for `Platform::fetch_all_questions`; `None` means unbounded.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PageCaps {
+    pub max_pages: Option<usize>,
+    pub max_items: Option<usize>,
 }
 
 pub struct ConnectionParams {}
@@ -40,6 +76,7 @@ pub enum PlatformType {
     Polymarket,
     Metaculus,
     Manifold,
+    Augur,
 }
 
 pub enum BinaryOutcome {
@@ -50,6 +87,23 @@ pub enum BinaryOutcome {
 #[async_trait]
 pub trait Platform: From<PlatformBuilder<Self>> + Any {
     async fn fetch_questions(&self) -> Result<Vec<Self::Market>>;
+    /// One page of questions, starting from `cursor` or the first page when `None`.
+    /// Platforms without pagination return everything as a single page.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
+        let _ = cursor;
+        Ok(Page {
+            items: self.fetch_questions().await?,
+            next: None,
+        })
+    }
+    /// Every question, following the platform's cursor until it runs out or a cap is hit.
+    fn fetch_all_questions(&self, caps: PageCaps) -> BoxStream<'_, Result<Self::Market>>
+    where
+        Self: Sync,
+        Self::Market: Send + 'static,
+    {
+        paginate(move |cursor| self.fetch_questions_page(cursor), caps)
+    }
     fn builder() -> PlatformBuilder<Self> {
         PlatformBuilder::new()
     }
@@ -63,10 +117,21 @@ pub trait Platform: From<PlatformBuilder<Self>> + Any {
         nonce: &str,
         outcome: &str,
     ) -> Result<()>;
+    /// Builder allowing `request_count` requests per `interval_ms`, or per the
+    /// platform's default interval when `None`.
     async fn fetch_ratelimited(
         request_count: usize,
         interval_ms: Option<u64>,
-    ) -> PlatformBuilder<Self>;
+    ) -> PlatformBuilder<Self> {
+        let mut builder = Self::builder();
+        builder.rate_limit(RateLimit::new(
+            request_count,
+            interval_ms
+                .map(Duration::from_millis)
+                .unwrap_or(Self::RATE_LIMIT.interval),
+        ));
+        builder
+    }
     async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>>;
     async fn fetch_orderbook(&self, id: &str) -> Result<Vec<Self::Position>>;
     async fn fetch_markets_by_terms(&self, terms: &str) -> Result<Vec<Self::Market>>;
@@ -79,18 +144,31 @@ pub trait Platform: From<PlatformBuilder<Self>> + Any {
     type Market;
     type Event;
     type Position;
+    /// Short lowercase name, also the platform's prefix on the mock server.
+    const NAME: &'static str;
+    /// Base url; request paths are appended to it.
     const ENDPOINT: &'static str;
     const SORT: &'static str;
+    /// Published request limit, used unless the builder overrides it.
+    const RATE_LIMIT: RateLimit;
 }
 
+const DEFAULT_MAX_RETRIES: u32 = 3;
+
 impl<P: Platform + Any> PlatformBuilder<P> {
     pub fn new() -> Self {
         Self {
             marker: std::marker::PhantomData,
-            client: reqwest::Client::new(),
+            client: middleware::build_client(P::RATE_LIMIT, DEFAULT_MAX_RETRIES, None),
             endpoint: P::ENDPOINT.to_string(),
+            clob_endpoint: CLOB_ENDPOINT.to_string(),
             limit: 3,
             //100
+            rate_limit: P::RATE_LIMIT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            cassette: None,
+            dry_run: false,
+            api_key: None,
         }
     }
     pub fn limit(&mut self, new_limit: u32) {
@@ -99,6 +177,52 @@ impl<P: Platform + Any> PlatformBuilder<P> {
     pub fn endpoint(&mut self, new_endpoint: &str) {
         self.endpoint = new_endpoint.to_string();
     }
+    pub fn clob_endpoint(&mut self, new_endpoint: &str) {
+        self.clob_endpoint = new_endpoint.to_string();
+    }
+    /// Points every endpoint at a `server::mock::MockServer` running at `base_url`.
+    pub fn mock(&mut self, base_url: &str) {
+        self.endpoint = format!("{}/{}", base_url, P::NAME);
+        self.clob_endpoint = format!("{}/clob", base_url);
+    }
+    pub fn rate_limit(&mut self, rate_limit: RateLimit) {
+        self.rate_limit = rate_limit;
+        self.rebuild_client();
+    }
+    /// Retries for 429s, 5xx and connection errors, with exponential backoff.
+    pub fn max_retries(&mut self, max_retries: u32) {
+        self.max_retries = max_retries;
+        self.rebuild_client();
+    }
+    /// Records responses to, or replays them from, the cassette file at `path`.
+    pub fn cassette(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        mode: CassetteMode,
+    ) -> std::io::Result<()> {
+        self.cassette = Some(Arc::new(Cassette::open(path, mode)?));
+        self.rebuild_client();
+        Ok(())
+    }
+    /// Builds and signs orders as usual but returns them instead of sending them.
+    pub fn dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+    /// Credential sent instead of the one read from the platform's environment variable.
+    pub fn api_key(&mut self, api_key: &str) {
+        self.api_key = Some(api_key.to_string());
+    }
+    /// The builder's credential, or the environment variable `var` without one.
+    fn credential(&self, var: &'static str) -> Result<String> {
+        match &self.api_key {
+            Some(api_key) => Ok(api_key.clone()),
+            None => error::credential(var),
+        }
+    }
+    fn rebuild_client(&mut self) {
+        self.client =
+            middleware::build_client(self.rate_limit, self.max_retries, self.cassette.clone());
+    }
 
     // fn build() -> Builder<Self> {
     //     unimplemented!()
@@ -117,7 +241,80 @@ impl<P: Platform + Any> Default for PlatformBuilder<P> {
     }
 }
 
+/// Walks `fetch` from the first page, stopping on an empty page or a missing cursor.
+pub fn paginate<'a, T, F, Fut>(fetch: F, caps: PageCaps) -> BoxStream<'a, Result<T>>
+where
+    T: Send + 'a,
+    F: Fn(Option<String>) -> Fut + Send + 'a,
+    Fut: Future<Output = Result<Page<T>>> + Send + 'a,
+{
+    // `None` once done, otherwise the cursor for the next page.
+    let start: Option<Option<String>> = Some(None);
+    let pages = stream::try_unfold(
+        (start, 0, fetch),
+        move |(cursor, pages, fetch)| async move {
+            let Some(cursor) = cursor else {
+                return Ok(None);
+            };
+            if caps.max_pages.is_some_and(|max_pages| pages >= max_pages) {
+                return Ok(None);
+            }
+            let page = fetch(cursor).await?;
+            let next = match page.next {
+                Some(next) if !page.items.is_empty() => Some(Some(next)),
+                _ => None,
+            };
+            Ok(Some((page.items, (next, pages + 1, fetch))))
+        },
+    );
+    let items = pages
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten();
+    match caps.max_items {
+        Some(max_items) => items.take(max_items).boxed(),
+        None => items.boxed(),
+    }
+}
+
 #[derive(Parser, Debug)]
 pub struct Args {}
 
-pub async fn senf_request_retries() {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Three pages of two items each, cursors "1" and "2".
+    async fn fetch_page(cursor: Option<String>) -> Result<Page<u32>> {
+        let page: u32 = cursor.map(|cursor| cursor.parse().unwrap()).unwrap_or(0);
+        Ok(Page {
+            items: vec![page * 2, page * 2 + 1],
+            next: (page < 2).then(|| (page + 1).to_string()),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_paginate_follows_cursor() {
+        let items: Vec<u32> = paginate(fetch_page, PageCaps::default())
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(items, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_paginate_caps() {
+        let caps = PageCaps {
+            max_pages: Some(2),
+            max_items: None,
+        };
+        let items: Vec<u32> = paginate(fetch_page, caps).try_collect().await.unwrap();
+        assert_eq!(items, vec![0, 1, 2, 3]);
+
+        let caps = PageCaps {
+            max_pages: None,
+            max_items: Some(3),
+        };
+        let items: Vec<u32> = paginate(fetch_page, caps).try_collect().await.unwrap();
+        assert_eq!(items, vec![0, 1, 2]);
+    }
+}
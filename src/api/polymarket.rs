@@ -1,14 +1,32 @@
+use super::clob_order::{
+    salt, ApiCreds, CancelResponse, ClobSigner, OrderArgs, OrderResponse, PlacedOrder,
+};
+use super::error::decode;
 use super::Result;
-use super::{Platform, PlatformBuilder};
-use crate::polymarket::{PolymarketEvent, PolymarketMarket, PolymarketPosition};
+use super::{Page, Platform, PlatformBuilder, PlatformError, RateLimit};
+use crate::polymarket::{
+    BookSide, Midpoint, NegRisk, OrderBook, PolymarketEvent, PolymarketMarket, PolymarketPosition,
+    PolymarketResult, PriceInterval, PricesHistory, PricesHistoryPoint, Spread, TickSize,
+};
 use async_trait::async_trait;
-use serde_json::json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::time::Duration;
 //https://github.com/Polymarket/py-clob-client
-pub struct PolymarketPlatform(PlatformBuilder<Self>);
+pub struct PolymarketPlatform {
+    builder: PlatformBuilder<Self>,
+    credentials: Option<(ClobSigner, ApiCreds)>,
+}
+
+/// `next_cursor` the CLOB returns on its last page (base64 of "-1").
+const END_CURSOR: &str = "LTE=";
 
 impl From<PlatformBuilder<Self>> for PolymarketPlatform {
     fn from(value: PlatformBuilder<Self>) -> Self {
-        Self(value)
+        Self {
+            builder: value,
+            credentials: None,
+        }
     }
 }
 
@@ -21,86 +39,288 @@ pub fn get_headers() -> reqwest::header::HeaderMap {
     headers
 }
 
+impl PolymarketPlatform {
+    /// Keys `build_order` signs with, instead of reading them from the environment.
+    pub fn credentials(&mut self, signer: ClobSigner, creds: ApiCreds) {
+        self.credentials = Some((signer, creds));
+    }
+
+    /// Resting bids and asks for one outcome token.
+    pub async fn fetch_book(&self, token_id: &str) -> Result<OrderBook> {
+        self.clob_get("/book", &[("token_id", token_id)]).await
+    }
+
+    /// Price of `token_id` over `interval`, one point per `fidelity_minutes`.
+    pub async fn fetch_prices_history(
+        &self,
+        token_id: &str,
+        interval: PriceInterval,
+        fidelity_minutes: u32,
+    ) -> Result<Vec<PricesHistoryPoint>> {
+        let fidelity = fidelity_minutes.to_string();
+        let query = [
+            ("market", token_id),
+            ("interval", interval.as_str()),
+            ("fidelity", fidelity.as_str()),
+        ];
+        let history: PricesHistory = self.clob_get("/prices-history", &query).await?;
+        Ok(history.history)
+    }
+
+    pub async fn fetch_midpoint(&self, token_id: &str) -> Result<f64> {
+        let midpoint: Midpoint = self
+            .clob_get("/midpoint", &[("token_id", token_id)])
+            .await?;
+        Ok(midpoint.mid)
+    }
+
+    pub async fn fetch_spread(&self, token_id: &str) -> Result<f64> {
+        let spread: Spread = self.clob_get("/spread", &[("token_id", token_id)]).await?;
+        Ok(spread.spread)
+    }
+
+    /// Fills `market.price_history` from its Yes token, so `events()` sees the full history.
+    pub async fn fetch_market_history(
+        &self,
+        market: &mut PolymarketMarket,
+        interval: PriceInterval,
+        fidelity_minutes: u32,
+    ) -> Result<()> {
+        let token_id = market
+            .yes_token()
+            .map(|token| token.token_id.clone())
+            .ok_or_else(|| PlatformError::NotFound(format!("yes token of {:?}", market.id)))?;
+        market.price_history = self
+            .fetch_prices_history(&token_id, interval, fidelity_minutes)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn fetch_tick_size(&self, token_id: &str) -> Result<f64> {
+        let tick_size: TickSize = self
+            .clob_get("/tick-size", &[("token_id", token_id)])
+            .await?;
+        Ok(tick_size.minimum_tick_size)
+    }
+
+    pub async fn fetch_neg_risk(&self, token_id: &str) -> Result<bool> {
+        let neg_risk: NegRisk = self
+            .clob_get("/neg-risk", &[("token_id", token_id)])
+            .await?;
+        Ok(neg_risk.neg_risk)
+    }
+
+    /// Signs `args` and posts it; in dry-run mode the signed payload is only returned.
+    pub async fn place_order(
+        &self,
+        signer: &ClobSigner,
+        creds: &ApiCreds,
+        args: &OrderArgs,
+    ) -> Result<PlacedOrder> {
+        let payload = signer.sign_order(args, &creds.api_key, salt())?;
+        if self.builder.dry_run {
+            return Ok(PlacedOrder {
+                payload,
+                response: None,
+            });
+        }
+        let response: OrderResponse = self
+            .clob_send(reqwest::Method::POST, "/order", signer, creds, &payload)
+            .await?;
+        if !response.success {
+            return Err(PlatformError::InvalidOrder(response.error_msg));
+        }
+        Ok(PlacedOrder {
+            payload,
+            response: Some(response),
+        })
+    }
+
+    /// Cancels a resting order. Nothing is sent, so nothing is cancelled, in dry-run mode.
+    pub async fn cancel_order(
+        &self,
+        signer: &ClobSigner,
+        creds: &ApiCreds,
+        order_id: &str,
+    ) -> Result<CancelResponse> {
+        if self.builder.dry_run {
+            return Ok(CancelResponse::default());
+        }
+        let body = serde_json::json!({ "orderID": order_id });
+        self.clob_send(reqwest::Method::DELETE, "/order", signer, creds, &body)
+            .await
+    }
+
+    /// Cancels `order_id`, then places `args` once the cancel is confirmed.
+    pub async fn replace_order(
+        &self,
+        signer: &ClobSigner,
+        creds: &ApiCreds,
+        order_id: &str,
+        args: &OrderArgs,
+    ) -> Result<PlacedOrder> {
+        let cancel = self.cancel_order(signer, creds, order_id).await?;
+        if !self.builder.dry_run && !cancel.canceled.iter().any(|id| id == order_id) {
+            let reason = cancel
+                .not_canceled
+                .get(order_id)
+                .cloned()
+                .unwrap_or_default();
+            return Err(PlatformError::InvalidOrder(format!(
+                "could not cancel {}: {}",
+                order_id, reason
+            )));
+        }
+        self.place_order(signer, creds, args).await
+    }
+
+    async fn clob_get<T: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<T> {
+        let builder = &self.builder;
+        let response = builder
+            .client
+            .get(format!("{}{}", builder.clob_endpoint, path))
+            .headers(get_headers())
+            .query(query)
+            .send()
+            .await?;
+        decode(response).await
+    }
+
+    /// Trading request authenticated with the L2 headers of `creds`.
+    async fn clob_send<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        signer: &ClobSigner,
+        creds: &ApiCreds,
+        body: &B,
+    ) -> Result<T> {
+        let builder = &self.builder;
+        let body = serde_json::to_string(body)
+            .map_err(|err| PlatformError::InvalidOrder(err.to_string()))?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let headers = creds.headers(signer.address(), timestamp, method.as_str(), path, &body)?;
+        let response = builder
+            .client
+            .request(method, format!("{}{}", builder.clob_endpoint, path))
+            .headers(get_headers())
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        decode(response).await
+    }
+}
+
 #[async_trait]
 impl Platform for PolymarketPlatform {
     // const ENDPOINT: &'static str = "https://clob.polymarket.com/markets";
-    const ENDPOINT: &'static str = "https://gamma-api.polymarket.com/";
+    const NAME: &'static str = "polymarket";
+    const ENDPOINT: &'static str = "https://gamma-api.polymarket.com";
     const SORT: &'static str = "order:";
+    // The CLOB `/markets` limit, the strictest endpoint we call.
+    const RATE_LIMIT: RateLimit = RateLimit::new(50, Duration::from_secs(10));
 
     type Market = PolymarketMarket;
     type Event = PolymarketEvent;
     type Position = PolymarketPosition;
     async fn fetch_questions(&self) -> Result<Vec<Self::Market>> {
-        let builder = &self.0;
-        let url = builder.endpoint.as_str().to_owned() + "/markets";
-        let limit = builder.limit;
-        let markets: Vec<Self::Market> = vec![];
-        let response = builder
+        Ok(self.fetch_questions_page(None).await?.items)
+    }
+    /// Walks the CLOB market listing, which pages with an opaque `next_cursor`.
+    async fn fetch_questions_page(&self, cursor: Option<String>) -> Result<Page<Self::Market>> {
+        let builder = &self.builder;
+        let mut request = builder
             .client
-            .get(url.to_string())
-            .headers(get_headers())
-            // .query(&("limit", builder.limit.to_string().as_str()))
-            // .query(&["limit", builder.limit.to])
-            .send()
-            .await?;
-        // .json::<Vec<Self::Market>>()
-        // .await?;
-        let response_headers = response.headers().clone();
-        let content_type = response_headers.get("content-type").unwrap();
-        let markets_text = response.text().await.unwrap();
-        println!("Markets text: {:?}", markets_text);
-        let response_body = if markets_text.trim().is_empty() {
-            None
-        } else {
-            let deserialized = if content_type == "application/json" {
-                serde_json::from_str::<Vec<PolymarketMarket>>(&markets_text)
-            } else {
-                let json = json!(markets_text);
-                serde_json::from_value::<Vec<PolymarketMarket>>(json)
-            }
-            .expect("Failed to parse JSON response");
-            Some(deserialized)
+            .get(format!("{}/markets", builder.clob_endpoint))
+            .headers(get_headers());
+        if let Some(next_cursor) = cursor {
+            request = request.query(&[("next_cursor", next_cursor)]);
+        }
+        let response = decode::<PolymarketResult>(request.send().await?).await?;
+        let next = match response.next_cursor.as_str() {
+            "" | END_CURSOR => None,
+            _ => Some(response.next_cursor),
         };
-        println!("Response: {:?}", response_body.unwrap());
-
-        println!("Markets: {:?}", markets);
-        // println!("Markets: {:?}", markets_text);
-
-        // // let markets = serde_json::from_str::<Vec<PolymarketMarket>>(&markets_text).unwrap();
-        // // // let markets = parse_polymarket_text(&markets_text);
-        // tracing::debug!("Markets: {:?}", markets_text);
-
-        Ok(markets)
+        Ok(Page {
+            items: response.data,
+            next,
+        })
     }
 
+    /// The CLOB market with condition id `id`, whose tokens carry `winner` once resolved.
     async fn fetch_question_by_id(&self, id: &str) -> Result<Self::Market> {
-        unimplemented!()
-    }
-    async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
-        let builder = &self.0;
-        let url = builder.endpoint.as_str();
+        let builder = &self.builder;
         let response = builder
             .client
-            .get(url)
+            .get(format!("{}/markets/{}", builder.clob_endpoint, id))
+            .headers(get_headers())
             .send()
-            .await?
-            .json()
-            .await
-            .expect("Failed to parse JSON response");
-        Ok(response)
+            .await?;
+        decode(response).await
+    }
+    async fn fetch_json(&self) -> Result<Vec<serde_json::Value>> {
+        let builder = &self.builder;
+        let url = format!("{}/markets", builder.endpoint);
+        let response = builder.client.get(url).send().await?;
+        decode(response).await
     }
 
+    /// Market buy of `amount` USDC of `outcome`, e.g. `YES`, in the market with
+    /// condition id `contract_id`, priced off the outcome token's current book.
+    /// Keys are the ones set with `credentials`, else `ClobSigner::from_env` and
+    /// `ApiCreds::from_env`.
     async fn build_order(
         &self,
-        token: &str,
+        contract_id: &str,
         amount: f64,
         nonce: &str,
         outcome: &str,
     ) -> Result<()> {
-        unimplemented!()
+        let nonce = match nonce {
+            "" => 0,
+            nonce => nonce
+                .parse()
+                .map_err(|_| PlatformError::InvalidOrder(format!("invalid nonce `{}`", nonce)))?,
+        };
+        let market = self.fetch_question_by_id(contract_id).await?;
+        let token = market
+            .tokens
+            .iter()
+            .flatten()
+            .find(|token| token.outcome.eq_ignore_ascii_case(outcome))
+            .map(|token| token.token_id.clone())
+            .ok_or_else(|| {
+                PlatformError::InvalidOrder(format!(
+                    "market {} has no outcome `{}`",
+                    contract_id, outcome
+                ))
+            })?;
+        let from_env;
+        let (signer, creds) = match &self.credentials {
+            Some((signer, creds)) => (signer, creds),
+            None => {
+                from_env = (ClobSigner::from_env()?, ApiCreds::from_env()?);
+                (&from_env.0, &from_env.1)
+            }
+        };
+        let price = self
+            .fetch_book(&token)
+            .await?
+            .marketable_price(BookSide::Buy, amount)
+            .ok_or_else(|| PlatformError::InvalidOrder(format!("book of {} too thin", token)))?;
+        let tick_size = self.fetch_tick_size(&token).await?;
+        let args = OrderArgs {
+            neg_risk: self.fetch_neg_risk(&token).await?,
+            nonce,
+            ..OrderArgs::market(&token, BookSide::Buy, amount, price, tick_size)
+        };
+        let placed = self.place_order(signer, creds, &args).await?;
+        tracing::debug!("Order: {:?}", placed);
+        Ok(())
     }
     async fn fetch_markets_by_terms(&self, terms: &str) -> Result<Vec<Self::Market>> {
-        let builder = &self.0;
+        let builder = &self.builder;
         let args: Vec<_> = [
             ("active", "true"),
             ("archived", "false"),
@@ -111,24 +331,16 @@ impl Platform for PolymarketPlatform {
         .iter()
         .map(|(arg, value)| (*arg, *value))
         .collect();
-        let url = format!("https://gamma-api.polymarket.com/events?tag={}", terms,);
+        let url = format!("{}/events?tag={}", builder.endpoint, terms);
         let response = builder
             .client
             .get(url)
             // .query(&args)
             .send()
-            .await?
-            .json::<Vec<Self::Market>>()
             .await?;
-        Ok(response)
+        decode::<Vec<Self::Market>>(response).await
     }
 
-    async fn fetch_ratelimited(
-        request_count: usize,
-        interval_ms: Option<u64>,
-    ) -> PlatformBuilder<Self> {
-        unimplemented!()
-    }
     async fn incoming_market_to_value(&self, market: Self::Market) -> Result<serde_json::Value> {
         unimplemented!()
     }
@@ -136,11 +348,16 @@ impl Platform for PolymarketPlatform {
         &self,
         position: Self::Position,
     ) -> Result<serde_json::Value> {
-        unimplemented!()
+        Ok(serde_json::json!({
+            "token_id": position.token_id,
+            "side": position.side,
+            "price": position.price,
+            "size": position.size,
+        }))
     }
 
     async fn fetch_json_by_description(&self, description: &str) -> Result<Vec<serde_json::Value>> {
-        let builder = &self.0;
+        let builder = &self.builder;
         let args: Vec<_> = [
             ("active", "true"),
             ("archived", "false"),
@@ -151,19 +368,14 @@ impl Platform for PolymarketPlatform {
         .iter()
         .map(|(arg, value)| (*arg, *value))
         .collect();
-        let url = format!(
-            "https://gamma-api.polymarket.com/events?tag={}",
-            description,
-        );
+        let url = format!("{}/events?tag={}", builder.endpoint, description);
         let response = builder
             .client
             .get(url)
             // .query(&args)
             .send()
-            .await?
-            .json::<Vec<serde_json::Value>>()
             .await?;
-        Ok(response)
+        decode::<Vec<serde_json::Value>>(response).await
     }
     async fn fetch_events(&self, limit: Option<u64>, offset: u64) -> Result<Vec<Self::Event>> {
         let offset = offset.to_string();
@@ -180,35 +392,149 @@ impl Platform for PolymarketPlatform {
         .iter()
         .map(|(arg, value)| (*arg, *value))
         .collect();
-        let url = "https://gamma-api.polymarket.com/events";
-        let builder = &self.0;
-        let response = builder
-            .client
-            .get(url)
-            .query(&args)
-            .send()
-            .await?
-            .json::<Vec<Self::Event>>()
-            .await?;
-        Ok(response)
+        let builder = &self.builder;
+        let url = format!("{}/events", builder.endpoint);
+        let response = builder.client.get(url).query(&args).send().await?;
+        decode::<Vec<Self::Event>>(response).await
     }
+    /// Book levels of the outcome token `id`, bids best first, then asks best first.
     async fn fetch_orderbook(&self, id: &str) -> Result<Vec<Self::Position>> {
-        unimplemented!()
+        Ok(self.fetch_book(id).await?.positions())
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::db::model::Market;
+    use crate::polymarket::BookSide;
+    use crate::server::mock::MockServer;
+
+    const YES_TOKEN: &str =
+        "21742633143463906290569050155826241533067272736897614950488156847949938836455";
+    const PRIVATE_KEY: &str = "0x4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+    async fn platform() -> (MockServer, PolymarketPlatform) {
+        let server = MockServer::start().await.unwrap();
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        (server, PolymarketPlatform::from(builder))
+    }
 
     #[tokio::test]
     async fn test_polymarket_markets() {
-        let platform = PolymarketPlatform::from(PlatformBuilder::default());
-        let markets = platform.fetch_questions().await.unwrap();
-        tracing::info!("Markets: {:?}", markets);
+        let (_server, platform) = platform().await;
+        let page = platform.fetch_questions_page(None).await.unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(
+            page.items[0].question.as_deref(),
+            Some("Will Bitcoin reach $100,000 by December 31, 2024?")
+        );
+        // `LTE=` marks the last page.
+        assert!(page.next.is_none());
+    }
+    #[tokio::test]
+    async fn test_polymarket_book() {
+        let (_server, platform) = platform().await;
+        let book = platform.fetch_book(YES_TOKEN).await.unwrap();
+        assert_eq!(book.best_bid(), Some(0.61));
+        assert_eq!(book.best_ask(), Some(0.63));
+        assert!((book.spread().unwrap() - 0.02).abs() < 1e-9);
+        // 100 at 0.63 and 100 of the 200 at 0.64.
+        assert!((book.fill_price(BookSide::Buy, 200.0).unwrap() - 0.635).abs() < 1e-9);
+        assert!(book.fill_price(BookSide::Sell, 10_000.0).is_none());
+        assert!(book.fill_price(BookSide::Buy, 0.0).is_none());
+        assert!(book.fill_price(BookSide::Buy, -5.0).is_none());
+        assert!(book.fill_price(BookSide::Buy, f64::NAN).is_none());
+        assert_eq!(book.depth(BookSide::Sell, 0.60), 350.0);
+
+        let positions = platform.fetch_orderbook(YES_TOKEN).await.unwrap();
+        assert_eq!(positions.len(), 6);
+        assert_eq!(positions[0].side, BookSide::Buy);
+        assert_eq!(positions[0].price, 0.61);
+
+        assert_eq!(platform.fetch_midpoint(YES_TOKEN).await.unwrap(), 0.62);
+        assert_eq!(platform.fetch_spread(YES_TOKEN).await.unwrap(), 0.02);
+    }
+    #[tokio::test]
+    async fn test_polymarket_price_history() {
+        let (_server, platform) = platform().await;
+        let mut market = platform.fetch_questions().await.unwrap().remove(0);
+        platform
+            .fetch_market_history(&mut market, PriceInterval::Day, 60)
+            .await
+            .unwrap();
+        assert_eq!(market.price_history.len(), 4);
+        assert_eq!(market.prob_latest(), 0.62);
+    }
+    #[tokio::test]
+    async fn test_place_and_replace_order() {
+        let (server, platform) = platform().await;
+        let signer = ClobSigner::new(PRIVATE_KEY).unwrap();
+        let creds = ApiCreds {
+            api_key: "api-key".to_string(),
+            secret: "c2VjcmV0".to_string(),
+            passphrase: "passphrase".to_string(),
+        };
+        let market = platform.fetch_questions().await.unwrap().remove(0);
+        let token = market.yes_token().unwrap();
+        let args = OrderArgs {
+            neg_risk: market.neg_risk,
+            ..OrderArgs::limit(
+                &token.token_id,
+                BookSide::Buy,
+                0.6,
+                10.0,
+                market.order_price_min_tick_size,
+            )
+        };
+
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        builder.dry_run(true);
+        let dry_run = PolymarketPlatform::from(builder);
+        let placed = dry_run.place_order(&signer, &creds, &args).await.unwrap();
+        assert!(placed.response.is_none());
+        assert_eq!(placed.payload.order.maker_amount, "6000000");
+        assert_eq!(placed.payload.order.taker_amount, "10000000");
+
+        let placed = platform.place_order(&signer, &creds, &args).await.unwrap();
+        assert_eq!(placed.response.unwrap().order_id, "0xmock-order");
+        let replaced = platform
+            .replace_order(&signer, &creds, "0xmock-order", &args)
+            .await
+            .unwrap();
+        assert!(replaced.response.unwrap().success);
+    }
+    #[tokio::test]
+    async fn test_build_order() {
+        let (_server, mut platform) = platform().await;
+        let condition_id = "0x9c1a953fe92c8357f1b646ba25d983aa83e90c525992db14fb726fa895cb5763";
+        let creds = ApiCreds {
+            api_key: "api-key".to_string(),
+            secret: "c2VjcmV0".to_string(),
+            passphrase: "passphrase".to_string(),
+        };
+        platform.credentials(ClobSigner::new(PRIVATE_KEY).unwrap(), creds);
+        platform
+            .build_order(condition_id, 10.0, "", "YES")
+            .await
+            .unwrap();
+        platform
+            .build_order(condition_id, 10.0, "7", "no")
+            .await
+            .unwrap();
+        assert!(matches!(
+            platform.build_order(condition_id, 10.0, "", "BUY").await,
+            Err(PlatformError::InvalidOrder(_))
+        ));
     }
     #[tokio::test]
     async fn test_polymarket_events() {
-        let platform = PolymarketPlatform::from(PlatformBuilder::default());
+        let (_server, platform) = platform().await;
         let events = platform.fetch_events(Some(5), 1).await.unwrap();
-        println!("Events: {:?}", events);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].markets.len(), 1);
+        assert_eq!(events[0].neg_risk, Some(false));
     }
 }
@@ -1,4 +1,9 @@
+use crate::api::Platform;
+use crate::db::ledger::ForecastLedger;
+use crate::model::forecast::Forecast;
+use crate::types::{Market, OutcomeTick, Tick};
 use crate::{executor::executor::Executor, types::StrategyConfig};
+use axum::async_trait;
 use ratatui::widgets::ListState;
 use ratatui::widgets::{Block, List, ListItem};
 use ratatui::{
@@ -8,6 +13,7 @@ use ratatui::{
     DefaultTerminal,
 };
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio::sync::broadcast::{Receiver, Sender};
 use tokio::task::JoinSet;
 
@@ -53,10 +59,82 @@ impl<M> StatefulList<M> {
     }
 }
 
+/// Source of items for the context's channel, run alongside the executors.
+#[async_trait]
+pub trait Collector<M>: Send + Sync {
+    async fn collect(&self, tx: Sender<M>) -> anyhow::Result<()>;
+}
+
+/// Polls a platform every `period` and publishes a probability tick per market.
 pub struct MarketCollector<M> {
-    provider: M,
+    provider: Arc<M>,
+    period: Duration,
+}
+
+impl<M> MarketCollector<M> {
+    pub fn new(provider: Arc<M>, period: Duration) -> Self {
+        Self { provider, period }
+    }
+}
+
+#[async_trait]
+impl<P> Collector<Market> for MarketCollector<P>
+where
+    P: Platform + Send + Sync + 'static,
+    P::Market: crate::db::model::Market + Send,
+{
+    async fn collect(&self, tx: Sender<Market>) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.period);
+        loop {
+            interval.tick().await;
+            let markets = match self.provider.fetch_questions().await {
+                Ok(markets) => markets,
+                Err(e) => {
+                    tracing::error!("error polling markets: {}", e);
+                    continue;
+                }
+            };
+            let timestamp = chrono::Utc::now().timestamp();
+            for market in markets {
+                // Sending only fails while nothing is subscribed.
+                let _ = tx.send(Market::MarketPosition(outcome_tick(&market, timestamp)));
+            }
+        }
+    }
+}
+
+/// Logs `forecast` and records it in `ledger`, if there is one and the forecast
+/// is tied to a market.
+fn keep_forecast(ledger: Option<&ForecastLedger>, forecast: &Forecast) {
+    tracing::info!(
+        "forecast {:.3} for {} on {:?}",
+        forecast.probability,
+        forecast.outcome,
+        forecast.question
+    );
+    let Some(ledger) = ledger else {
+        return;
+    };
+    if forecast.platform_id.is_none() {
+        return;
+    }
+    if let Err(e) = ledger.record(forecast) {
+        tracing::error!("error recording forecast: {}", e);
+    }
+}
+
+fn outcome_tick<M: crate::db::model::Market>(market: &M, timestamp: i64) -> OutcomeTick {
+    OutcomeTick {
+        platform: market.platform().to_string(),
+        market_id: market.platform_id(),
+        outcome: "YES".to_string(),
+        tick: Tick {
+            timestamp,
+            probability: market.prob_latest() as f64,
+            volume: market.volume_usd() as f64,
+        },
+    }
 }
-impl<M> MarketCollector<M> {}
 
 pub struct OrderCollector<M> {
     provider: M,
@@ -71,8 +149,10 @@ pub struct Context<M> {
     pub outcome: String,
     pub tags: Vec<String>,
     pub executors: Vec<Box<dyn Executor<M>>>,
+    pub collectors: Vec<Box<dyn Collector<M>>>,
     pub exit: bool,
     question_channel_capacity: usize,
+    ledger: Option<ForecastLedger>,
 }
 
 impl<M: Sync + Send + Clone + 'static> Context<M> {
@@ -80,6 +160,7 @@ impl<M: Sync + Send + Clone + 'static> Context<M> {
         let strategy_config = StrategyConfig::default();
         Context {
             executors: vec![],
+            collectors: vec![],
             tags: vec![],
             outcome: "".to_string(),
             questions: StatefulList::with_items(vec![]),
@@ -87,6 +168,7 @@ impl<M: Sync + Send + Clone + 'static> Context<M> {
             question_channel_capacity: 512,
             strategy_config: Arc::new(RwLock::new(strategy_config)),
             exit: false,
+            ledger: None,
         }
     }
 
@@ -94,6 +176,12 @@ impl<M: Sync + Send + Clone + 'static> Context<M> {
         self.question_channel_capacity = capacity;
         self
     }
+
+    /// Records the forecasts executors make in `ledger`, to be scored once settled.
+    pub fn with_ledger(mut self, ledger: ForecastLedger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
 }
 
 impl<M: Send + Sync + Clone + 'static> Default for Context<M> {
@@ -114,6 +202,10 @@ where
         self.executors.push(executor);
     }
 
+    pub fn add_collector(&mut self, collector: Box<dyn Collector<M>>) {
+        self.collectors.push(collector);
+    }
+
     //Core run loop. Spawn thread for each question/executo.
     pub async fn run(self) -> Result<JoinSet<()>, Box<dyn std::error::Error>> {
         let (tx, _): (Sender<M>, _) =
@@ -121,16 +213,23 @@ where
         let mut set = JoinSet::new();
         for executor in self.executors {
             let mut rx = tx.subscribe();
+            let ledger = self.ledger.clone();
+            let questions = self.questions.items.clone();
+            let outcome = self.outcome.clone();
+            let tags = self.tags.clone();
             set.spawn(async move {
                 tracing::info!("starting executor... ");
+                for question in &questions {
+                    match executor.init(question, &outcome, tags.clone()).await {
+                        Ok(forecast) => keep_forecast(ledger.as_ref(), &forecast),
+                        Err(e) => tracing::debug!("no forecast for {:?}: {}", question, e),
+                    }
+                }
                 loop {
-
                     match rx.recv().await {
-                        //TODO: un-dummy this
-                        Ok(market) => match executor.execute(market).await
-                            //"Will the 10 Year Treasury Yield at closing on 12/31/2024 be 4% or higher?", "The 10 year treasury yield closing at 4% or higher on 12/31/2024", vec!["us-economics".to_string()]).await 
-                            {
-                            Ok(_) => {}
+                        Ok(market) => match executor.execute(market).await {
+                            Ok(Some(forecast)) => keep_forecast(ledger.as_ref(), &forecast),
+                            Ok(None) => {}
                             Err(e) => tracing::error!("error executing action: {}", e),
                         },
                         Err(e) => tracing::error!("error receiving action: {}", e),
@@ -138,6 +237,15 @@ where
                 }
             });
         }
+        for collector in self.collectors {
+            let tx = tx.clone();
+            set.spawn(async move {
+                tracing::info!("starting collector... ");
+                if let Err(e) = collector.collect(tx).await {
+                    tracing::error!("error collecting markets: {}", e);
+                }
+            });
+        }
 
         Ok(set)
     }
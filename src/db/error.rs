@@ -1,2 +1,49 @@
-#[derive(thiserror::Error, Debug, Clone)]
-pub enum Error {}
+#[derive(thiserror::Error, Debug, Clone, PartialEq)]
+pub enum Error {
+    #[error("{platform} market is missing `{field}`")]
+    MissingField {
+        platform: &'static str,
+        field: &'static str,
+    },
+    #[error("invalid timestamp `{0}`")]
+    InvalidTimestamp(String),
+    #[error("storage error: {0}")]
+    Storage(String),
+    #[error("serialization error: {0}")]
+    Serialization(String),
+    #[error("market error: {0}")]
+    Market(String),
+}
+
+impl From<sled::Error> for Error {
+    fn from(value: sled::Error) -> Self {
+        Error::Storage(value.to_string())
+    }
+}
+
+impl From<sled::transaction::TransactionError<Error>> for Error {
+    fn from(value: sled::transaction::TransactionError<Error>) -> Self {
+        match value {
+            sled::transaction::TransactionError::Abort(err) => err,
+            sled::transaction::TransactionError::Storage(err) => err.into(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Serialization(value.to_string())
+    }
+}
+
+impl From<sqlx::Error> for Error {
+    fn from(value: sqlx::Error) -> Self {
+        Error::Storage(value.to_string())
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(value: sqlx::migrate::MigrateError) -> Self {
+        Error::Storage(value.to_string())
+    }
+}
@@ -0,0 +1,390 @@
+use super::error::Error;
+use super::model::forecast::{Forecast, Score};
+use super::model::Market;
+use super::repository::{index_prefix, sortable_seconds};
+use super::MarketsDb;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const FORECASTS_TREE: &str = "forecast_ledger";
+
+/// Every forecast made on a market, kept until and after its market resolves.
+///
+/// Keys are the platform and market id, each `\0`-terminated, then the sortable
+/// forecast time and a sequence number from the database, so a market's forecasts
+/// are one contiguous range and forecasts made in the same second all stay.
+#[derive(Clone)]
+pub struct ForecastLedger {
+    db: MarketsDb,
+    forecasts: sled::Tree,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub forecast: Forecast,
+    /// What the forecast's outcome settled at, once its market resolved.
+    pub outcome: Option<f64>,
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// When the market ended without settling the forecast's outcome, e.g. because
+    /// it was cancelled or annulled.
+    #[serde(default)]
+    pub voided_at: Option<DateTime<Utc>>,
+}
+
+impl LedgerEntry {
+    pub fn score(&self) -> Option<Score> {
+        self.outcome.map(|outcome| self.forecast.score(outcome))
+    }
+
+    /// Still waiting on the market, neither settled nor voided.
+    pub fn is_pending(&self) -> bool {
+        self.outcome.is_none() && self.voided_at.is_none()
+    }
+}
+
+/// Forecasts sharing a model, prompt version and category whose probabilities
+/// fell in `[lower, upper)`, against how often their outcomes happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    pub mean_probability: f64,
+    pub observed: f64,
+}
+
+/// Mean scores of the resolved forecasts of one model, prompt version and category.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    pub model: String,
+    pub prompt_version: String,
+    pub category: String,
+    pub count: usize,
+    pub brier: f64,
+    pub log: f64,
+    /// Means over the forecasts made against a market price, `None` if there were none.
+    pub relative_brier: Option<f64>,
+    pub relative_log: Option<f64>,
+    /// Only bins holding forecasts, lowest first.
+    pub bins: Vec<CalibrationBin>,
+}
+
+impl ForecastLedger {
+    pub fn open(db: &MarketsDb) -> Result<Self> {
+        Ok(Self {
+            db: db.clone(),
+            forecasts: db.open_tree(FORECASTS_TREE)?,
+        })
+    }
+
+    /// Ledger backed by a throwaway database, removed on drop.
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Self::open(&db)
+    }
+
+    /// Adds a forecast to be settled when its market resolves. Forecasts not tied
+    /// to a market can never settle and are refused.
+    pub fn record(&self, forecast: &Forecast) -> Result<()> {
+        let platform_id = forecast.platform_id.as_deref().ok_or_else(|| {
+            Error::Market(format!("forecast of {:?} has no market", forecast.question))
+        })?;
+        let mut key = market_prefix(&forecast.platform, platform_id);
+        key.extend_from_slice(&sortable_seconds(forecast.timestamp.timestamp()));
+        key.extend_from_slice(&self.db.generate_id()?.to_be_bytes());
+        let entry = LedgerEntry {
+            forecast: forecast.clone(),
+            outcome: None,
+            resolved_at: None,
+            voided_at: None,
+        };
+        self.forecasts.insert(key, serde_json::to_vec(&entry)?)?;
+        Ok(())
+    }
+
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>> {
+        self.forecasts
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    /// Ids of the markets on `platform` with forecasts still waiting on a resolution.
+    pub fn pending_markets(&self, platform: &str) -> Result<Vec<String>> {
+        let mut ids: Vec<String> = Vec::new();
+        for value in self.forecasts.scan_prefix(index_prefix(platform)).values() {
+            let entry: LedgerEntry = serde_json::from_slice(&value?)?;
+            if !entry.is_pending() {
+                continue;
+            }
+            let id = entry.forecast.platform_id.unwrap_or_default();
+            if ids.last() != Some(&id) {
+                ids.push(id);
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Settles the pending forecasts on `market` if it has resolved, returning how
+    /// many were settled. Forecasts the final market does not settle, such as those
+    /// on a cancelled market, are voided and counted too.
+    pub fn settle<M: Market + ?Sized>(&self, market: &M, now: DateTime<Utc>) -> Result<usize> {
+        let prefix = market_prefix(market.platform(), &market.platform_id());
+        let mut settled = 0;
+        for item in self.forecasts.scan_prefix(&prefix) {
+            let (key, value) = item?;
+            let mut entry: LedgerEntry = serde_json::from_slice(&value)?;
+            if !entry.is_pending() {
+                continue;
+            }
+            match market.settlement(&entry.forecast.outcome) {
+                Some(outcome) => {
+                    entry.outcome = Some(outcome);
+                    entry.resolved_at = Some(now);
+                }
+                None if market.is_final() => entry.voided_at = Some(now),
+                None => continue,
+            }
+            self.forecasts.insert(key, serde_json::to_vec(&entry)?)?;
+            settled += 1;
+        }
+        Ok(settled)
+    }
+
+    /// Calibration of resolved forecasts, one row per model, prompt version and
+    /// category, with probabilities split into `bins` equal-width bins.
+    pub fn calibration(&self, bins: usize) -> Result<Vec<Calibration>> {
+        let mut groups: BTreeMap<(String, String, String), Vec<(f64, f64, Score)>> =
+            BTreeMap::new();
+        for entry in self.entries()? {
+            let Some(score) = entry.score() else {
+                continue;
+            };
+            let forecast = entry.forecast;
+            groups
+                .entry((forecast.model, forecast.prompt_version, forecast.category))
+                .or_default()
+                .push((
+                    forecast.probability,
+                    entry.outcome.unwrap_or_default(),
+                    score,
+                ));
+        }
+        Ok(groups
+            .into_iter()
+            .map(|((model, prompt_version, category), scored)| Calibration {
+                model,
+                prompt_version,
+                category,
+                count: scored.len(),
+                brier: mean(scored.iter().map(|(_, _, s)| s.brier)).unwrap_or_default(),
+                log: mean(scored.iter().map(|(_, _, s)| s.log)).unwrap_or_default(),
+                relative_brier: mean(scored.iter().filter_map(|(_, _, s)| s.relative_brier)),
+                relative_log: mean(scored.iter().filter_map(|(_, _, s)| s.relative_log)),
+                bins: calibration_bins(&scored, bins),
+            })
+            .collect())
+    }
+}
+
+fn calibration_bins(scored: &[(f64, f64, Score)], bins: usize) -> Vec<CalibrationBin> {
+    let bins = bins.max(1);
+    let width = 1.0 / bins as f64;
+    let mut sums = vec![(0, 0.0, 0.0); bins];
+    for (probability, outcome, _) in scored {
+        // Scaling rather than dividing by the width keeps round probabilities such as
+        // 0.3 in their own bin; a probability of exactly 1 belongs to the top bin.
+        let bin = ((probability * bins as f64) as usize).min(bins - 1);
+        sums[bin].0 += 1;
+        sums[bin].1 += probability;
+        sums[bin].2 += outcome;
+    }
+    sums.into_iter()
+        .enumerate()
+        .filter(|(_, (count, _, _))| *count > 0)
+        .map(|(bin, (count, probability, outcome))| CalibrationBin {
+            lower: bin as f64 * width,
+            upper: (bin + 1) as f64 * width,
+            count,
+            mean_probability: probability / count as f64,
+            observed: outcome / count as f64,
+        })
+        .collect()
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (count, sum) = values.fold((0, 0.0), |(count, sum), value| (count + 1, sum + value));
+    (count > 0).then(|| sum / count as f64)
+}
+
+fn market_prefix(platform: &str, platform_id: &str) -> Vec<u8> {
+    let mut prefix = index_prefix(platform);
+    prefix.extend_from_slice(&index_prefix(platform_id));
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifold::ManifoldMarket;
+    use crate::metaculus::{MetaculusMarket, Status};
+
+    const MANIFOLD_MARKET: &str = include_str!("../../tests/fixtures/manifold_market.json");
+    const METACULUS_MARKET: &str = include_str!("../../tests/fixtures/metaculus_market.json");
+
+    fn forecast(platform: &str, id: &str, probability: f64, timestamp: i64) -> Forecast {
+        Forecast {
+            platform: platform.to_string(),
+            platform_id: Some(id.to_string()),
+            model: "gpt-4o".to_string(),
+            timestamp: DateTime::from_timestamp(timestamp, 0).unwrap(),
+            market_probability: Some(0.5),
+            category: "finance".to_string(),
+            ..Forecast::fixture(probability)
+        }
+    }
+
+    #[test]
+    fn test_settle_on_resolution() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        ledger
+            .record(&forecast("manifold", &market.id, 0.8, 100))
+            .unwrap();
+        ledger
+            .record(&forecast("manifold", &market.id, 0.6, 200))
+            .unwrap();
+        ledger
+            .record(&forecast("manifold", "other", 0.5, 100))
+            .unwrap();
+        let mut unlinked = forecast("manifold", "", 0.5, 100);
+        unlinked.platform_id = None;
+        assert!(ledger.record(&unlinked).is_err());
+
+        assert_eq!(ledger.settle(&market, Utc::now()).unwrap(), 0);
+        assert_eq!(ledger.pending_markets("manifold").unwrap().len(), 2);
+        market.isResolved = Some(true);
+        market.resolution = Some("NO".to_string());
+        assert_eq!(ledger.settle(&market, Utc::now()).unwrap(), 2);
+        assert_eq!(ledger.settle(&market, Utc::now()).unwrap(), 0);
+        assert_eq!(ledger.pending_markets("manifold").unwrap(), vec!["other"]);
+
+        let entries = ledger.entries().unwrap();
+        let settled: Vec<Score> = entries.iter().filter_map(LedgerEntry::score).collect();
+        assert_eq!(settled.len(), 2);
+        assert!(settled
+            .iter()
+            .all(|score| score.relative_brier.unwrap() < 0.0));
+    }
+
+    #[test]
+    fn test_same_second_forecasts_are_kept() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let yes = forecast("manifold", "9Ccsjc0fmbIb9g50p7SB", 0.7, 100);
+        let no = Forecast {
+            outcome: "no".to_string(),
+            ..yes.clone()
+        };
+        ledger.record(&yes).unwrap();
+        ledger.record(&yes).unwrap();
+        ledger.record(&no).unwrap();
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].forecast.outcome, "no");
+    }
+
+    #[test]
+    fn test_cancelled_market_voids_forecasts() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        ledger
+            .record(&forecast("manifold", &market.id, 0.8, 100))
+            .unwrap();
+        market.isResolved = Some(true);
+        market.resolution = Some("CANCEL".to_string());
+        let now = Utc::now();
+        assert_eq!(ledger.settle(&market, now).unwrap(), 1);
+        assert!(ledger.pending_markets("manifold").unwrap().is_empty());
+        let entry = &ledger.entries().unwrap()[0];
+        assert_eq!((entry.outcome, entry.voided_at), (None, Some(now)));
+        assert_eq!(entry.score(), None);
+        assert!(ledger.calibration(10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_metaculus_settles_once_resolved() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let mut market: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        let id = market.id.to_string();
+        ledger
+            .record(&forecast("metaculus", &id, 0.3, 100))
+            .unwrap();
+        let question = market.question.as_mut().unwrap();
+        question.resolution = Some("yes".to_string());
+        // Resolution values appear before the post's status flips.
+        assert_eq!(ledger.settle(&market, Utc::now()).unwrap(), 0);
+        market.status = Some(Status::resolved);
+        assert_eq!(ledger.settle(&market, Utc::now()).unwrap(), 1);
+        assert_eq!(ledger.entries().unwrap()[0].outcome, Some(1.0));
+    }
+
+    #[test]
+    fn test_calibration_groups() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        market.isResolved = Some(true);
+        market.resolution = Some("YES".to_string());
+        for (timestamp, probability) in [(1, 0.72), (2, 0.78), (3, 0.15)] {
+            let forecast = forecast("manifold", &market.id, probability, timestamp);
+            ledger.record(&forecast).unwrap();
+        }
+        let mut other_prompt = forecast("manifold", &market.id, 0.9, 4);
+        other_prompt.prompt_version = "contrarian".to_string();
+        ledger.record(&other_prompt).unwrap();
+        ledger.settle(&market, Utc::now()).unwrap();
+
+        let calibration = ledger.calibration(10).unwrap();
+        assert_eq!(calibration.len(), 2);
+        let row = calibration.iter().find(|row| row.count == 3).unwrap();
+        assert_eq!(
+            (row.model.as_str(), row.category.as_str()),
+            ("gpt-4o", "finance")
+        );
+        let brier = (0.28f64.powi(2) + 0.22f64.powi(2) + 0.85f64.powi(2)) / 3.0;
+        assert!((row.brier - brier).abs() < 1e-12);
+        assert!((row.relative_brier.unwrap() - (0.25 - brier)).abs() < 1e-12);
+        let bins: Vec<(usize, f64)> = row
+            .bins
+            .iter()
+            .map(|bin| (bin.count, (bin.lower * 10.0).round()))
+            .collect();
+        assert_eq!(bins, vec![(1, 1.0), (2, 7.0)]);
+        assert!((row.bins[1].mean_probability - 0.75).abs() < 1e-12);
+        assert_eq!(row.bins[1].observed, 1.0);
+    }
+
+    #[test]
+    fn test_calibration_bins_round_probabilities() {
+        let ledger = ForecastLedger::temporary().unwrap();
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        market.isResolved = Some(true);
+        market.resolution = Some("NO".to_string());
+        for (timestamp, probability) in [(1, 0.3), (2, 0.6), (3, 0.7), (4, 1.0)] {
+            let forecast = forecast("manifold", &market.id, probability, timestamp);
+            ledger.record(&forecast).unwrap();
+        }
+        ledger.settle(&market, Utc::now()).unwrap();
+
+        let calibration = ledger.calibration(10).unwrap();
+        let lowers: Vec<f64> = calibration[0]
+            .bins
+            .iter()
+            .map(|bin| (bin.lower * 10.0).round())
+            .collect();
+        assert_eq!(lowers, vec![3.0, 6.0, 7.0, 9.0]);
+    }
+}
@@ -2,11 +2,21 @@ pub mod model;
 pub use model::*;
 pub mod error;
 pub mod graphql;
+pub mod ledger;
+pub mod repository;
+pub mod sqlite;
+pub mod store;
+pub mod timeseries;
 use async_graphql::{http::GraphiQLSource, Schema};
 use axum::response::{self, IntoResponse};
+use error::Error;
 use question::{MutationRoot, QueryRoot, QuestionStorage, SubscriptionRoot};
+use repository::MarketRepository;
+use sqlite::SqliteMarketStore;
+use store::MarketStore;
 pub enum DbType {
-    GraphQL,
+    Sled,
+    Sqlite,
 }
 pub type MarketsDb = sled::Db;
 
@@ -20,39 +30,24 @@ pub async fn build_graphql() -> impl IntoResponse {
     response
 }
 
-pub async fn build_schema() {
-    let schema = Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
-        .data(QuestionStorage::default())
-        .finish();
+pub fn build_schema(storage: QuestionStorage) -> Schema<QueryRoot, MutationRoot, SubscriptionRoot> {
+    Schema::build(QueryRoot, MutationRoot, SubscriptionRoot)
+        .data(storage)
+        .finish()
 }
 
-// pub fn build(db_type: DbType, db_path: &str) -> Result<Box<dyn Db>, Error> {
-//     let db = match db_type {
-//         DbType::GraphQL =>
-//             Builder::<GraphQL>::build(db_path)
-//         }?;
-
-//     }
-
-// pub struct Builder<T>
-// where
-//     T: Db,
-// {
-//     phantom: std::marker::PhantomData<T>,
-// }
-
-// impl<T> Builder<T>
-// where
-//     T: Db + 'static,
-// {
-//     pub(self) fn build(db_path: &str) -> Result<Box<dyn Db>, Error> {
-//         todo!()
-//     }
-// }
-
-// pub trait Db: Sync + Send {
-//     fn new() -> Result<Self, Error>
-//     where
-//         Self: Sized;
-//     fn create(&self, db_path: &str) -> Result<(), Error>;
-// }
+/// Opens a `MarketStore`. `db_path` is a directory for sled and a database url
+/// such as `sqlite://markets.db` for SQLite.
+pub async fn build(
+    db_type: DbType,
+    db_path: &str,
+) -> std::result::Result<Box<dyn MarketStore>, Error> {
+    let store: Box<dyn MarketStore> = match db_type {
+        DbType::Sled => {
+            let db = sled::Config::default().path(db_path).open()?;
+            Box::new(MarketRepository::open(&db)?)
+        }
+        DbType::Sqlite => Box::new(SqliteMarketStore::connect(db_path).await?),
+    };
+    Ok(store)
+}
@@ -1 +1,221 @@
+use super::{Market, Result as MarketResult, Update, UNRESOLVED};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
+/// Outcome index Augur v2 reserves for "Invalid" in every market.
+pub const INVALID_OUTCOME: usize = 0;
+/// Outcome index of "Yes" in yes/no markets.
+pub const YES_OUTCOME: usize = 2;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AugurMarketType {
+    YesNo,
+    Categorical,
+    Scalar,
+}
+
+impl TryFrom<u64> for AugurMarketType {
+    type Error = u64;
+    fn try_from(value: u64) -> std::result::Result<Self, Self::Error> {
+        match value {
+            0 => Ok(AugurMarketType::YesNo),
+            1 => Ok(AugurMarketType::Categorical),
+            2 => Ok(AugurMarketType::Scalar),
+            other => Err(other),
+        }
+    }
+}
+
+/// `extraInfo` JSON attached to a `MarketCreated` event by the Augur UI.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AugurExtraInfo {
+    #[serde(default)]
+    pub description: String,
+    pub long_description: Option<String>,
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+/// A market read from its `MarketCreated` event, plus on-chain state filled in later.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AugurMarket {
+    pub address: String,
+    pub universe: String,
+    pub creator: String,
+    pub extra_info: AugurExtraInfo,
+    pub market_type: AugurMarketType,
+    pub num_ticks: u64,
+    /// Labels indexed by outcome, starting with "Invalid".
+    pub outcomes: Vec<String>,
+    pub created_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub block_number: u64,
+    #[serde(default)]
+    pub finalized: bool,
+    /// Winning payout numerators by outcome, once finalized.
+    #[serde(default)]
+    pub payout_numerators: Vec<u64>,
+    #[serde(default)]
+    pub fills: Vec<AugurFill>,
+}
+
+/// One filled order from the trading contract's `OrderEvent` log.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AugurFill {
+    pub market: String,
+    pub outcome: usize,
+    /// Price in ticks, `0..=num_ticks`.
+    pub price: u64,
+    /// Shares filled, in attoshares.
+    pub amount: u128,
+    pub time: DateTime<Utc>,
+}
+
+impl AugurMarket {
+    /// Default outcome labels; categorical markets list theirs after "Invalid".
+    pub fn outcome_labels(market_type: AugurMarketType, named: Vec<String>) -> Vec<String> {
+        let mut labels = vec!["Invalid".to_string()];
+        match market_type {
+            AugurMarketType::YesNo => labels.extend(["No".to_string(), "Yes".to_string()]),
+            AugurMarketType::Scalar => labels.extend(["Short".to_string(), "Long".to_string()]),
+            AugurMarketType::Categorical => labels.extend(named),
+        }
+        labels
+    }
+
+    /// Fill price as a probability in `[0, 1]`.
+    pub fn price_probability(&self, fill: &AugurFill) -> f32 {
+        if self.num_ticks == 0 {
+            return 0.0;
+        }
+        fill.price as f32 / self.num_ticks as f32
+    }
+
+    /// Last traded price per outcome, as a probability.
+    pub fn last_prices(&self) -> Vec<Option<f32>> {
+        let mut prices = vec![None; self.outcomes.len()];
+        for fill in &self.fills {
+            if let Some(price) = prices.get_mut(fill.outcome) {
+                *price = Some(self.price_probability(fill));
+            }
+        }
+        prices
+    }
+}
+
+impl Market for AugurMarket {
+    fn title(&self) -> &str {
+        &self.extra_info.description
+    }
+    fn platform(&self) -> &str {
+        "augur"
+    }
+    fn platform_id(&self) -> String {
+        self.address.clone()
+    }
+    fn open_time(&self) -> MarketResult<DateTime<Utc>> {
+        Ok(self.created_time)
+    }
+    fn close_time(&self) -> MarketResult<DateTime<Utc>> {
+        Ok(self.end_time)
+    }
+    /// Filled shares at their fill price, treating the DAI collateral as USD.
+    fn volume_usd(&self) -> f32 {
+        self.fills
+            .iter()
+            .map(|fill| self.price_probability(fill) * (fill.amount as f64 / 1e18) as f32)
+            .sum()
+    }
+    // Only fills are read, and those do not carry both counterparties.
+    fn num_traders(&self) -> i32 {
+        0
+    }
+    fn category(&self) -> &str {
+        self.extra_info
+            .categories
+            .first()
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+    fn resolution(&self) -> f32 {
+        if !self.finalized || self.market_type != AugurMarketType::YesNo || self.num_ticks == 0 {
+            return UNRESOLVED;
+        }
+        // An invalid market pays out everything on outcome 0.
+        match self.payout_numerators.get(YES_OUTCOME) {
+            Some(yes) if self.payout_numerators[INVALID_OUTCOME] == 0 => {
+                *yes as f32 / self.num_ticks as f32
+            }
+            _ => UNRESOLVED,
+        }
+    }
+    /// Yes fills of yes/no markets; other market types have no single probability.
+    fn events(&self) -> Vec<Update> {
+        if self.market_type != AugurMarketType::YesNo {
+            return Vec::new();
+        }
+        self.fills
+            .iter()
+            .filter(|fill| fill.outcome == YES_OUTCOME)
+            .map(|fill| Update::new(fill.time, self.price_probability(fill)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market() -> AugurMarket {
+        AugurMarket {
+            address: "0x0000000000000000000000000000000000000abc".to_string(),
+            universe: "0x0000000000000000000000000000000000000001".to_string(),
+            creator: "0x0000000000000000000000000000000000000002".to_string(),
+            extra_info: AugurExtraInfo {
+                description: "Will it rain?".to_string(),
+                long_description: None,
+                categories: vec!["weather".to_string()],
+            },
+            market_type: AugurMarketType::YesNo,
+            num_ticks: 100,
+            outcomes: AugurMarket::outcome_labels(AugurMarketType::YesNo, Vec::new()),
+            created_time: DateTime::from_timestamp(1_600_000_000, 0).unwrap(),
+            end_time: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            block_number: 10_600_000,
+            finalized: false,
+            payout_numerators: Vec::new(),
+            fills: vec![
+                AugurFill {
+                    market: "0x0000000000000000000000000000000000000abc".to_string(),
+                    outcome: YES_OUTCOME,
+                    price: 40,
+                    amount: 10 * 10u128.pow(18),
+                    time: DateTime::from_timestamp(1_650_000_000, 0).unwrap(),
+                },
+                AugurFill {
+                    market: "0x0000000000000000000000000000000000000abc".to_string(),
+                    outcome: 1,
+                    price: 30,
+                    amount: 10u128.pow(18),
+                    time: DateTime::from_timestamp(1_660_000_000, 0).unwrap(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_events_and_resolution() {
+        let mut market = market();
+        assert_eq!(market.events().len(), 1);
+        assert_eq!(market.prob_latest(), 0.4);
+        assert_eq!(market.last_prices(), vec![None, Some(0.3), Some(0.4)]);
+        assert_eq!(market.resolution(), UNRESOLVED);
+
+        market.finalized = true;
+        market.payout_numerators = vec![0, 0, 100];
+        assert_eq!(market.resolution(), 1.0);
+        market.payout_numerators = vec![100, 0, 0];
+        assert_eq!(market.resolution(), UNRESOLVED);
+    }
+}
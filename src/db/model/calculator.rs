@@ -0,0 +1,132 @@
+use super::{Update, DEFAULT_OPENING_PROB, SECS_PER_DAY};
+use chrono::{DateTime, TimeDelta, Utc};
+
+/// Summary probabilities over a market's `Update` history.
+///
+/// Every platform's `Market` implementation goes through this, so the numbers are
+/// comparable across platforms. Before the first update the probability is
+/// `DEFAULT_OPENING_PROB`.
+#[derive(Debug, Clone)]
+pub struct Calculator {
+    events: Vec<Update>,
+    open_time: DateTime<Utc>,
+    close_time: DateTime<Utc>,
+}
+
+impl Calculator {
+    pub fn new(
+        mut events: Vec<Update>,
+        open_time: DateTime<Utc>,
+        close_time: DateTime<Utc>,
+    ) -> Self {
+        events.sort_by_key(|event| event.time);
+        Self {
+            events,
+            open_time,
+            close_time,
+        }
+    }
+
+    /// Probability in effect at `time`, i.e. the latest update at or before it.
+    pub fn prob_at(&self, time: DateTime<Utc>) -> f32 {
+        self.events
+            .iter()
+            .take_while(|event| event.time <= time)
+            .last()
+            .map(|event| event.probability)
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+
+    /// Probability halfway between open and close.
+    pub fn prob_midpoint(&self) -> f32 {
+        self.prob_at(self.open_time + (self.close_time - self.open_time) / 2)
+    }
+
+    pub fn prob_close(&self) -> f32 {
+        self.prob_at(self.close_time)
+    }
+
+    /// Probability `days` before close, clamped to the open time.
+    pub fn prob_days_before_close(&self, days: f32) -> f32 {
+        let offset = TimeDelta::seconds((days * SECS_PER_DAY) as i64);
+        self.prob_at((self.close_time - offset).max(self.open_time))
+    }
+
+    /// Time-weighted average probability between open and close.
+    pub fn prob_tma(&self) -> f32 {
+        let lifetime = (self.close_time - self.open_time).num_seconds();
+        if lifetime <= 0 {
+            return self.prob_close();
+        }
+        let mut weighted = 0.0;
+        let mut previous_time = self.open_time;
+        let mut previous_probability = DEFAULT_OPENING_PROB;
+        for event in &self.events {
+            if event.time > self.close_time {
+                break;
+            }
+            if event.time > previous_time {
+                weighted +=
+                    previous_probability as f64 * (event.time - previous_time).num_seconds() as f64;
+                previous_time = event.time;
+            }
+            previous_probability = event.probability;
+        }
+        weighted +=
+            previous_probability as f64 * (self.close_time - previous_time).num_seconds() as f64;
+        (weighted / lifetime as f64) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(n: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(n * SECS_PER_DAY as i64, 0).unwrap()
+    }
+
+    #[test]
+    fn test_empty_history() {
+        let calculator = Calculator::new(vec![], day(0), day(10));
+
+        assert_eq!(calculator.prob_midpoint(), DEFAULT_OPENING_PROB);
+        assert_eq!(calculator.prob_close(), DEFAULT_OPENING_PROB);
+        assert_eq!(calculator.prob_tma(), DEFAULT_OPENING_PROB);
+        assert_eq!(calculator.prob_days_before_close(3.0), DEFAULT_OPENING_PROB);
+    }
+
+    #[test]
+    fn test_time_weighted_average() {
+        // 0.5 for 2 days, 0.9 for 4 days, 0.1 for 4 days.
+        let events = vec![Update::new(day(6), 0.1), Update::new(day(2), 0.9)];
+        let calculator = Calculator::new(events, day(0), day(10));
+
+        assert!((calculator.prob_tma() - 0.5).abs() < 1e-6);
+        assert_eq!(calculator.prob_midpoint(), 0.9);
+        assert_eq!(calculator.prob_close(), 0.1);
+    }
+
+    #[test]
+    fn test_updates_after_close_are_ignored() {
+        let events = vec![Update::new(day(5), 0.8), Update::new(day(12), 0.0)];
+        let calculator = Calculator::new(events, day(0), day(10));
+
+        assert_eq!(calculator.prob_close(), 0.8);
+        assert!((calculator.prob_tma() - 0.65).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_prob_days_before_close() {
+        let events = vec![Update::new(day(2), 0.3), Update::new(day(8), 0.7)];
+        let calculator = Calculator::new(events, day(0), day(10));
+
+        assert_eq!(calculator.prob_days_before_close(1.0), 0.7);
+        assert_eq!(calculator.prob_days_before_close(5.0), 0.3);
+        // Clamped to open, before the first update.
+        assert_eq!(
+            calculator.prob_days_before_close(30.0),
+            DEFAULT_OPENING_PROB
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const LOG_SCORE_FLOOR: f64 = 1e-4;
+
+/// `(float)% for outcome of (str)` as the superforecaster prompt asks for it,
+/// or `for answer (str)` on multiple-choice questions.
+static LEGACY_FORMAT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)likelihood\D*?(\d+(?:\.\d+)?)\s*%\s*for\s+(?:outcome\s+of|answer)\s+([^;\n]+)")
+        .expect("valid legacy forecast pattern")
+});
+
+/// A probability an executor gave an outcome, with what it was based on.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Forecast {
+    pub question: String,
+    pub platform: String,
+    /// Set when the question is a market on `platform`.
+    pub platform_id: Option<String>,
+    pub outcome: String,
+    pub probability: f64,
+    pub rationale: String,
+    pub model: String,
+    /// Name of the prompt template, the same across questions.
+    #[serde(default)]
+    pub prompt_version: String,
+    /// Hex SHA-256 of the instructions and prompt as sent.
+    pub prompt_hash: String,
+    pub news_sources: Vec<String>,
+    pub timestamp: DateTime<Utc>,
+    /// The market's probability of `outcome` when the forecast was made.
+    #[serde(default)]
+    pub market_probability: Option<f64>,
+    #[serde(default)]
+    pub category: String,
+    /// Standard deviation of the member probabilities of an ensemble forecast.
+    #[serde(default)]
+    pub dispersion: Option<f64>,
+}
+
+/// How a forecast did once its outcome settled. Relative scores are positive when
+/// the forecast beat the market price it was made against.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Score {
+    pub brier: f64,
+    pub log: f64,
+    pub relative_brier: Option<f64>,
+    pub relative_log: Option<f64>,
+}
+
+impl Forecast {
+    /// Scores the forecast against `outcome`, 1 if it happened and 0 if not.
+    pub fn score(&self, outcome: f64) -> Score {
+        let brier = brier_score(self.probability, outcome);
+        let log = log_score(self.probability, outcome);
+        Score {
+            brier,
+            log,
+            relative_brier: self
+                .market_probability
+                .map(|market| brier_score(market, outcome) - brier),
+            relative_log: self
+                .market_probability
+                .map(|market| log - log_score(market, outcome)),
+        }
+    }
+
+    /// A yes forecast on the Manifold market of the test fixtures, for tests to
+    /// adjust with struct update syntax.
+    #[cfg(test)]
+    pub fn fixture(probability: f64) -> Self {
+        Self {
+            question: "Will it happen?".to_string(),
+            platform: "manifold".to_string(),
+            platform_id: Some("9Ccsjc0fmbIb9g50p7SB".to_string()),
+            outcome: "yes".to_string(),
+            probability,
+            rationale: String::new(),
+            model: "scripted".to_string(),
+            prompt_version: "superforecaster".to_string(),
+            prompt_hash: prompt_hash("system", "prompt"),
+            news_sources: Vec::new(),
+            timestamp: Utc::now(),
+            market_probability: None,
+            category: String::new(),
+            dispersion: None,
+        }
+    }
+}
+
+/// Squared error of `probability`; 0 is perfect, lower is better.
+pub fn brier_score(probability: f64, outcome: f64) -> f64 {
+    (probability - outcome).powi(2)
+}
+
+/// Log likelihood of `outcome` under `probability`; 0 is perfect, higher is better.
+/// Probabilities are kept off 0 and 1 so a confident miss costs a lot but not everything.
+pub fn log_score(probability: f64, outcome: f64) -> f64 {
+    let probability = probability.clamp(LOG_SCORE_FLOOR, 1.0 - LOG_SCORE_FLOOR);
+    outcome * probability.ln() + (1.0 - outcome) * (1.0 - probability).ln()
+}
+
+/// The part of a `Forecast` the model writes.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ForecastAnswer {
+    pub outcome: String,
+    pub probability: f64,
+    pub rationale: String,
+}
+
+impl ForecastAnswer {
+    /// JSON schema of the answer, for structured output.
+    pub fn schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "outcome": { "type": "string" },
+                "probability": {
+                    "type": "number",
+                    "description": "Probability of the outcome, between 0 and 1",
+                },
+                "rationale": { "type": "string" },
+            },
+            "required": ["outcome", "probability", "rationale"],
+            "additionalProperties": false,
+        })
+    }
+
+    /// Reads a reply in the legacy text format. The likeliest outcome wins when
+    /// there is one line per answer.
+    pub fn from_legacy(reply: &str) -> Option<Self> {
+        let (probability, outcome) = LEGACY_FORMAT
+            .captures_iter(reply)
+            .filter_map(|captures| {
+                let percent = captures[1].parse::<f64>().ok()?;
+                let outcome = captures[2].trim().trim_end_matches('.').trim();
+                Some((percent / 100.0, outcome.to_string()))
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0))?;
+        Some(Self {
+            outcome,
+            probability,
+            rationale: reply.trim().to_string(),
+        })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (0.0..=1.0).contains(&self.probability) && !self.outcome.is_empty()
+    }
+}
+
+pub fn prompt_hash(instructions: &str, prompt: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(instructions.as_bytes());
+    hasher.update([0]);
+    hasher.update(prompt.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forecast_answer_from_legacy() {
+        let reply = "Base rates favour it.
+        The question Nvidia over Apple?; has a likelihood 37.5% for outcome of yes.";
+        let answer = ForecastAnswer::from_legacy(reply).unwrap();
+        assert_eq!(answer.outcome, "yes");
+        assert_eq!(answer.probability, 0.375);
+        assert!(answer.rationale.starts_with("Base rates"));
+
+        let reply = "The question Who wins?; has a likelihood 30% for answer Conservative.
+        The question Who wins?; has a likelihood 70% for answer Labour.";
+        let answer = ForecastAnswer::from_legacy(reply).unwrap();
+        assert_eq!(answer.outcome, "Labour");
+        assert_eq!(answer.probability, 0.7);
+        assert!(ForecastAnswer::from_legacy("I cannot say.").is_none());
+    }
+
+    #[test]
+    fn test_score() {
+        assert!((brier_score(0.8, 1.0) - 0.04).abs() < 1e-12);
+        assert!((log_score(0.8, 0.0) - 0.2f64.ln()).abs() < 1e-12);
+        assert!((log_score(1.0, 0.0) - LOG_SCORE_FLOOR.ln()).abs() < 1e-6);
+
+        let forecast = Forecast {
+            market_probability: Some(0.6),
+            ..Forecast::fixture(0.9)
+        };
+        let score = forecast.score(1.0);
+        assert!((score.relative_brier.unwrap() - (0.16 - 0.01)).abs() < 1e-12);
+        assert!(score.relative_log.unwrap() > 0.0);
+        assert!(forecast.score(0.0).relative_brier.unwrap() < 0.0);
+    }
+
+    #[test]
+    fn test_prompt_hash() {
+        assert_eq!(prompt_hash("a", "b"), prompt_hash("a", "b"));
+        assert_ne!(prompt_hash("a", "b"), prompt_hash("ab", ""));
+        assert_ne!(prompt_hash("a", "b"), prompt_hash("a", "c"));
+        assert_eq!(prompt_hash("", "").len(), 64);
+    }
+}
@@ -1,8 +1,316 @@
-use serde::{Deserialize, Serialize};
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct GammaMarket {}
+use super::{parse_timestamp, Market, Result as MarketResult, Update, UNRESOLVED};
+use crate::db::error::Error;
+use chrono::{DateTime, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct GammaEvent {}
+/// A market from Gamma `/markets`, or nested in an event.
+///
+/// Gamma sends list fields such as `outcomes` as JSON encoded strings and
+/// amounts sometimes as strings, sometimes as numbers; both are accepted.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaMarket {
+    pub id: String,
+    pub question: Option<String>,
+    pub condition_id: Option<String>,
+    pub question_id: Option<String>,
+    pub slug: Option<String>,
+    pub description: Option<String>,
+    pub category: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    #[serde(default, deserialize_with = "string_list")]
+    pub outcomes: Vec<String>,
+    #[serde(default, deserialize_with = "number_list")]
+    pub outcome_prices: Vec<f64>,
+    #[serde(default, deserialize_with = "string_list")]
+    pub clob_token_ids: Vec<String>,
+    #[serde(default, deserialize_with = "number")]
+    pub volume_num: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub liquidity_num: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub volume24hr: Option<f64>,
+    pub active: Option<bool>,
+    pub closed: Option<bool>,
+    pub archived: Option<bool>,
+    pub accepting_orders: Option<bool>,
+    pub neg_risk: Option<bool>,
+    /// Shared by every market of one neg-risk event.
+    pub neg_risk_market_id: Option<String>,
+    /// Short label of this market within its event, e.g. a candidate name.
+    pub group_item_title: Option<String>,
+    #[serde(default, deserialize_with = "number")]
+    pub spread: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub best_bid: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub best_ask: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub last_trade_price: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<GammaTag>,
+}
 
-pub struct GammaPosition {}
+/// A Gamma `/events` entry grouping related markets.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct GammaEvent {
+    pub id: String,
+    pub ticker: Option<String>,
+    pub slug: Option<String>,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub active: Option<bool>,
+    pub closed: Option<bool>,
+    pub archived: Option<bool>,
+    /// Outcomes of a neg-risk event are mutually exclusive; their Yes prices sum to one.
+    pub neg_risk: Option<bool>,
+    pub neg_risk_market_id: Option<String>,
+    #[serde(default, deserialize_with = "number")]
+    pub volume: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub liquidity: Option<f64>,
+    #[serde(default, deserialize_with = "number")]
+    pub volume24hr: Option<f64>,
+    #[serde(default)]
+    pub tags: Vec<GammaTag>,
+    #[serde(default)]
+    pub markets: Vec<GammaMarket>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GammaTag {
+    pub id: String,
+    pub label: Option<String>,
+    pub slug: Option<String>,
+}
+
+/// One priced outcome of a market, tradable on the CLOB as `token_id`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct GammaPosition {
+    pub outcome: String,
+    pub token_id: Option<String>,
+    pub price: Option<f64>,
+}
+
+impl GammaMarket {
+    pub fn positions(&self) -> Vec<GammaPosition> {
+        self.outcomes
+            .iter()
+            .enumerate()
+            .map(|(index, outcome)| GammaPosition {
+                outcome: outcome.clone(),
+                token_id: self.clob_token_ids.get(index).cloned(),
+                price: self.outcome_prices.get(index).copied(),
+            })
+            .collect()
+    }
+
+    /// Price of the "Yes" outcome, or of the first outcome when none is named so.
+    pub fn yes_price(&self) -> Option<f64> {
+        let index = self
+            .outcomes
+            .iter()
+            .position(|outcome| outcome.eq_ignore_ascii_case("yes"))
+            .unwrap_or(0);
+        self.outcome_prices.get(index).copied()
+    }
+
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        self.end_date
+            .as_deref()
+            .and_then(|end_date| parse_timestamp(end_date).ok())
+    }
+}
+
+impl GammaEvent {
+    pub fn is_neg_risk(&self) -> bool {
+        self.neg_risk.unwrap_or(false)
+    }
+
+    /// Sum of the markets' Yes prices; close to one for a well-priced neg-risk event.
+    pub fn yes_price_sum(&self) -> f64 {
+        self.markets.iter().filter_map(GammaMarket::yes_price).sum()
+    }
+}
+
+/// Markets by `neg_risk_market_id`, so the mutually exclusive outcomes of an
+/// event end up together. Markets outside a neg-risk group are left out.
+pub fn group_neg_risk(markets: &[GammaMarket]) -> BTreeMap<String, Vec<GammaMarket>> {
+    let mut groups: BTreeMap<String, Vec<GammaMarket>> = BTreeMap::new();
+    for market in markets {
+        if !market.neg_risk.unwrap_or(false) {
+            continue;
+        }
+        if let Some(group) = &market.neg_risk_market_id {
+            groups
+                .entry(group.clone())
+                .or_default()
+                .push(market.clone());
+        }
+    }
+    groups
+}
+
+/// Gamma markets are Polymarket markets, keyed by condition id like the CLOB listing.
+impl Market for GammaMarket {
+    fn title(&self) -> &str {
+        self.question.as_deref().unwrap_or("")
+    }
+    fn platform(&self) -> &str {
+        "polymarket"
+    }
+    fn platform_id(&self) -> String {
+        self.condition_id.clone().unwrap_or_else(|| self.id.clone())
+    }
+    fn open_time(&self) -> MarketResult<DateTime<Utc>> {
+        let start_date = self.start_date.as_ref().ok_or(Error::MissingField {
+            platform: "gamma",
+            field: "startDate",
+        })?;
+        Ok(parse_timestamp(start_date)?)
+    }
+    fn close_time(&self) -> MarketResult<DateTime<Utc>> {
+        let end_date = self.end_date.as_ref().ok_or(Error::MissingField {
+            platform: "gamma",
+            field: "endDate",
+        })?;
+        Ok(parse_timestamp(end_date)?)
+    }
+    fn volume_usd(&self) -> f32 {
+        self.volume_num.unwrap_or_default() as f32
+    }
+    // Gamma does not expose a unique trader count.
+    fn num_traders(&self) -> i32 {
+        0
+    }
+    fn category(&self) -> &str {
+        self.category
+            .as_deref()
+            .or_else(|| self.tags.first().and_then(|tag| tag.label.as_deref()))
+            .unwrap_or("")
+    }
+    /// Closed markets settle their outcome prices at 0 or 1.
+    fn resolution(&self) -> f32 {
+        if !self.closed.unwrap_or(false) {
+            return UNRESOLVED;
+        }
+        match self.yes_price() {
+            Some(price) if price >= 0.99 => 1.0,
+            Some(price) if price <= 0.01 => 0.0,
+            _ => UNRESOLVED,
+        }
+    }
+    // Only the current price is listed; the history lives on the CLOB.
+    fn events(&self) -> Vec<Update> {
+        match (self.open_time(), self.yes_price()) {
+            (Ok(open_time), Some(price)) => vec![Update::new(open_time, price as f32)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Encoded<T> {
+    Json(String),
+    Plain(T),
+}
+
+fn string_list<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Encoded<Vec<String>>>::deserialize(deserializer)? {
+        Some(Encoded::Json(text)) => {
+            serde_json::from_str(&text).map_err(|err| de::Error::custom(err.to_string()))
+        }
+        Some(Encoded::Plain(list)) => Ok(list),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn number_list<'de, D>(deserializer: D) -> std::result::Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    string_list(deserializer)?
+        .iter()
+        .map(|value| {
+            value
+                .parse::<f64>()
+                .map_err(|err| de::Error::custom(err.to_string()))
+        })
+        .collect()
+}
+
+fn number<'de, D>(deserializer: D) -> std::result::Result<Option<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Encoded<f64>>::deserialize(deserializer)? {
+        Some(Encoded::Json(text)) => text
+            .parse::<f64>()
+            .map(Some)
+            .map_err(|err| de::Error::custom(err.to_string())),
+        Some(Encoded::Plain(value)) => Ok(Some(value)),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMMA_MARKET: &str = include_str!("../../../tests/fixtures/polymarket_market.json");
+    const GAMMA_EVENTS: &str = include_str!("../../../tests/fixtures/polymarket_events.json");
+
+    #[test]
+    fn test_parse_market() {
+        let market: GammaMarket = serde_json::from_str(GAMMA_MARKET).unwrap();
+        assert_eq!(market.outcomes, vec!["Yes", "No"]);
+        assert_eq!(market.outcome_prices, vec![0.62, 0.38]);
+        assert_eq!(market.clob_token_ids.len(), 2);
+        assert_eq!(market.volume_num, Some(1250342.12));
+        assert_eq!(market.yes_price(), Some(0.62));
+        assert_eq!(market.positions()[1].outcome, "No");
+        assert_eq!(market.prob_latest(), 0.62);
+        assert_eq!(market.resolution(), UNRESOLVED);
+        assert_eq!(
+            market.platform_id(),
+            "0x9c1a953fe92c8357f1b646ba25d983aa83e90c525992db14fb726fa895cb5763"
+        );
+    }
+
+    #[test]
+    fn test_neg_risk_groups() {
+        let events: Vec<GammaEvent> = serde_json::from_str(GAMMA_EVENTS).unwrap();
+        assert!(!events[0].is_neg_risk());
+        assert_eq!(events[0].markets.len(), 1);
+
+        let market: GammaMarket = serde_json::from_str(GAMMA_MARKET).unwrap();
+        let candidate = |id: &str, price: &str| GammaMarket {
+            id: id.to_string(),
+            neg_risk: Some(true),
+            neg_risk_market_id: Some("0xgroup".to_string()),
+            outcome_prices: vec![price.parse().unwrap(), 0.0],
+            ..market.clone()
+        };
+        let markets = [
+            candidate("1", "0.7"),
+            candidate("2", "0.25"),
+            market.clone(),
+        ];
+        let groups = group_neg_risk(&markets);
+        assert_eq!(groups.len(), 1);
+        let event = GammaEvent {
+            markets: groups["0xgroup"].clone(),
+            ..events[0].clone()
+        };
+        assert!((event.yes_price_sum() - 0.95).abs() < 1e-9);
+    }
+}
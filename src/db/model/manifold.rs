@@ -1,3 +1,9 @@
+use super::{
+    binary_settlement, timestamp_from_millis, Market, MarketKind, Result as MarketResult, Update,
+    DEFAULT_OPENING_PROB, MANA_PER_USD, UNRESOLVED,
+};
+use crate::db::error::Error;
+use chrono::{DateTime, Utc};
 use core::fmt;
 use serde::Deserializer;
 use serde::{Deserialize, Serialize};
@@ -34,23 +40,242 @@ impl FromStr for OutcomeType {
 pub struct ManifoldMarket {
     pub question: String,
     pub id: String,
-    //    #[serde(with = "ts_milliseconds")]
-    // pub createdTime: Option<u64>,
-    // // #[serde(with = "ts_milliseconds_option")]
-    // // #[serde(default)]
-    // pub closeTime: Option<u64>,
-    // // #[serde(with = "ts_milliseconds_option")]
-    // // #[serde(default)]
-    // pub resolutionTime: Option<u64>,
-    // pub totalLiquidity: Option<f64>,
+    pub createdTime: Option<i64>,
+    pub closeTime: Option<i64>,
+    pub resolutionTime: Option<i64>,
+    pub totalLiquidity: Option<f64>,
+    pub volume: Option<f64>,
+    pub uniqueBettorCount: Option<i32>,
+    pub isResolved: Option<bool>,
+    pub resolution: Option<String>,
+    pub resolutionProbability: Option<f64>,
+    pub groupSlugs: Option<Vec<String>>,
     pub outcomeType: Option<OutcomeType>,
     pub pool: Option<BetPool>,
     pub probability: Option<f64>,
+    /// Answers of a multiple-choice market, each traded as its own YES/NO pool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub answers: Vec<ManifoldAnswer>,
+    /// Range of a numeric market; `probability` is the position of `value` in it.
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub isLogScale: Option<bool>,
+    pub value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bets: Vec<ManifoldBet>,
     // pub positions: Option<Vec<Position>>,
 }
 
+/// One answer of a multiple-choice market.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct ManifoldAnswer {
+    pub id: String,
+    pub text: String,
+    pub index: Option<i64>,
+    pub probability: Option<f64>,
+    pub poolYes: Option<f64>,
+    pub poolNo: Option<f64>,
+    /// `YES`, `NO`, `MKT` or `CANCEL`, on markets that resolve answers one by one.
+    pub resolution: Option<String>,
+    pub resolutionProbability: Option<f64>,
+}
+
+impl ManifoldAnswer {
+    pub fn pool(&self) -> Option<BetPool> {
+        Some(BetPool {
+            NO: self.poolNo?,
+            YES: self.poolYes?,
+        })
+    }
+}
+
+impl ManifoldMarket {
+    /// The most likely answer of a multiple-choice market.
+    pub fn leading_answer(&self) -> Option<&ManifoldAnswer> {
+        self.answers
+            .iter()
+            .filter_map(|answer| Some((answer, answer.probability.filter(|p| p.is_finite())?)))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(answer, _)| answer)
+    }
+
+    /// What `answer` settled at: 1 if it won, 0 if it lost and its resolution
+    /// probability on a split (`MKT`) resolution. `None` while it is unresolved or
+    /// when it was cancelled.
+    pub fn answer_resolution(&self, answer: &ManifoldAnswer) -> Option<f64> {
+        match answer.resolution.as_deref() {
+            Some("YES") => return Some(1.0),
+            Some("NO") => return Some(0.0),
+            Some("MKT") => return answer.resolutionProbability,
+            Some(_) => return None,
+            None => {}
+        }
+        match self.resolution.as_deref()? {
+            "CANCEL" => None,
+            "MKT" => answer.resolutionProbability,
+            winner => Some(if winner == answer.id { 1.0 } else { 0.0 }),
+        }
+    }
+
+    /// Position of `value` between `min` and `max`, on a log scale when the market is.
+    pub fn scaled_value(&self) -> Option<f64> {
+        let (min, max, value) = (self.min?, self.max?, self.value?);
+        if max <= min {
+            return None;
+        }
+        let scaled = if self.isLogScale.unwrap_or(false) {
+            (value - min + 1.0).ln() / (max - min + 1.0).ln()
+        } else {
+            (value - min) / (max - min)
+        };
+        Some(scaled.clamp(0.0, 1.0))
+    }
+
+    /// Current probability of the YES side, the leading answer, or the scaled
+    /// value, matching what `events()` tracks for each kind of market.
+    pub fn current_probability(&self) -> Option<f64> {
+        match self.kind() {
+            MarketKind::Binary => self.probability,
+            MarketKind::MultipleChoice => self.leading_answer()?.probability,
+            MarketKind::Numeric => self.probability.or_else(|| self.scaled_value()),
+        }
+    }
+}
+
+/// A bet, limit order or sale; `POST /bet` answers with the same shape under `betId`.
+///
+/// `amount` and `shares` are what actually filled, negative for a sale. A limit
+/// order asked for `orderAmount` and rests until filled, cancelled or `expiresAt`.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct ManifoldBet {
+    #[serde(alias = "betId")]
+    pub id: String,
+    pub userId: Option<String>,
+    pub contractId: String,
+    /// Answer of a multiple-choice market.
+    pub answerId: Option<String>,
+    pub createdTime: i64,
+    pub amount: f64,
+    pub orderAmount: Option<f64>,
+    pub shares: f64,
+    pub outcome: String,
+    pub probBefore: f64,
+    pub probAfter: f64,
+    pub limitProb: Option<f64>,
+    pub expiresAt: Option<i64>,
+    #[serde(default)]
+    pub isFilled: bool,
+    #[serde(default)]
+    pub isCancelled: bool,
+    #[serde(default)]
+    pub isRedemption: bool,
+    #[serde(default)]
+    pub fills: Vec<ManifoldFill>,
+}
+
+/// One match of a bet, against the AMM when `matchedBetId` is `None`.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct ManifoldFill {
+    pub amount: f64,
+    pub shares: f64,
+    pub timestamp: i64,
+    pub matchedBetId: Option<String>,
+}
+
+impl ManifoldBet {
+    /// Mana filled so far, from the fills when the API lists them.
+    pub fn filled_amount(&self) -> f64 {
+        if self.fills.is_empty() {
+            self.amount
+        } else {
+            self.fills.iter().map(|fill| fill.amount).sum()
+        }
+    }
+
+    /// Mana still resting in the book; zero once filled or cancelled.
+    pub fn unfilled_amount(&self) -> f64 {
+        if self.isFilled || self.isCancelled {
+            return 0.0;
+        }
+        (self.orderAmount.unwrap_or(self.amount) - self.filled_amount()).max(0.0)
+    }
+
+    /// Average probability paid per share.
+    pub fn average_price(&self) -> Option<f64> {
+        (self.shares != 0.0).then(|| self.amount / self.shares)
+    }
+}
+
 #[derive(Deserialize, Debug, Serialize, Clone)]
 pub struct ManifoldEvent {}
+
+/// A group, shown as a topic on the site; markets list theirs in `groupSlugs`.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct ManifoldGroup {
+    pub id: String,
+    pub slug: String,
+    pub name: String,
+    pub totalMembers: Option<i64>,
+    pub privacyStatus: Option<String>,
+    pub createdTime: Option<i64>,
+    pub importanceScore: Option<f64>,
+}
+
+/// Free-text tags to groups, matched on slug or name regardless of case and separators.
+#[derive(Debug, Clone, Default)]
+pub struct GroupIndex {
+    groups: HashMap<String, ManifoldGroup>,
+    /// Slug by normalized tag.
+    keys: HashMap<String, String>,
+}
+
+impl GroupIndex {
+    pub fn new(groups: Vec<ManifoldGroup>) -> Self {
+        let mut index = Self::default();
+        for group in &groups {
+            index
+                .keys
+                .insert(group_key(&group.name), group.slug.clone());
+        }
+        // A slug wins over another group's name.
+        for group in groups {
+            index
+                .keys
+                .insert(group_key(&group.slug), group.slug.clone());
+            index.groups.insert(group.slug.clone(), group);
+        }
+        index
+    }
+
+    /// Points `tag` at the group `slug`, for tags that match no group by name.
+    pub fn alias(&mut self, tag: &str, slug: &str) {
+        self.keys.insert(group_key(tag), slug.to_string());
+    }
+
+    pub fn resolve(&self, tag: &str) -> Option<&ManifoldGroup> {
+        self.keys
+            .get(&group_key(tag))
+            .and_then(|slug| self.groups.get(slug))
+    }
+
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+/// Lowercase with runs of anything but letters and digits collapsed to `-`,
+/// so `US Economics`, `us_economics` and `US-economics` agree.
+pub fn group_key(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
 // #[derive(Deserialize, Debug, Serialize)]
 // pub struct Indicators {
 //     num_forecasts: i32,
@@ -119,6 +344,11 @@ impl fmt::Display for ManifoldMarket {
             } else {
                 write!(f, "{}...YES: {} Very unlikely", question, yes_share)
             }
+        } else if let Some(answer) = self.leading_answer() {
+            let probability = answer.probability.unwrap_or_default();
+            write!(f, "{}...{}: {}", self.question, answer.text, probability)
+        } else if let Some(value) = self.value {
+            write!(f, "{}...{}", self.question, value)
         } else {
             let question = self.question.to_string();
             write!(f, "{}...", question,)
@@ -137,31 +367,193 @@ impl From<serde_json::Value> for ManifoldMarket {
     fn from(value: serde_json::Value) -> Self {
         let id = value["id"].to_string();
         let question = value["question"].to_string();
-        // let createdTime = value["createdTime"]
-        //     .to_string()
-        //     .parse::<u64>()
-        //     .expect("Failed to parse created time");
-        // let closeTime = value["closeTime"]
-        //     .to_string()
-        //     .parse::<u64>()
-        //     .unwrap();
-        // let volume = value["volume"].as_f64().unwrap();
+        let createdTime = value["createdTime"].as_i64();
+        let closeTime = value["closeTime"].as_i64();
+        let resolutionTime = value["resolutionTime"].as_i64();
+        let totalLiquidity = value["totalLiquidity"].as_f64();
+        let volume = value["volume"].as_f64();
+        let uniqueBettorCount = value["uniqueBettorCount"]
+            .as_i64()
+            .map(|count| count as i32);
+        let isResolved = value["isResolved"].as_bool();
+        let resolution = value["resolution"].as_str().map(str::to_string);
+        let resolutionProbability = value["resolutionProbability"].as_f64();
+        let groupSlugs = value["groupSlugs"].as_array().map(|slugs| {
+            slugs
+                .iter()
+                .filter_map(|slug| slug.as_str().map(str::to_string))
+                .collect()
+        });
         let outcomeType = value["outcomeType"]
-            .to_string()
-            .parse::<OutcomeType>()
-            .unwrap();
-        let pool = BetPool::from(value["pool"].clone());
-        let probability = value["probability"].as_f64().unwrap();
+            .as_str()
+            .and_then(|outcome_type| outcome_type.parse::<OutcomeType>().ok());
+        // Only binary and pseudo-numeric markets have a single pool.
+        let pool = value["pool"]
+            .is_object()
+            .then(|| BetPool::from(value["pool"].clone()));
+        let probability = value["probability"].as_f64();
+        let answers = serde_json::from_value(value["answers"].clone()).unwrap_or_default();
+        let min = value["min"].as_f64();
+        let max = value["max"].as_f64();
+        let isLogScale = value["isLogScale"].as_bool();
+        let market_value = value["value"].as_f64();
         // let positions = value["positions"].as_array().unwrap();
         ManifoldMarket {
             id,
             question,
-            // createdTime: Some(createdTime),
-            // resolutionTime: Some(closeTime),
-            // closeTime: Some(closeTime),
-            probability: Some(probability),
-            outcomeType: Some(outcomeType),
-            pool: Some(pool),
+            createdTime,
+            closeTime,
+            resolutionTime,
+            totalLiquidity,
+            volume,
+            uniqueBettorCount,
+            isResolved,
+            resolution,
+            resolutionProbability,
+            groupSlugs,
+            probability,
+            outcomeType,
+            pool,
+            answers,
+            min,
+            max,
+            isLogScale,
+            value: market_value,
+            bets: Vec::new(),
+        }
+    }
+}
+
+impl Market for ManifoldMarket {
+    fn title(&self) -> &str {
+        &self.question
+    }
+    fn platform(&self) -> &str {
+        "manifold"
+    }
+    fn platform_id(&self) -> String {
+        self.id.clone()
+    }
+    fn open_time(&self) -> MarketResult<DateTime<Utc>> {
+        let created_time = self.createdTime.ok_or(Error::MissingField {
+            platform: "manifold",
+            field: "createdTime",
+        })?;
+        Ok(timestamp_from_millis(created_time)?)
+    }
+    fn close_time(&self) -> MarketResult<DateTime<Utc>> {
+        let close_time = self
+            .closeTime
+            .or(self.resolutionTime)
+            .ok_or(Error::MissingField {
+                platform: "manifold",
+                field: "closeTime",
+            })?;
+        Ok(timestamp_from_millis(close_time)?)
+    }
+    fn volume_usd(&self) -> f32 {
+        self.volume.unwrap_or_default() as f32 / MANA_PER_USD
+    }
+    fn num_traders(&self) -> i32 {
+        self.uniqueBettorCount.unwrap_or_default()
+    }
+    fn category(&self) -> &str {
+        self.groupSlugs
+            .as_ref()
+            .and_then(|slugs| slugs.first())
+            .map(String::as_str)
+            .unwrap_or("")
+    }
+    fn kind(&self) -> MarketKind {
+        match self.outcomeType {
+            Some(OutcomeType::MULTIPLE_CHOICE) => MarketKind::MultipleChoice,
+            Some(OutcomeType::PSEUDO_NUMERIC) | Some(OutcomeType::NUMBER) => MarketKind::Numeric,
+            _ => MarketKind::Binary,
+        }
+    }
+    /// A multiple-choice market resolves as the answer `events()` follows did.
+    fn resolution(&self) -> f32 {
+        if self.kind() == MarketKind::MultipleChoice {
+            return self
+                .leading_answer()
+                .and_then(|answer| self.answer_resolution(answer))
+                .map_or(UNRESOLVED, |resolution| resolution as f32);
+        }
+        match self.resolution.as_deref() {
+            Some("YES") => 1.0,
+            Some("NO") => 0.0,
+            Some("MKT") => self
+                .resolutionProbability
+                .map(|probability| probability as f32)
+                .unwrap_or(UNRESOLVED),
+            _ => UNRESOLVED,
+        }
+    }
+    /// Multiple-choice outcomes are answer texts; the rest settle as binary markets.
+    fn settlement(&self, outcome: &str) -> Option<f64> {
+        if self.isResolved != Some(true) {
+            return None;
+        }
+        if self.kind() == MarketKind::MultipleChoice {
+            let answer = self
+                .answers
+                .iter()
+                .find(|answer| answer.text.eq_ignore_ascii_case(outcome))?;
+            return self.answer_resolution(answer);
+        }
+        binary_settlement(self.resolution(), outcome)
+    }
+    fn is_final(&self) -> bool {
+        self.isResolved == Some(true)
+    }
+    fn outcome_probability(&self, outcome: &str) -> Option<f64> {
+        match self.kind() {
+            MarketKind::MultipleChoice => {
+                self.answers
+                    .iter()
+                    .find(|answer| answer.text.eq_ignore_ascii_case(outcome))?
+                    .probability
+            }
+            MarketKind::Binary => {
+                let yes = self.probability?;
+                if outcome.eq_ignore_ascii_case("no") {
+                    Some(1.0 - yes)
+                } else {
+                    Some(yes)
+                }
+            }
+            MarketKind::Numeric => None,
+        }
+    }
+    fn events(&self) -> Vec<Update> {
+        let Ok(open_time) = self.open_time() else {
+            return Vec::new();
+        };
+        // Bets on a multiple-choice market move one answer each; follow the leader.
+        let answer_id = self.leading_answer().map(|answer| answer.id.as_str());
+        let mut bets: Vec<&ManifoldBet> = self
+            .bets
+            .iter()
+            .filter(|bet| answer_id.is_none() || bet.answerId.as_deref() == answer_id)
+            .collect();
+        bets.sort_by_key(|bet| bet.createdTime);
+        match bets.first() {
+            Some(first) => {
+                let mut events = vec![Update::new(open_time, first.probBefore as f32)];
+                events.extend(bets.iter().filter_map(|bet| {
+                    timestamp_from_millis(bet.createdTime)
+                        .ok()
+                        .map(|time| Update::new(time, bet.probAfter as f32))
+                }));
+                events
+            }
+            // Without the bet history the current probability is the only observation.
+            None => vec![Update::new(
+                open_time,
+                self.current_probability()
+                    .map(|probability| probability as f32)
+                    .unwrap_or(DEFAULT_OPENING_PROB),
+            )],
         }
     }
 }
@@ -1,6 +1,18 @@
+use super::{
+    binary_settlement, parse_timestamp, Market, Result as MarketResult, Update, UNRESOLVED,
+};
+use crate::db::error::Error;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
+
+/// Points a continuous forecast's CDF is sampled at, evenly spaced over the range.
+pub const CDF_SIZE: usize = 201;
+/// Probability left outside an open bound by `Question::cdf_from_percentiles`.
+const OPEN_BOUND_MASS: f64 = 0.01;
+/// Share of a uniform CDF mixed in, so every step of the CDF rises.
+const UNIFORM_WEIGHT: f64 = 0.02;
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
 pub struct MetaculusResponse {
     pub results: Vec<MetaculusMarket>,
@@ -17,15 +29,29 @@ pub struct MetaculusMarket {
     pub scheduled_close_time: Option<String>,
     pub scheduled_resolve_time: Option<String>,
 
-    // question: Question,
+    pub question: Option<Question>,
     pub nr_forecasters: u32,
     pub forecasts_count: u32,
     pub status: Option<Status>,
     pub forecast_type: Option<Type>,
+    pub projects: Option<Projects>,
     // type: Type,
     //
 }
 
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct Projects {
+    #[serde(default)]
+    pub category: Vec<Project>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct Project {
+    pub id: u32,
+    pub name: String,
+    pub slug: Option<String>,
+}
+
 pub struct MetaculusPosition {}
 
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
@@ -55,12 +81,226 @@ impl FromStr for Status {
 
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
 pub struct Question {
-    aggregations: Aggregations,
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub question_type: Option<Type>,
+    pub resolution: Option<String>,
+    pub aggregations: Option<Aggregations>,
+    /// Range of a numeric or date question; dates are unix seconds.
+    pub scaling: Option<Scaling>,
+    /// Whether the CDF leaves probability mass below `range_min` / above `range_max`.
+    pub open_lower_bound: Option<bool>,
+    pub open_upper_bound: Option<bool>,
+    /// Answers of a multiple-choice question.
+    pub options: Option<Vec<String>>,
 }
+
+/// Maps a location on the internal `[0, 1]` axis of a continuous question to its values.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct Scaling {
+    pub range_min: Option<f64>,
+    pub range_max: Option<f64>,
+    /// Set on log-scaled questions, where values grow geometrically away from it.
+    pub zero_point: Option<f64>,
+}
+
+impl Scaling {
+    pub fn unscale(&self, location: f64) -> Option<f64> {
+        let (min, max) = (self.range_min?, self.range_max?);
+        match self.zero_point {
+            Some(zero_point) => {
+                let ratio = (max - zero_point) / (min - zero_point);
+                Some(min + (max - min) * (ratio.powf(location) - 1.0) / (ratio - 1.0))
+            }
+            None => Some(min + (max - min) * location),
+        }
+    }
+
+    /// Location of `value` on the internal axis; the inverse of `unscale`.
+    pub fn scale(&self, value: f64) -> Option<f64> {
+        let (min, max) = (self.range_min?, self.range_max?);
+        match self.zero_point {
+            Some(zero_point) => {
+                let ratio = (max - zero_point) / (min - zero_point);
+                Some(((value - min) * (ratio - 1.0) / (max - min) + 1.0).ln() / ratio.ln())
+            }
+            None => Some((value - min) / (max - min)),
+        }
+    }
+}
+
+impl Question {
+    /// The recency-weighted community forecast.
+    pub fn community(&self) -> Option<&Aggregation> {
+        self.aggregations.as_ref()?.recency_weighted.as_ref()
+    }
+
+    /// Latest community probability of a binary question.
+    pub fn community_probability(&self) -> Option<f64> {
+        if self.question_type != Some(Type::binary) {
+            return None;
+        }
+        self.community()?
+            .current()?
+            .centers
+            .as_ref()?
+            .first()
+            .copied()
+    }
+
+    /// Latest community percentile `p` (0 to 1) of a numeric or date question,
+    /// in the question's units.
+    pub fn community_percentile(&self, p: f64) -> Option<f64> {
+        self.percentile(self.community()?.current()?, p)
+    }
+
+    /// Latest community percentile `p` of a date question.
+    pub fn community_date_percentile(&self, p: f64) -> Option<DateTime<Utc>> {
+        if self.question_type != Some(Type::date) {
+            return None;
+        }
+        DateTime::from_timestamp(self.community_percentile(p)? as i64, 0)
+    }
+
+    /// Community percentile `p` over time, oldest first.
+    pub fn percentile_history(&self, p: f64) -> Vec<(DateTime<Utc>, f64)> {
+        let Some(community) = self.community() else {
+            return Vec::new();
+        };
+        let mut history: Vec<(DateTime<Utc>, f64)> = community
+            .history
+            .iter()
+            .filter_map(|forecast| {
+                let time = DateTime::from_timestamp(forecast.start_time as i64, 0)?;
+                Some((time, self.percentile(forecast, p)?))
+            })
+            .collect();
+        history.sort_by_key(|(time, _)| *time);
+        history
+    }
+
+    /// A CDF of `CDF_SIZE` points through `(p, value)` percentiles, linear in
+    /// between. Open bounds keep a little mass outside the range; percentiles
+    /// beyond it fall in that mass. `None` when the percentiles contradict each
+    /// other or the question is not continuous.
+    pub fn cdf_from_percentiles(&self, percentiles: &[(f64, f64)]) -> Option<Vec<f64>> {
+        if !matches!(self.question_type, Some(Type::numeric) | Some(Type::date)) {
+            return None;
+        }
+        let scaling = self.scaling.as_ref()?;
+        let lower = if self.open_lower_bound.unwrap_or(false) {
+            OPEN_BOUND_MASS
+        } else {
+            0.0
+        };
+        let upper = if self.open_upper_bound.unwrap_or(false) {
+            1.0 - OPEN_BOUND_MASS
+        } else {
+            1.0
+        };
+        let mut anchors = vec![(0.0, lower), (1.0, upper)];
+        for &(p, value) in percentiles {
+            let location = scaling.scale(value)?;
+            if location > 0.0 && location < 1.0 {
+                anchors.push((location, p.clamp(lower, upper)));
+            }
+        }
+        anchors.sort_by(|a, b| a.0.total_cmp(&b.0));
+        if anchors.windows(2).any(|pair| pair[1].1 < pair[0].1) {
+            return None;
+        }
+        let cdf = (0..CDF_SIZE)
+            .map(|index| {
+                let location = index as f64 / (CDF_SIZE - 1) as f64;
+                let uniform = lower + (upper - lower) * location;
+                (1.0 - UNIFORM_WEIGHT) * interpolate(&anchors, location) + UNIFORM_WEIGHT * uniform
+            })
+            .collect();
+        Some(cdf)
+    }
+
+    fn percentile(&self, forecast: &AggregateForecast, p: f64) -> Option<f64> {
+        if !matches!(self.question_type, Some(Type::numeric) | Some(Type::date)) {
+            return None;
+        }
+        self.scaling.as_ref()?.unscale(forecast.location(p)?)
+    }
+}
+/// Piecewise linear through `anchors`, sorted by location and spanning `[0, 1]`.
+fn interpolate(anchors: &[(f64, f64)], location: f64) -> f64 {
+    for pair in anchors.windows(2) {
+        let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+        if location <= x1 {
+            if x1 <= x0 {
+                return y1;
+            }
+            return y0 + (y1 - y0) * (location - x0) / (x1 - x0);
+        }
+    }
+    anchors.last().map(|anchor| anchor.1).unwrap_or_default()
+}
+
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
 pub struct Aggregations {
-    recently_weighted: u32,
-    metaculus_prediction: u32,
+    pub recency_weighted: Option<Aggregation>,
+    pub metaculus_prediction: Option<Aggregation>,
+}
+
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct Aggregation {
+    #[serde(default)]
+    pub history: Vec<AggregateForecast>,
+    pub latest: Option<AggregateForecast>,
+}
+
+impl Aggregation {
+    /// `latest`, or the last step of the history when it is left out.
+    pub fn current(&self) -> Option<&AggregateForecast> {
+        self.latest.as_ref().or_else(|| {
+            self.history
+                .iter()
+                .max_by(|a, b| a.start_time.total_cmp(&b.start_time))
+        })
+    }
+}
+
+/// One step of the community forecast, valid from `start_time` (unix seconds).
+///
+/// Binary questions carry the probability in `centers`. Continuous ones carry
+/// quartiles on the internal `[0, 1]` axis and, in `forecast_values`, the CDF
+/// sampled at evenly spaced locations across it.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct AggregateForecast {
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    pub forecaster_count: Option<u32>,
+    pub interval_lower_bounds: Option<Vec<f64>>,
+    pub centers: Option<Vec<f64>>,
+    pub interval_upper_bounds: Option<Vec<f64>>,
+    pub means: Option<Vec<f64>>,
+    pub forecast_values: Option<Vec<f64>>,
+}
+
+impl AggregateForecast {
+    /// Location on the internal axis where the CDF reaches `p`, interpolating
+    /// between samples. Mass outside the range clamps to its ends.
+    pub fn location(&self, p: f64) -> Option<f64> {
+        let cdf = self.forecast_values.as_ref().filter(|cdf| cdf.len() > 2)?;
+        let step = 1.0 / (cdf.len() - 1) as f64;
+        match cdf.iter().position(|&value| value >= p) {
+            Some(0) => Some(0.0),
+            Some(index) => {
+                let (below, above) = (cdf[index - 1], cdf[index]);
+                let fraction = if above > below {
+                    (p - below) / (above - below)
+                } else {
+                    0.0
+                };
+                Some((index as f64 - 1.0 + fraction) * step)
+            }
+            None => Some(1.0),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
@@ -126,6 +366,8 @@ impl From<serde_json::Value> for MetaculusMarket {
         let forecasts_count = results["forecasts_count"].as_u64().unwrap() as u32;
         let status = Status::from_str(results["status"].as_str().unwrap()).unwrap();
         let forecast_type = Type::from_str(results["forecast_type"].as_str().unwrap()).unwrap();
+        let projects = serde_json::from_value(results["projects"].clone()).ok();
+        let question = serde_json::from_value(results["question"].clone()).ok();
 
         MetaculusMarket {
             id,
@@ -137,7 +379,113 @@ impl From<serde_json::Value> for MetaculusMarket {
             forecasts_count,
             status: Some(status),
             forecast_type: Some(forecast_type),
+            projects,
+            question,
+        }
+    }
+}
+
+impl Market for MetaculusMarket {
+    fn title(&self) -> &str {
+        self.title.as_deref().unwrap_or("")
+    }
+    fn platform(&self) -> &str {
+        "metaculus"
+    }
+    fn platform_id(&self) -> String {
+        self.id.to_string()
+    }
+    fn open_time(&self) -> MarketResult<DateTime<Utc>> {
+        let created_at = self.created_at.as_ref().ok_or(Error::MissingField {
+            platform: "metaculus",
+            field: "created_at",
+        })?;
+        Ok(parse_timestamp(created_at)?)
+    }
+    fn close_time(&self) -> MarketResult<DateTime<Utc>> {
+        let scheduled_close_time =
+            self.scheduled_close_time
+                .as_ref()
+                .ok_or(Error::MissingField {
+                    platform: "metaculus",
+                    field: "scheduled_close_time",
+                })?;
+        Ok(parse_timestamp(scheduled_close_time)?)
+    }
+    // Metaculus is a forecasting platform, nothing is traded.
+    fn volume_usd(&self) -> f32 {
+        0.0
+    }
+    fn num_traders(&self) -> i32 {
+        self.nr_forecasters as i32
+    }
+    fn category(&self) -> &str {
+        self.projects
+            .as_ref()
+            .and_then(|projects| projects.category.first())
+            .map(|project| project.name.as_str())
+            .unwrap_or("")
+    }
+    fn resolution(&self) -> f32 {
+        match self
+            .question
+            .as_ref()
+            .and_then(|question| question.resolution.as_deref())
+        {
+            Some("yes") => 1.0,
+            Some("no") => 0.0,
+            _ => UNRESOLVED,
+        }
+    }
+    /// Only final once the post's status is `resolved`.
+    fn settlement(&self, outcome: &str) -> Option<f64> {
+        if self.status != Some(Status::resolved) {
+            return None;
+        }
+        binary_settlement(self.resolution(), outcome)
+    }
+    /// Annulled and numeric questions resolve too, without a settlement.
+    fn is_final(&self) -> bool {
+        self.status == Some(Status::resolved)
+    }
+    /// Recency-weighted community forecast history, binary questions only.
+    fn events(&self) -> Vec<Update> {
+        let Some(question) = &self.question else {
+            return Vec::new();
+        };
+        if question.question_type != Some(Type::binary) {
+            return Vec::new();
         }
+        let Some(recency_weighted) = question
+            .aggregations
+            .as_ref()
+            .and_then(|aggregations| aggregations.recency_weighted.as_ref())
+        else {
+            return Vec::new();
+        };
+        let mut events: Vec<Update> = recency_weighted
+            .history
+            .iter()
+            .filter_map(|forecast| {
+                let time = DateTime::from_timestamp(forecast.start_time as i64, 0)?;
+                let probability = forecast.centers.as_ref()?.first()?;
+                Some(Update::new(time, *probability as f32))
+            })
+            .collect();
+        events.sort_by_key(|event| event.time);
+        events
+    }
+}
+
+impl MetaculusMarket {
+    /// Latest community probability, for binary questions.
+    pub fn community_probability(&self) -> Option<f64> {
+        self.question.as_ref()?.community_probability()
+    }
+
+    /// Latest community percentile `p`, for numeric and date questions.
+    pub fn community_percentile(&self, p: f64) -> Option<f64> {
+        self.question.as_ref()?.community_percentile(p)
     }
 }
 
@@ -146,3 +494,84 @@ impl fmt::Display for MetaculusMarket {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const METACULUS_MARKET: &str = include_str!("../../../tests/fixtures/metaculus_market.json");
+    const METACULUS_NUMERIC: &str = include_str!("../../../tests/fixtures/metaculus_numeric.json");
+
+    #[test]
+    fn test_binary_community_probability() {
+        let market: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        assert_eq!(market.community_probability(), Some(0.42));
+        assert_eq!(market.community_percentile(0.5), None);
+    }
+
+    #[test]
+    fn test_numeric_percentiles() {
+        let market: MetaculusMarket = serde_json::from_str(METACULUS_NUMERIC).unwrap();
+        let question = market.question.as_ref().unwrap();
+        assert_eq!(market.community_probability(), None);
+        // 5% of the mass sits below the range and 5% above it.
+        assert!((market.community_percentile(0.5).unwrap() - 50.0).abs() < 1e-6);
+        assert!((market.community_percentile(0.25).unwrap() - 22.2222).abs() < 1e-3);
+        assert_eq!(market.community_percentile(0.01), Some(0.0));
+        assert_eq!(market.community_percentile(0.99), Some(100.0));
+        let history = question.percentile_history(0.5);
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].0.timestamp(), 1730505600);
+
+        let mut date = question.clone();
+        date.question_type = Some(Type::date);
+        date.scaling = Some(Scaling {
+            range_min: Some(1_735_689_600.0),
+            range_max: Some(1_767_225_600.0),
+            zero_point: None,
+        });
+        let median = date.community_date_percentile(0.5).unwrap();
+        assert_eq!(median.timestamp(), 1_751_457_600);
+    }
+
+    #[test]
+    fn test_cdf_from_percentiles() {
+        let market: MetaculusMarket = serde_json::from_str(METACULUS_NUMERIC).unwrap();
+        let question = market.question.unwrap();
+        let cdf = question
+            .cdf_from_percentiles(&[(0.05, 10.0), (0.5, 30.0), (0.95, 80.0)])
+            .unwrap();
+        assert_eq!(cdf.len(), CDF_SIZE);
+        assert!((cdf[0] - OPEN_BOUND_MASS).abs() < 1e-9);
+        assert!((cdf[CDF_SIZE - 1] - (1.0 - OPEN_BOUND_MASS)).abs() < 1e-9);
+        assert!(cdf.windows(2).all(|pair| pair[1] > pair[0]));
+        // The median comes back out, up to the uniform mix.
+        let forecast = AggregateForecast {
+            forecast_values: Some(cdf),
+            ..question.community().unwrap().current().unwrap().clone()
+        };
+        let median = question
+            .scaling
+            .as_ref()
+            .unwrap()
+            .unscale(forecast.location(0.5).unwrap());
+        assert!((median.unwrap() - 30.0).abs() < 1.0);
+
+        assert!(question
+            .cdf_from_percentiles(&[(0.5, 60.0), (0.95, 40.0)])
+            .is_none());
+    }
+
+    #[test]
+    fn test_log_scaling() {
+        let scaling = Scaling {
+            range_min: Some(1.0),
+            range_max: Some(1000.0),
+            zero_point: Some(0.0),
+        };
+        assert!((scaling.unscale(0.0).unwrap() - 1.0).abs() < 1e-9);
+        assert!((scaling.unscale(0.5).unwrap() - 1000f64.sqrt()).abs() < 1e-9);
+        assert!((scaling.unscale(1.0).unwrap() - 1000.0).abs() < 1e-9);
+        assert!((scaling.scale(1000f64.sqrt()).unwrap() - 0.5).abs() < 1e-9);
+    }
+}
@@ -2,11 +2,17 @@ use chrono::serde::ts_seconds;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use self::augur::AugurMarket;
+use self::calculator::Calculator;
+use self::manifold::ManifoldMarket;
 use self::metaculus::MetaculusMarket;
 use self::polymarket::PolymarketMarket;
 use self::question::DBQuestion;
 use self::question::QuestionStorage;
+use crate::db::error::Error;
 pub mod augur;
+pub mod calculator;
+pub mod forecast;
 pub mod gamma;
 pub mod index;
 pub mod manifold;
@@ -18,16 +24,49 @@ pub mod simplebroker;
 pub type Result<T> = std::result::Result<T, Box<MarketError>>;
 const DEFAULT_OPENING_PROB: f32 = 0.5;
 const SECS_PER_DAY: f32 = (60 * 60 * 24) as f32;
+/// Manifold mana has historically been sold at M$100 per USD.
+const MANA_PER_USD: f32 = 100.0;
+/// Stored in `StandardMarket::resolution` while a market is still open or was cancelled.
+pub const UNRESOLVED: f32 = -1.0;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct MarketError {
     details: String,
 }
-#[derive(Debug, Serialize, Deserialize)]
+
+impl From<Error> for Box<MarketError> {
+    fn from(value: Error) -> Self {
+        Box::new(MarketError {
+            details: value.to_string(),
+        })
+    }
+}
+
+impl From<Box<MarketError>> for Error {
+    fn from(value: Box<MarketError>) -> Self {
+        Error::Market(value.details)
+    }
+}
+/// Shape of a market's outcomes. Non-binary markets are reduced to one
+/// probability: the leading answer's, or the value's position in its range.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum MarketKind {
+    #[default]
+    Binary,
+    MultipleChoice,
+    Numeric,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
 pub struct StandardMarket {
     title: String,
     platform: String,
     platform_id: String,
+    // Markets stored before kinds existed are binary.
+    #[serde(default)]
+    kind: MarketKind,
     open_time: DateTime<Utc>,
     close_time: DateTime<Utc>,
     volume_usd: f32,
@@ -39,25 +78,102 @@ pub struct StandardMarket {
     prob_tma: f32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, sqlx::FromRow)]
 pub struct Update {
-    time: DateTime<Utc>,
-    probability: f32,
+    pub time: DateTime<Utc>,
+    pub probability: f32,
+}
+
+impl Update {
+    pub fn new(time: DateTime<Utc>, probability: f32) -> Self {
+        Self { time, probability }
+    }
+}
+
+/// Settlement of `outcome` on a market whose YES side resolved to `yes`.
+pub(crate) fn binary_settlement(yes: f32, outcome: &str) -> Option<f64> {
+    if yes == UNRESOLVED {
+        return None;
+    }
+    if outcome.eq_ignore_ascii_case("no") {
+        Some(1.0 - yes as f64)
+    } else {
+        Some(yes as f64)
+    }
 }
 
 pub trait Market {
     fn title(&self) -> &str;
     fn platform(&self) -> &str;
-    fn platform_id(&self) -> &str;
+    fn platform_id(&self) -> String;
     fn open_time(&self) -> Result<DateTime<Utc>>;
     fn close_time(&self) -> Result<DateTime<Utc>>;
     fn volume_usd(&self) -> f32;
     fn num_traders(&self) -> i32;
     fn category(&self) -> &str;
+    fn kind(&self) -> MarketKind {
+        MarketKind::Binary
+    }
     fn resolution(&self) -> f32;
-    fn prob_midpoint(&self) -> f32;
-    fn prob_close(&self) -> f32;
-    fn prob_tma(&self) -> f32;
+    /// What `outcome` settled at: 1 if it happened, 0 if not, or in between for
+    /// markets resolved to a probability. `None` while open or when cancelled.
+    fn settlement(&self, outcome: &str) -> Option<f64> {
+        binary_settlement(self.resolution(), outcome)
+    }
+    /// Whether the market is done, settled or not. A cancelled market is final
+    /// but settles nothing, so forecasts on it can be given up on.
+    fn is_final(&self) -> bool {
+        self.resolution() != UNRESOLVED
+    }
+    /// The market's current probability of `outcome`, to score forecasts against.
+    fn outcome_probability(&self, outcome: &str) -> Option<f64> {
+        if self.kind() != MarketKind::Binary {
+            return None;
+        }
+        let yes = self.events().last()?.probability as f64;
+        if outcome.eq_ignore_ascii_case("no") {
+            Some(1.0 - yes)
+        } else {
+            Some(yes)
+        }
+    }
+    fn calculator(&self) -> Result<Calculator> {
+        Ok(Calculator::new(
+            self.events(),
+            self.open_time()?,
+            self.close_time()?,
+        ))
+    }
+    /// Probability halfway through the market's lifetime.
+    fn prob_midpoint(&self) -> f32 {
+        self.calculator()
+            .map(|calculator| calculator.prob_midpoint())
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+    fn prob_close(&self) -> f32 {
+        self.calculator()
+            .map(|calculator| calculator.prob_close())
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+    /// Time-weighted average probability between open and close.
+    fn prob_tma(&self) -> f32 {
+        self.calculator()
+            .map(|calculator| calculator.prob_tma())
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+    fn prob_days_before_close(&self, days: f32) -> f32 {
+        self.calculator()
+            .map(|calculator| calculator.prob_days_before_close(days))
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+    /// Most recent probability, used for live snapshots.
+    fn prob_latest(&self) -> f32 {
+        self.events()
+            .last()
+            .map(|event| event.probability)
+            .unwrap_or(DEFAULT_OPENING_PROB)
+    }
+    /// Probability history ordered by time.
     fn events(&self) -> Vec<Update>;
     fn prob_at_time(&self, time: DateTime<Utc>) -> Result<f32> {
         if time < self.open_time()? {
@@ -79,53 +195,403 @@ pub trait Market {
     }
 }
 
-async fn store_markets(markets: Vec<StandardMarket>, db: axum::extract::State<QuestionStorage>) {
-    let mut db = db.0.lock().await;
-    for chunk in markets.chunks(1000) {
-        for market in chunk {
-            let key = format!("market:{}", market.platform_id);
-            let value: DBQuestion = DBQuestion::from(market);
-            db.insert(value);
-        }
+impl StandardMarket {
+    /// Storage key, unique across platforms.
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.platform, self.platform_id)
     }
 }
 
-impl From<DBQuestion> for StandardMarket {
-    fn from(value: DBQuestion) -> Self {
-        let open_time: DateTime<Utc> =
-            DateTime::parse_from_str(&value.open_time, "%Y %b %d %H:%M:%S.3f %z")
-                .unwrap()
-                .to_utc();
-        let close_time: DateTime<Utc> =
-            DateTime::parse_from_str(&value.close_time, "%Y %b %d %H:%M:%S.3f %z")
-                .unwrap()
-                .to_utc();
+pub async fn store_markets(
+    markets: Vec<StandardMarket>,
+    db: axum::extract::State<QuestionStorage>,
+) -> std::result::Result<(), Error> {
+    for market in &markets {
+        db.upsert(market)?;
+    }
+    Ok(())
+}
 
-        StandardMarket {
+impl TryFrom<DBQuestion> for StandardMarket {
+    type Error = Error;
+    fn try_from(value: DBQuestion) -> std::result::Result<Self, Self::Error> {
+        Ok(StandardMarket {
+            open_time: parse_timestamp(&value.open_time)?,
+            close_time: parse_timestamp(&value.close_time)?,
             title: value.title,
             platform: value.platform,
             platform_id: value.platform_id,
-            open_time,
-            close_time,
             volume_usd: value.volume_usd,
             num_traders: value.num_traders,
             category: value.category,
+            kind: value.kind,
             resolution: value.resolution,
             prob_midpoint: value.prob_midpoint,
             prob_close: value.prob_close,
             prob_tma: value.prob_tma,
+        })
+    }
+}
+
+impl Market for StandardMarket {
+    fn title(&self) -> &str {
+        &self.title
+    }
+    fn platform(&self) -> &str {
+        &self.platform
+    }
+    fn platform_id(&self) -> String {
+        self.platform_id.clone()
+    }
+    fn open_time(&self) -> Result<DateTime<Utc>> {
+        Ok(self.open_time)
+    }
+    fn close_time(&self) -> Result<DateTime<Utc>> {
+        Ok(self.close_time)
+    }
+    fn volume_usd(&self) -> f32 {
+        self.volume_usd
+    }
+    fn num_traders(&self) -> i32 {
+        self.num_traders
+    }
+    fn category(&self) -> &str {
+        &self.category
+    }
+    fn kind(&self) -> MarketKind {
+        self.kind
+    }
+    fn resolution(&self) -> f32 {
+        self.resolution
+    }
+    fn prob_midpoint(&self) -> f32 {
+        self.prob_midpoint
+    }
+    fn prob_close(&self) -> f32 {
+        self.prob_close
+    }
+    fn prob_tma(&self) -> f32 {
+        self.prob_tma
+    }
+    fn prob_latest(&self) -> f32 {
+        self.prob_close
+    }
+    // Only the summary statistics are kept once a market is standardized.
+    fn events(&self) -> Vec<Update> {
+        Vec::new()
+    }
+}
+
+impl TryFrom<ManifoldMarket> for StandardMarket {
+    type Error = Error;
+    fn try_from(value: ManifoldMarket) -> std::result::Result<Self, Self::Error> {
+        let created_time = value.createdTime.ok_or(Error::MissingField {
+            platform: "manifold",
+            field: "createdTime",
+        })?;
+        let close_time = value
+            .closeTime
+            .or(value.resolutionTime)
+            .ok_or(Error::MissingField {
+                platform: "manifold",
+                field: "closeTime",
+            })?;
+
+        Ok(StandardMarket {
+            title: value.title().to_string(),
+            platform: value.platform().to_string(),
+            platform_id: value.platform_id(),
+            open_time: timestamp_from_millis(created_time)?,
+            close_time: timestamp_from_millis(close_time)?,
+            volume_usd: value.volume_usd(),
+            num_traders: value.num_traders(),
+            category: value.category().to_string(),
+            kind: value.kind(),
+            resolution: value.resolution(),
+            prob_midpoint: value.prob_midpoint(),
+            prob_close: value.prob_close(),
+            prob_tma: value.prob_tma(),
+        })
+    }
+}
+
+impl TryFrom<PolymarketMarket> for StandardMarket {
+    type Error = Error;
+    fn try_from(value: PolymarketMarket) -> std::result::Result<Self, Self::Error> {
+        let title = value.question.as_ref().ok_or(Error::MissingField {
+            platform: "polymarket",
+            field: "question",
+        })?;
+        if value.condition_id.is_none() && value.id.is_none() {
+            return Err(Error::MissingField {
+                platform: "polymarket",
+                field: "conditionId",
+            });
         }
+        let start_date = value.start_date.as_ref().ok_or(Error::MissingField {
+            platform: "polymarket",
+            field: "startDate",
+        })?;
+        let end_date = value.end_date.as_ref().ok_or(Error::MissingField {
+            platform: "polymarket",
+            field: "endDate",
+        })?;
+
+        Ok(StandardMarket {
+            title: title.clone(),
+            platform: value.platform().to_string(),
+            platform_id: value.platform_id(),
+            open_time: parse_timestamp(start_date)?,
+            close_time: parse_timestamp(end_date)?,
+            volume_usd: value.volume_usd(),
+            num_traders: value.num_traders(),
+            category: value.category().to_string(),
+            kind: value.kind(),
+            resolution: value.resolution(),
+            prob_midpoint: value.prob_midpoint(),
+            prob_close: value.prob_close(),
+            prob_tma: value.prob_tma(),
+        })
     }
 }
 
-impl From<PolymarketMarket> for StandardMarket {
-    fn from(value: PolymarketMarket) -> Self {
-        unimplemented!()
+impl TryFrom<MetaculusMarket> for StandardMarket {
+    type Error = Error;
+    fn try_from(value: MetaculusMarket) -> std::result::Result<Self, Self::Error> {
+        let title = value.title.as_ref().ok_or(Error::MissingField {
+            platform: "metaculus",
+            field: "title",
+        })?;
+        let created_at = value.created_at.as_ref().ok_or(Error::MissingField {
+            platform: "metaculus",
+            field: "created_at",
+        })?;
+        let scheduled_close_time =
+            value
+                .scheduled_close_time
+                .as_ref()
+                .ok_or(Error::MissingField {
+                    platform: "metaculus",
+                    field: "scheduled_close_time",
+                })?;
+
+        Ok(StandardMarket {
+            title: title.clone(),
+            platform: value.platform().to_string(),
+            platform_id: value.platform_id(),
+            open_time: parse_timestamp(created_at)?,
+            close_time: parse_timestamp(scheduled_close_time)?,
+            volume_usd: value.volume_usd(),
+            num_traders: value.num_traders(),
+            category: value.category().to_string(),
+            kind: value.kind(),
+            resolution: value.resolution(),
+            prob_midpoint: value.prob_midpoint(),
+            prob_close: value.prob_close(),
+            prob_tma: value.prob_tma(),
+        })
     }
 }
 
-impl From<MetaculusMarket> for StandardMarket {
-    fn from(value: MetaculusMarket) -> Self {
-        unimplemented!()
+impl TryFrom<AugurMarket> for StandardMarket {
+    type Error = Error;
+    fn try_from(value: AugurMarket) -> std::result::Result<Self, Self::Error> {
+        if value.extra_info.description.is_empty() {
+            return Err(Error::MissingField {
+                platform: "augur",
+                field: "description",
+            });
+        }
+        Ok(StandardMarket {
+            title: value.title().to_string(),
+            platform: value.platform().to_string(),
+            platform_id: value.platform_id(),
+            open_time: value.created_time,
+            close_time: value.end_time,
+            volume_usd: value.volume_usd(),
+            num_traders: value.num_traders(),
+            category: value.category().to_string(),
+            kind: value.kind(),
+            resolution: value.resolution(),
+            prob_midpoint: value.prob_midpoint(),
+            prob_close: value.prob_close(),
+            prob_tma: value.prob_tma(),
+        })
+    }
+}
+
+fn parse_timestamp(value: &str) -> std::result::Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.to_utc())
+        .map_err(|_| Error::InvalidTimestamp(value.to_string()))
+}
+
+fn timestamp_from_millis(millis: i64) -> std::result::Result<DateTime<Utc>, Error> {
+    DateTime::from_timestamp_millis(millis).ok_or(Error::InvalidTimestamp(millis.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFOLD_MARKET: &str = include_str!("../../../tests/fixtures/manifold_market.json");
+    const POLYMARKET_MARKET: &str = include_str!("../../../tests/fixtures/polymarket_market.json");
+    const METACULUS_MARKET: &str = include_str!("../../../tests/fixtures/metaculus_market.json");
+    const MANIFOLD_BETS: &str = include_str!("../../../tests/fixtures/manifold_bets.json");
+    const MANIFOLD_MULTIPLE_CHOICE: &str =
+        include_str!("../../../tests/fixtures/manifold_multiple_choice.json");
+    const MANIFOLD_NUMERIC: &str = include_str!("../../../tests/fixtures/manifold_numeric.json");
+
+    #[test]
+    fn test_manifold_to_standard_market() {
+        let market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        let standard = StandardMarket::try_from(market).unwrap();
+
+        assert_eq!(standard.platform, "manifold");
+        assert_eq!(standard.platform_id, "9Ccsjc0fmbIb9g50p7SB");
+        assert_eq!(standard.kind, MarketKind::Binary);
+        assert_eq!(standard.open_time.timestamp(), 1704067200);
+        assert_eq!(standard.close_time.timestamp(), 1735689599);
+        assert!((standard.volume_usd - 254.305).abs() < 1e-3);
+        assert_eq!(standard.num_traders, 87);
+        assert_eq!(standard.category, "us-economics");
+        assert_eq!(standard.resolution, UNRESOLVED);
+        assert!((standard.prob_close - 0.7823).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_manifold_events_from_bets() {
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        market.bets = serde_json::from_str(MANIFOLD_BETS).unwrap();
+        let events = market.events();
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].time, market.open_time().unwrap());
+        assert_eq!(events[0].probability, 0.5);
+        assert_eq!(events[2].probability, 0.8);
+        assert!((market.prob_midpoint() - 0.6).abs() < 1e-6);
+        assert!((market.prob_close() - 0.8).abs() < 1e-6);
+        assert!((market.prob_tma() - 0.650273).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_manifold_multiple_choice() {
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MULTIPLE_CHOICE).unwrap();
+        assert_eq!(market.kind(), MarketKind::MultipleChoice);
+        assert_eq!(market.answers.len(), 3);
+        assert_eq!(market.answers[1].pool().unwrap().YES, 790.1);
+        let leader = market.leading_answer().unwrap();
+        assert_eq!(leader.text, "Labour");
+
+        let standard = StandardMarket::try_from(market.clone()).unwrap();
+        assert_eq!(standard.kind, MarketKind::MultipleChoice);
+        assert!((standard.prob_close - 0.71).abs() < 1e-6);
+        assert_eq!(standard.resolution, UNRESOLVED);
+
+        market.resolution = Some("a2Con".to_string());
+        assert_eq!(market.resolution(), 0.0);
+        market.resolution = Some("a1Lab".to_string());
+        assert_eq!(market.resolution(), 1.0);
+
+        // A split resolution pays each answer its resolution probability.
+        market.resolution = Some("MKT".to_string());
+        assert_eq!(market.resolution(), UNRESOLVED);
+        market.answers[0].resolutionProbability = Some(0.6);
+        assert!((market.resolution() - 0.6).abs() < 1e-6);
+        market.resolution = Some("CANCEL".to_string());
+        assert_eq!(market.resolution(), UNRESOLVED);
+        market.answers[1].resolution = Some("NO".to_string());
+        assert_eq!(market.answer_resolution(&market.answers[1]), Some(0.0));
+
+        market.answers[0].probability = Some(f64::NAN);
+        assert_eq!(market.leading_answer().unwrap().text, "Conservative");
+    }
+
+    #[test]
+    fn test_manifold_numeric() {
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_NUMERIC).unwrap();
+        assert_eq!(market.kind(), MarketKind::Numeric);
+        assert!((market.scaled_value().unwrap() - 0.5).abs() < 1e-6);
+        market.isLogScale = Some(false);
+        assert!((market.scaled_value().unwrap() - 0.030653).abs() < 1e-6);
+
+        let standard = StandardMarket::try_from(market).unwrap();
+        assert_eq!(standard.kind, MarketKind::Numeric);
+        assert!((standard.prob_close - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_prob_at_time_before_open() {
+        let market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        let before_open = market.open_time().unwrap() - chrono::TimeDelta::days(1);
+
+        assert!(market.prob_at_time(before_open).is_err());
+    }
+
+    #[test]
+    fn test_manifold_missing_close_time() {
+        let mut market: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        market.closeTime = None;
+        let err = StandardMarket::try_from(market).unwrap_err();
+
+        assert_eq!(
+            err,
+            Error::MissingField {
+                platform: "manifold",
+                field: "closeTime"
+            }
+        );
+    }
+
+    #[test]
+    fn test_polymarket_to_standard_market() {
+        let market: PolymarketMarket = serde_json::from_str(POLYMARKET_MARKET).unwrap();
+        let standard = StandardMarket::try_from(market).unwrap();
+
+        assert_eq!(standard.platform, "polymarket");
+        assert_eq!(
+            standard.platform_id,
+            "0x9c1a953fe92c8357f1b646ba25d983aa83e90c525992db14fb726fa895cb5763"
+        );
+        assert_eq!(standard.close_time.timestamp(), 1735646400);
+        assert!((standard.volume_usd - 1250342.1).abs() < 1.0);
+        assert_eq!(standard.category, "Crypto");
+        assert!((standard.prob_close - 0.62).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_metaculus_to_standard_market() {
+        let market: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        let standard = StandardMarket::try_from(market).unwrap();
+
+        assert_eq!(standard.platform, "metaculus");
+        assert_eq!(standard.platform_id, "28072");
+        assert_eq!(standard.close_time.timestamp(), 1730246400);
+        assert_eq!(standard.num_traders, 143);
+        assert_eq!(standard.category, "Economy & Business");
+        assert_eq!(standard.volume_usd, 0.0);
+        assert!((standard.prob_close - 0.42).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_metaculus_events_from_community_forecast() {
+        let market: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        let events = market.events();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].time.timestamp(), 1725292800);
+        assert!((events[0].probability - 0.35).abs() < 1e-6);
+        // Before the first community forecast the default opening probability applies.
+        let open = market.open_time().unwrap();
+        assert_eq!(market.prob_at_time(open).unwrap(), DEFAULT_OPENING_PROB);
+    }
+
+    #[test]
+    fn test_invalid_timestamp() {
+        let mut market: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        market.scheduled_close_time = Some("next tuesday".to_string());
+        let err = StandardMarket::try_from(market).unwrap_err();
+
+        assert_eq!(err, Error::InvalidTimestamp("next tuesday".to_string()));
     }
 }
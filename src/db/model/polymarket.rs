@@ -1,4 +1,5 @@
 use super::*;
+use crate::db::error::Error;
 use axum::Json;
 use core::fmt;
 use serde::{de, Deserializer};
@@ -6,7 +7,7 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PolymarketResult {
-    next_cursor: String,
+    pub next_cursor: String,
     pub data: Vec<PolymarketMarket>,
     // tokens: Option<Vec<PolymarketToken>>,
 }
@@ -14,34 +15,303 @@ pub struct PolymarketResult {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PolymarketMarket {
+    pub id: Option<String>,
     pub active: bool,
+    pub closed: Option<bool>,
     pub question: Option<String>,
-    #[serde(rename = "questionId")]
+    #[serde(rename = "questionId", alias = "question_id")]
     pub question_id: Option<String>,
+    #[serde(alias = "condition_id")]
+    pub condition_id: Option<String>,
+    #[serde(alias = "market_slug")]
+    pub slug: Option<String>,
+    pub start_date: Option<String>,
+    #[serde(alias = "end_date_iso")]
+    pub end_date: Option<String>,
+    pub volume_num: Option<f64>,
+    pub liquidity_num: Option<f64>,
     // #[serde(deserialize_with = "deserialize_into_string_array")]
     // pub outcomes: [String; 2],
+    #[serde(alias = "accepting_orders")]
     pub accepting_orders: Option<bool>,
-    #[serde(deserialize_with = "deserialize_outcome_prices")]
+    #[serde(default, deserialize_with = "deserialize_outcome_prices")]
     pub outcome_prices: Option<[f64; 2]>,
     pub category: Option<String>,
     // is_50_50_outcome: bool,
     // #[serde(deserialize_with = "deserialize_into_string_array")]
     // pub clob_token_ids: [String; 2],
+    // Gamma only; the CLOB listing has no spread.
+    #[serde(default)]
     pub spread: f64,
+    #[serde(default, alias = "minimum_tick_size")]
     pub order_price_min_tick_size: f64,
     pub tokens: Option<Vec<PolymarketToken>>,
+    /// Orders on neg-risk markets are signed for the neg-risk exchange.
+    #[serde(default, alias = "neg_risk")]
+    pub neg_risk: bool,
     pub rewards: Option<PolymarketRewards>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub price_history: Vec<PricesHistoryPoint>,
     // events: Option<Vec<PolymarketEvent>>,
 }
 
-pub struct PolymarketPosition {}
+/// One resting order level, flattened out of an `OrderBook`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PolymarketPosition {
+    pub token_id: String,
+    pub side: BookSide,
+    pub price: f64,
+    pub size: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BookSide {
+    Buy,
+    Sell,
+}
+
+/// CLOB `/book` for one outcome token. Prices are probabilities, sizes are shares.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OrderBook {
+    pub market: String,
+    pub asset_id: String,
+    pub hash: Option<String>,
+    pub timestamp: Option<String>,
+    // The websocket market channel has used `buys` and `sells` for the same levels.
+    #[serde(default, alias = "buys")]
+    pub bids: Vec<OrderLevel>,
+    #[serde(default, alias = "sells")]
+    pub asks: Vec<OrderLevel>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct OrderLevel {
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub price: f64,
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub size: f64,
+}
+
+impl OrderBook {
+    /// Bids from the highest price down.
+    pub fn sorted_bids(&self) -> Vec<OrderLevel> {
+        let mut bids = self.bids.clone();
+        bids.sort_by(|a, b| b.price.total_cmp(&a.price));
+        bids
+    }
+
+    /// Asks from the lowest price up.
+    pub fn sorted_asks(&self) -> Vec<OrderLevel> {
+        let mut asks = self.asks.clone();
+        asks.sort_by(|a, b| a.price.total_cmp(&b.price));
+        asks
+    }
+
+    pub fn best_bid(&self) -> Option<f64> {
+        self.sorted_bids().first().map(|level| level.price)
+    }
+
+    pub fn best_ask(&self) -> Option<f64> {
+        self.sorted_asks().first().map(|level| level.price)
+    }
+
+    pub fn midpoint(&self) -> Option<f64> {
+        Some((self.best_bid()? + self.best_ask()?) / 2.0)
+    }
+
+    pub fn spread(&self) -> Option<f64> {
+        Some(self.best_ask()? - self.best_bid()?)
+    }
+
+    /// Sets the size resting at `price` on `side`; a size of zero removes the level.
+    pub fn apply(&mut self, side: BookSide, price: f64, size: f64) {
+        let levels = match side {
+            BookSide::Buy => &mut self.bids,
+            BookSide::Sell => &mut self.asks,
+        };
+        levels.retain(|level| level.price != price);
+        if size > 0.0 {
+            levels.push(OrderLevel { price, size });
+        }
+    }
+
+    /// Shares resting on `side` at prices no worse than `limit`.
+    pub fn depth(&self, side: BookSide, limit: f64) -> f64 {
+        let within = |level: &&OrderLevel| match side {
+            BookSide::Buy => level.price <= limit,
+            BookSide::Sell => level.price >= limit,
+        };
+        let levels = match side {
+            BookSide::Buy => &self.asks,
+            BookSide::Sell => &self.bids,
+        };
+        levels.iter().filter(within).map(|level| level.size).sum()
+    }
+
+    /// Average price of a market order for `size` shares walking the book, or
+    /// `None` when the book is too thin to fill it or `size` is not positive.
+    pub fn fill_price(&self, side: BookSide, size: f64) -> Option<f64> {
+        if !(size.is_finite() && size > 0.0) {
+            return None;
+        }
+        let levels = match side {
+            BookSide::Buy => self.sorted_asks(),
+            BookSide::Sell => self.sorted_bids(),
+        };
+        let mut remaining = size;
+        let mut cost = 0.0;
+        for level in levels {
+            let take = remaining.min(level.size);
+            cost += take * level.price;
+            remaining -= take;
+            if remaining <= 0.0 {
+                return Some(cost / size);
+            }
+        }
+        None
+    }
+
+    /// Worst price a market order reaches: a buy spends `amount` USDC up the asks,
+    /// a sell sells `amount` shares down the bids. `None` when the book is too thin.
+    pub fn marketable_price(&self, side: BookSide, amount: f64) -> Option<f64> {
+        let levels = match side {
+            BookSide::Buy => self.sorted_asks(),
+            BookSide::Sell => self.sorted_bids(),
+        };
+        let mut total = 0.0;
+        for level in levels {
+            total += match side {
+                BookSide::Buy => level.size * level.price,
+                BookSide::Sell => level.size,
+            };
+            if total >= amount {
+                return Some(level.price);
+            }
+        }
+        None
+    }
+
+    pub fn positions(&self) -> Vec<PolymarketPosition> {
+        let position = |side: BookSide| {
+            move |level: OrderLevel| PolymarketPosition {
+                token_id: self.asset_id.clone(),
+                side,
+                price: level.price,
+                size: level.size,
+            }
+        };
+        self.sorted_bids()
+            .into_iter()
+            .map(position(BookSide::Buy))
+            .chain(self.sorted_asks().into_iter().map(position(BookSide::Sell)))
+            .collect()
+    }
+}
+
+/// Message of the CLOB websocket market channel.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "event_type", rename_all = "snake_case")]
+pub enum ClobEvent {
+    /// Full book of one token, sent on subscribe and after each trade.
+    Book(OrderBook),
+    PriceChange(PriceChange),
+    LastTradePrice(LastTrade),
+    /// `tick_size_change` and anything added later.
+    #[serde(other)]
+    Other,
+}
+
+/// Levels of one token's book that changed since the last message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PriceChange {
+    pub asset_id: String,
+    pub market: String,
+    pub changes: Vec<LevelChange>,
+    pub timestamp: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct LevelChange {
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub price: f64,
+    pub side: BookSide,
+    /// New size at `price`, not a delta.
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub size: f64,
+}
+
+/// A match between a maker and a taker; `side` is the taker's.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct LastTrade {
+    pub asset_id: String,
+    pub market: String,
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub price: f64,
+    pub side: BookSide,
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub size: f64,
+    pub timestamp: Option<String>,
+}
+
+/// `interval` of CLOB `/prices-history`, counted back from now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PriceInterval {
+    Hour,
+    SixHours,
+    Day,
+    Week,
+    Month,
+    Max,
+}
+
+impl PriceInterval {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriceInterval::Hour => "1h",
+            PriceInterval::SixHours => "6h",
+            PriceInterval::Day => "1d",
+            PriceInterval::Week => "1w",
+            PriceInterval::Month => "1m",
+            PriceInterval::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Midpoint {
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub mid: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct Spread {
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub spread: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct TickSize {
+    #[serde(deserialize_with = "deserialize_number_string")]
+    pub minimum_tick_size: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct NegRisk {
+    pub neg_risk: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PricesHistory {
+    pub history: Vec<PricesHistoryPoint>,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PolymarketToken {
-    token_id: String,
-    outcome: String,
+    pub token_id: String,
+    pub outcome: String,
     // price: f64,
-    winner: bool,
+    pub winner: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -71,10 +341,30 @@ pub struct PolymarketEvent {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct PricesHistoryPoint {
-    #[serde(with = "ts_seconds")]
-    timestamp: DateTime<Utc>,
-    price: f64,
+pub struct PricesHistoryPoint {
+    #[serde(with = "ts_seconds", alias = "t")]
+    pub timestamp: DateTime<Utc>,
+    #[serde(alias = "p")]
+    pub price: f64,
+}
+
+/// The CLOB sends prices and sizes as decimal strings.
+fn deserialize_number_string<'de, D>(deserializer: D) -> std::result::Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Text(String),
+        Value(f64),
+    }
+    match Number::deserialize(deserializer)? {
+        Number::Text(text) => text
+            .parse::<f64>()
+            .map_err(|err| de::Error::custom(err.to_string())),
+        Number::Value(value) => Ok(value),
+    }
 }
 
 fn deserialize_outcome_prices<'de, D>(
@@ -133,6 +423,16 @@ pub fn parse_polymarket_text(text: &str) -> Json<Vec<PolymarketMarket>> {
     Json(markets)
 }
 
+impl PolymarketMarket {
+    /// CLOB token of the "Yes" outcome, when the listing includes tokens.
+    pub fn yes_token(&self) -> Option<&PolymarketToken> {
+        self.tokens
+            .as_ref()?
+            .iter()
+            .find(|token| token.outcome.eq_ignore_ascii_case("yes"))
+    }
+}
+
 impl PolymarketEvent {
     pub fn get_url(&self) -> String {
         format!("https://polymarket.com/event/{}", self.slug)
@@ -144,6 +444,90 @@ impl std::fmt::Display for PolymarketEvent {
         write!(f, "{} - {}", self.title, self.get_url(),)
     }
 }
+impl Market for PolymarketMarket {
+    fn title(&self) -> &str {
+        self.question.as_deref().unwrap_or("")
+    }
+    fn platform(&self) -> &str {
+        "polymarket"
+    }
+    fn platform_id(&self) -> String {
+        self.condition_id
+            .clone()
+            .or_else(|| self.id.clone())
+            .unwrap_or_default()
+    }
+    fn open_time(&self) -> Result<DateTime<Utc>> {
+        let start_date = self.start_date.as_ref().ok_or(Error::MissingField {
+            platform: "polymarket",
+            field: "startDate",
+        })?;
+        Ok(parse_timestamp(start_date)?)
+    }
+    fn close_time(&self) -> Result<DateTime<Utc>> {
+        let end_date = self.end_date.as_ref().ok_or(Error::MissingField {
+            platform: "polymarket",
+            field: "endDate",
+        })?;
+        Ok(parse_timestamp(end_date)?)
+    }
+    fn volume_usd(&self) -> f32 {
+        self.volume_num.unwrap_or_default() as f32
+    }
+    // Gamma does not expose a unique trader count.
+    fn num_traders(&self) -> i32 {
+        0
+    }
+    fn category(&self) -> &str {
+        self.category.as_deref().unwrap_or("")
+    }
+    fn resolution(&self) -> f32 {
+        self.tokens
+            .as_ref()
+            .and_then(|tokens| tokens.iter().find(|token| token.winner))
+            .map(|winner| {
+                if winner.outcome.eq_ignore_ascii_case("yes") {
+                    1.0
+                } else {
+                    0.0
+                }
+            })
+            .unwrap_or(UNRESOLVED)
+    }
+    /// Outcomes are token names; once a token wins, every other one lost.
+    fn settlement(&self, outcome: &str) -> Option<f64> {
+        let tokens = self.tokens.as_ref()?;
+        if !tokens.iter().any(|token| token.winner) {
+            return None;
+        }
+        tokens
+            .iter()
+            .find(|token| token.outcome.eq_ignore_ascii_case(outcome))
+            .map(|token| if token.winner { 1.0 } else { 0.0 })
+    }
+    fn is_final(&self) -> bool {
+        self.tokens
+            .as_ref()
+            .is_some_and(|tokens| tokens.iter().any(|token| token.winner))
+    }
+    fn events(&self) -> Vec<Update> {
+        if !self.price_history.is_empty() {
+            let mut events: Vec<Update> = self
+                .price_history
+                .iter()
+                .map(|point| Update::new(point.timestamp, point.price as f32))
+                .collect();
+            events.sort_by_key(|event| event.time);
+            return events;
+        }
+        // Without the price history the current price is the only observation.
+        match (self.open_time(), self.outcome_prices) {
+            (Ok(open_time), Some(prices)) => vec![Update::new(open_time, prices[0] as f32)],
+            _ => Vec::new(),
+        }
+    }
+}
+
 impl fmt::Display for PolymarketMarket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
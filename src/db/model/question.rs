@@ -1,9 +1,8 @@
 use super::simplebroker::SimpleBroker;
-use crate::StandardMarket;
+use super::{parse_timestamp, MarketKind, StandardMarket};
+use crate::db::repository::MarketRepository;
 use async_graphql::*;
-use futures_util::{lock::Mutex, Stream, StreamExt};
-use slab::Slab;
-use std::sync::Arc;
+use futures_util::{Stream, StreamExt};
 // use crate::db::Db;
 pub struct Indicators {
     num_forecasts: i32,
@@ -28,6 +27,7 @@ pub struct DBQuestion {
     pub volume_usd: f32,
     pub num_traders: i32,
     pub category: String,
+    pub kind: MarketKind,
     pub resolution: f32,
     pub prob_midpoint: f32,
     pub prob_close: f32,
@@ -56,16 +56,47 @@ impl DBQuestion {
     // }
 }
 
-pub type QuestionStorage = Arc<Mutex<Slab<DBQuestion>>>;
+pub type QuestionStorage = MarketRepository;
 
 pub struct QueryRoot;
 
 #[Object]
 impl QueryRoot {
-    async fn questions(&self, ctx: &Context<'_>) -> Vec<DBQuestion> {
-        let storage = ctx.data_unchecked::<QuestionStorage>().lock().await;
-        let questions = storage.iter().map(|(_, q)| q).cloned().collect();
-        questions
+    async fn questions(
+        &self,
+        ctx: &Context<'_>,
+        platform: Option<String>,
+        category: Option<String>,
+    ) -> Result<Vec<DBQuestion>> {
+        let storage = ctx.data_unchecked::<QuestionStorage>();
+        let markets = match (platform, category) {
+            (Some(platform), Some(category)) => storage
+                .by_platform(&platform)?
+                .into_iter()
+                .filter(|market| market.category == category)
+                .collect(),
+            (Some(platform), None) => storage.by_platform(&platform)?,
+            (None, Some(category)) => storage.by_category(&category)?,
+            (None, None) => storage.all()?,
+        };
+        Ok(markets.iter().map(DBQuestion::from).collect())
+    }
+
+    async fn question(&self, ctx: &Context<'_>, id: ID) -> Result<Option<DBQuestion>> {
+        let storage = ctx.data_unchecked::<QuestionStorage>();
+        Ok(storage.get_by_key(&id)?.as_ref().map(DBQuestion::from))
+    }
+
+    /// Questions closing between two RFC 3339 timestamps.
+    async fn questions_closing(
+        &self,
+        ctx: &Context<'_>,
+        from: String,
+        to: String,
+    ) -> Result<Vec<DBQuestion>> {
+        let storage = ctx.data_unchecked::<QuestionStorage>();
+        let markets = storage.closing_between(parse_timestamp(&from)?, parse_timestamp(&to)?)?;
+        Ok(markets.iter().map(DBQuestion::from).collect())
     }
 }
 
@@ -91,10 +122,8 @@ impl QuestionChanged {
         &self.id
     }
     async fn question(&self, ctx: &Context<'_>) -> Result<Option<DBQuestion>> {
-        let questions = ctx.data_unchecked::<QuestionStorage>().lock().await;
-        let id = self.id.parse::<usize>()?;
-
-        Ok(questions.get(id).cloned())
+        let storage = ctx.data_unchecked::<QuestionStorage>();
+        Ok(storage.get_by_key(&self.id)?.as_ref().map(DBQuestion::from))
     }
 }
 #[Object]
@@ -114,16 +143,15 @@ impl MutationRoot {
         prob_tma: f32,
         prob_midpoint: f32,
         resolution: f32,
-    ) -> ID {
-        let mut questions = ctx.data_unchecked::<QuestionStorage>().lock().await;
-        let entry = questions.vacant_entry();
-        let id: ID = entry.key().into();
-        let question = DBQuestion {
-            id: id.clone(),
+    ) -> Result<ID> {
+        let storage = ctx.data_unchecked::<QuestionStorage>();
+        let market = StandardMarket {
             title,
-            open_time,
-            close_time,
+            open_time: parse_timestamp(&open_time)?,
+            close_time: parse_timestamp(&close_time)?,
             category,
+            // Questions added by hand are yes/no.
+            kind: MarketKind::Binary,
             platform,
             volume_usd,
             num_traders,
@@ -133,12 +161,13 @@ impl MutationRoot {
             prob_tma,
             resolution,
         };
-        questions.insert(question);
+        storage.upsert(&market)?;
+        let id = ID::from(market.key());
         SimpleBroker::publish(QuestionChanged {
             mutation_type: MutationType::Created,
             id: id.clone(),
         });
-        id
+        Ok(id)
     }
 }
 
@@ -175,16 +204,16 @@ impl SubscriptionRoot {
 impl From<&StandardMarket> for DBQuestion {
     fn from(value: &StandardMarket) -> Self {
         DBQuestion {
-            //TODO: Change ID to be a unique identifier
-            id: async_graphql::ID::from(uuid::Uuid::new_v4().to_string().as_str()),
+            id: async_graphql::ID::from(value.key()),
             title: value.title.clone(),
             platform: value.platform.clone(),
             platform_id: value.platform_id.clone(),
-            open_time: value.open_time.to_string(),
-            close_time: value.close_time.to_string(),
+            open_time: value.open_time.to_rfc3339(),
+            close_time: value.close_time.to_rfc3339(),
             volume_usd: value.volume_usd,
             num_traders: value.num_traders,
             category: value.category.clone(),
+            kind: value.kind,
             resolution: value.resolution,
             prob_midpoint: value.prob_midpoint,
             prob_close: value.prob_close,
@@ -0,0 +1,320 @@
+use super::error::Error;
+use super::model::{Market, StandardMarket, Update};
+use super::store::MarketStore;
+use super::MarketsDb;
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sled::transaction::ConflictableTransactionError;
+use sled::Transactional;
+
+type Result<T> = std::result::Result<T, Error>;
+
+const MARKETS_TREE: &str = "markets";
+const CLOSE_TIME_INDEX: &str = "markets_by_close_time";
+const PLATFORM_INDEX: &str = "markets_by_platform";
+const CATEGORY_INDEX: &str = "markets_by_category";
+const UPDATES_TREE: &str = "probability_updates";
+
+/// `StandardMarket`s persisted in sled, keyed by `platform:platform_id`.
+///
+/// Secondary index trees map close time, platform and category back to the
+/// primary key and are kept in sync inside one transaction on every upsert.
+/// Probability updates live in their own tree keyed by market key and time.
+#[derive(Clone)]
+pub struct MarketRepository {
+    markets: sled::Tree,
+    by_close_time: sled::Tree,
+    by_platform: sled::Tree,
+    by_category: sled::Tree,
+    updates: sled::Tree,
+}
+
+impl MarketRepository {
+    pub fn open(db: &MarketsDb) -> Result<Self> {
+        Ok(Self {
+            markets: db.open_tree(MARKETS_TREE)?,
+            by_close_time: db.open_tree(CLOSE_TIME_INDEX)?,
+            by_platform: db.open_tree(PLATFORM_INDEX)?,
+            by_category: db.open_tree(CATEGORY_INDEX)?,
+            updates: db.open_tree(UPDATES_TREE)?,
+        })
+    }
+
+    /// Repository backed by a throwaway database, removed on drop.
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Self::open(&db)
+    }
+
+    pub fn upsert(&self, market: &StandardMarket) -> Result<()> {
+        let key = market.key();
+        let value = serde_json::to_vec(market)?;
+        let index_keys = IndexKeys::new(market, &key);
+
+        (
+            &self.markets,
+            &self.by_close_time,
+            &self.by_platform,
+            &self.by_category,
+        )
+            .transaction(|(markets, by_close_time, by_platform, by_category)| {
+                if let Some(previous) = markets.insert(key.as_bytes(), value.as_slice())? {
+                    let previous: StandardMarket = serde_json::from_slice(&previous)
+                        .map_err(|err| ConflictableTransactionError::Abort(Error::from(err)))?;
+                    let previous_keys = IndexKeys::new(&previous, &key);
+                    if let Some(close_time) = previous_keys.close_time {
+                        by_close_time.remove(close_time)?;
+                    }
+                    by_platform.remove(previous_keys.platform)?;
+                    by_category.remove(previous_keys.category)?;
+                }
+                if let Some(close_time) = &index_keys.close_time {
+                    by_close_time.insert(close_time.as_slice(), key.as_bytes())?;
+                }
+                by_platform.insert(index_keys.platform.as_slice(), key.as_bytes())?;
+                by_category.insert(index_keys.category.as_slice(), key.as_bytes())?;
+                Ok::<(), ConflictableTransactionError<Error>>(())
+            })?;
+        Ok(())
+    }
+
+    pub fn get(&self, platform: &str, platform_id: &str) -> Result<Option<StandardMarket>> {
+        self.get_by_key(&format!("{}:{}", platform, platform_id))
+    }
+
+    pub fn get_by_key(&self, key: &str) -> Result<Option<StandardMarket>> {
+        match self.markets.get(key)? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn all(&self) -> Result<Vec<StandardMarket>> {
+        self.markets
+            .iter()
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    /// Markets closing in `[from, to)`, ordered by close time.
+    pub fn closing_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StandardMarket>> {
+        let range = sortable_timestamp(from)..sortable_timestamp(to);
+        self.resolve(self.by_close_time.range(range))
+    }
+
+    pub fn by_platform(&self, platform: &str) -> Result<Vec<StandardMarket>> {
+        self.resolve(self.by_platform.scan_prefix(index_prefix(platform)))
+    }
+
+    pub fn by_category(&self, category: &str) -> Result<Vec<StandardMarket>> {
+        self.resolve(self.by_category.scan_prefix(index_prefix(category)))
+    }
+
+    /// Adds updates for a stored market, replacing any at the same time.
+    pub fn append_updates(&self, key: &str, updates: &[Update]) -> Result<()> {
+        if !self.markets.contains_key(key)? {
+            return Err(Error::Storage(format!("no market stored under `{}`", key)));
+        }
+        let mut batch = sled::Batch::default();
+        for update in updates {
+            let mut update_key = index_prefix(key);
+            update_key.extend_from_slice(&sortable_timestamp(update.time));
+            batch.insert(update_key, serde_json::to_vec(update)?);
+        }
+        self.updates.apply_batch(batch)?;
+        Ok(())
+    }
+
+    /// Updates for a market, ordered by time.
+    pub fn updates(&self, key: &str) -> Result<Vec<Update>> {
+        self.updates
+            .scan_prefix(index_prefix(key))
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    fn resolve(&self, index: sled::Iter) -> Result<Vec<StandardMarket>> {
+        let mut markets = Vec::new();
+        for key in index.values() {
+            if let Some(value) = self.markets.get(key?)? {
+                markets.push(serde_json::from_slice(&value)?);
+            }
+        }
+        Ok(markets)
+    }
+}
+
+#[async_trait]
+impl MarketStore for MarketRepository {
+    async fn upsert_market(&self, market: &StandardMarket) -> Result<()> {
+        self.upsert(market)
+    }
+
+    async fn get_market(&self, key: &str) -> Result<Option<StandardMarket>> {
+        self.get_by_key(key)
+    }
+
+    async fn markets_closing_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StandardMarket>> {
+        self.closing_between(from, to)
+    }
+
+    async fn markets_by_platform(&self, platform: &str) -> Result<Vec<StandardMarket>> {
+        self.by_platform(platform)
+    }
+
+    async fn markets_by_category(&self, category: &str) -> Result<Vec<StandardMarket>> {
+        self.by_category(category)
+    }
+
+    async fn append_updates(&self, key: &str, updates: &[Update]) -> Result<()> {
+        MarketRepository::append_updates(self, key, updates)
+    }
+
+    async fn updates(&self, key: &str) -> Result<Vec<Update>> {
+        MarketRepository::updates(self, key)
+    }
+}
+
+struct IndexKeys {
+    close_time: Option<Vec<u8>>,
+    platform: Vec<u8>,
+    category: Vec<u8>,
+}
+
+impl IndexKeys {
+    fn new(market: &StandardMarket, key: &str) -> Self {
+        let close_time = market.close_time().ok().map(|close_time| {
+            let mut index_key = sortable_timestamp(close_time).to_vec();
+            index_key.extend_from_slice(key.as_bytes());
+            index_key
+        });
+        let mut platform = index_prefix(market.platform());
+        platform.extend_from_slice(key.as_bytes());
+        let mut category = index_prefix(market.category());
+        category.extend_from_slice(key.as_bytes());
+        Self {
+            close_time,
+            platform,
+            category,
+        }
+    }
+}
+
+pub(super) fn index_prefix(value: &str) -> Vec<u8> {
+    let mut prefix = value.as_bytes().to_vec();
+    prefix.push(0);
+    prefix
+}
+
+fn sortable_timestamp(time: DateTime<Utc>) -> [u8; 8] {
+    sortable_seconds(time.timestamp())
+}
+
+/// Big-endian with the sign bit flipped so byte order matches time order.
+pub(super) fn sortable_seconds(seconds: i64) -> [u8; 8] {
+    ((seconds as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifold::ManifoldMarket;
+    use crate::metaculus::MetaculusMarket;
+
+    const MANIFOLD_MARKET: &str = include_str!("../../tests/fixtures/manifold_market.json");
+    const METACULUS_MARKET: &str = include_str!("../../tests/fixtures/metaculus_market.json");
+
+    fn markets() -> (StandardMarket, StandardMarket) {
+        let manifold: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        let metaculus: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        (
+            StandardMarket::try_from(manifold).unwrap(),
+            StandardMarket::try_from(metaculus).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_upsert_and_get() {
+        let repository = MarketRepository::temporary().unwrap();
+        let (manifold, _) = markets();
+        repository.upsert(&manifold).unwrap();
+
+        let stored = repository
+            .get("manifold", "9Ccsjc0fmbIb9g50p7SB")
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.key(), manifold.key());
+        assert!(repository.get("manifold", "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_secondary_indexes() {
+        let repository = MarketRepository::temporary().unwrap();
+        let (manifold, metaculus) = markets();
+        repository.upsert(&manifold).unwrap();
+        repository.upsert(&metaculus).unwrap();
+
+        assert_eq!(repository.by_platform("manifold").unwrap().len(), 1);
+        assert_eq!(repository.by_platform("metaculus").unwrap().len(), 1);
+        assert_eq!(repository.by_category("us-economics").unwrap().len(), 1);
+        assert!(repository.by_category("us").unwrap().is_empty());
+        assert_eq!(repository.all().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_closing_between() {
+        let repository = MarketRepository::temporary().unwrap();
+        let (manifold, metaculus) = markets();
+        repository.upsert(&manifold).unwrap();
+        repository.upsert(&metaculus).unwrap();
+
+        let from = DateTime::parse_from_rfc3339("2024-10-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let to = DateTime::parse_from_rfc3339("2024-11-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let closing = repository.closing_between(from, to).unwrap();
+        assert_eq!(closing.len(), 1);
+        assert_eq!(closing[0].platform(), "metaculus");
+    }
+
+    #[test]
+    fn test_upsert_replaces_index_entries() {
+        let repository = MarketRepository::temporary().unwrap();
+        let (manifold, _) = markets();
+        repository.upsert(&manifold).unwrap();
+        repository.upsert(&manifold).unwrap();
+
+        assert_eq!(repository.by_platform("manifold").unwrap().len(), 1);
+        assert_eq!(repository.by_category("us-economics").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_updates_ordered_by_time() {
+        let repository = MarketRepository::temporary().unwrap();
+        let (manifold, _) = markets();
+        repository.upsert(&manifold).unwrap();
+        let later = Update::new(DateTime::from_timestamp(1725148800, 0).unwrap(), 0.8);
+        let earlier = Update::new(DateTime::from_timestamp(1709251200, 0).unwrap(), 0.6);
+        repository
+            .append_updates(&manifold.key(), &[later.clone(), earlier.clone()])
+            .unwrap();
+
+        assert_eq!(
+            repository.updates(&manifold.key()).unwrap(),
+            vec![earlier, later]
+        );
+        assert!(repository.append_updates("manifold:missing", &[]).is_err());
+    }
+}
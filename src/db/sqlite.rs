@@ -0,0 +1,238 @@
+use super::model::{Market, StandardMarket, Update};
+use super::store::{MarketStore, Result};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
+use std::str::FromStr;
+
+const MARKET_COLUMNS: &str = "title, platform, platform_id, open_time, close_time, volume_usd, \
+     num_traders, category, kind, resolution, prob_midpoint, prob_close, prob_tma";
+
+/// `MarketStore` backed by SQLite, migrated from `migrations/` on connect.
+#[derive(Clone)]
+pub struct SqliteMarketStore {
+    pool: SqlitePool,
+}
+
+impl SqliteMarketStore {
+    /// Connects to a database url such as `sqlite://markets.db`, creating the file if needed.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        Self::migrate(pool).await
+    }
+
+    /// Store backed by a private in-memory database.
+    pub async fn in_memory() -> Result<Self> {
+        // Every in-memory connection is its own database, so keep exactly one.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+        Self::migrate(pool).await
+    }
+
+    async fn migrate(pool: SqlitePool) -> Result<Self> {
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    async fn select_markets(&self, filter: &str, value: &str) -> Result<Vec<StandardMarket>> {
+        let query = format!(
+            "SELECT {} FROM markets WHERE {} = ? ORDER BY close_time",
+            MARKET_COLUMNS, filter
+        );
+        Ok(sqlx::query_as::<_, StandardMarket>(&query)
+            .bind(value)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
+
+#[async_trait]
+impl MarketStore for SqliteMarketStore {
+    async fn upsert_market(&self, market: &StandardMarket) -> Result<()> {
+        let query = format!(
+            "INSERT INTO markets (id, {}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) \
+             ON CONFLICT (id) DO UPDATE SET \
+             title = excluded.title, open_time = excluded.open_time, \
+             close_time = excluded.close_time, volume_usd = excluded.volume_usd, \
+             num_traders = excluded.num_traders, category = excluded.category, \
+             kind = excluded.kind, resolution = excluded.resolution, prob_midpoint = excluded.prob_midpoint, \
+             prob_close = excluded.prob_close, prob_tma = excluded.prob_tma",
+            MARKET_COLUMNS
+        );
+        sqlx::query(&query)
+            .bind(market.key())
+            .bind(market.title())
+            .bind(market.platform())
+            .bind(market.platform_id())
+            .bind(market.open_time()?)
+            .bind(market.close_time()?)
+            .bind(market.volume_usd())
+            .bind(market.num_traders())
+            .bind(market.category())
+            .bind(market.kind())
+            .bind(market.resolution())
+            .bind(market.prob_midpoint())
+            .bind(market.prob_close())
+            .bind(market.prob_tma())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_market(&self, key: &str) -> Result<Option<StandardMarket>> {
+        Ok(self.select_markets("id", key).await?.into_iter().next())
+    }
+
+    async fn markets_closing_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StandardMarket>> {
+        let query = format!(
+            "SELECT {} FROM markets WHERE close_time >= ? AND close_time < ? ORDER BY close_time",
+            MARKET_COLUMNS
+        );
+        Ok(sqlx::query_as::<_, StandardMarket>(&query)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn markets_by_platform(&self, platform: &str) -> Result<Vec<StandardMarket>> {
+        self.select_markets("platform", platform).await
+    }
+
+    async fn markets_by_category(&self, category: &str) -> Result<Vec<StandardMarket>> {
+        self.select_markets("category", category).await
+    }
+
+    async fn append_updates(&self, key: &str, updates: &[Update]) -> Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        for update in updates {
+            sqlx::query(
+                "INSERT OR REPLACE INTO probability_updates (market_id, time, probability) \
+                 VALUES (?, ?, ?)",
+            )
+            .bind(key)
+            .bind(update.time)
+            .bind(update.probability)
+            .execute(&mut *transaction)
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn updates(&self, key: &str) -> Result<Vec<Update>> {
+        Ok(sqlx::query_as::<_, Update>(
+            "SELECT time, probability FROM probability_updates WHERE market_id = ? ORDER BY time",
+        )
+        .bind(key)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::model::MarketKind;
+    use crate::manifold::ManifoldMarket;
+    use crate::metaculus::MetaculusMarket;
+
+    const MANIFOLD_MARKET: &str = include_str!("../../tests/fixtures/manifold_market.json");
+    const METACULUS_MARKET: &str = include_str!("../../tests/fixtures/metaculus_market.json");
+
+    async fn store() -> (SqliteMarketStore, StandardMarket, StandardMarket) {
+        let store = SqliteMarketStore::in_memory().await.unwrap();
+        let manifold: ManifoldMarket = serde_json::from_str(MANIFOLD_MARKET).unwrap();
+        let metaculus: MetaculusMarket = serde_json::from_str(METACULUS_MARKET).unwrap();
+        let manifold = StandardMarket::try_from(manifold).unwrap();
+        let metaculus = StandardMarket::try_from(metaculus).unwrap();
+        store.upsert_market(&manifold).await.unwrap();
+        store.upsert_market(&metaculus).await.unwrap();
+        (store, manifold, metaculus)
+    }
+
+    #[tokio::test]
+    async fn test_upsert_and_query() {
+        let (store, manifold, _) = store().await;
+        store.upsert_market(&manifold).await.unwrap();
+
+        let stored = store.get_market(&manifold.key()).await.unwrap().unwrap();
+        assert_eq!(stored.key(), manifold.key());
+        assert_eq!(stored.close_time().unwrap(), manifold.close_time().unwrap());
+        assert_eq!(stored.prob_tma(), manifold.prob_tma());
+        assert_eq!(stored.kind(), MarketKind::Binary);
+        assert_eq!(
+            store.markets_by_platform("manifold").await.unwrap().len(),
+            1
+        );
+        assert_eq!(
+            store
+                .markets_by_category("us-economics")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(store
+            .get_market("manifold:missing")
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_closing_between() {
+        let (store, _, metaculus) = store().await;
+        let from = DateTime::parse_from_rfc3339("2024-10-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+        let to = DateTime::parse_from_rfc3339("2024-11-01T00:00:00Z")
+            .unwrap()
+            .to_utc();
+
+        let closing = store.markets_closing_between(from, to).await.unwrap();
+        assert_eq!(closing.len(), 1);
+        assert_eq!(closing[0].key(), metaculus.key());
+    }
+
+    #[tokio::test]
+    async fn test_updates_round_trip() {
+        let (store, manifold, _) = store().await;
+        let updates = manifold_updates();
+        store
+            .append_updates(&manifold.key(), &updates[1..])
+            .await
+            .unwrap();
+        store
+            .append_updates(&manifold.key(), &updates[..1])
+            .await
+            .unwrap();
+
+        assert_eq!(store.updates(&manifold.key()).await.unwrap(), updates);
+        // Updates must belong to a stored market.
+        assert!(store
+            .append_updates("manifold:missing", &updates)
+            .await
+            .is_err());
+    }
+
+    fn manifold_updates() -> Vec<Update> {
+        vec![
+            Update::new(DateTime::from_timestamp(1709251200, 0).unwrap(), 0.6),
+            Update::new(DateTime::from_timestamp(1725148800, 0).unwrap(), 0.8),
+        ]
+    }
+}
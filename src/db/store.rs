@@ -0,0 +1,28 @@
+use super::error::Error;
+use super::model::{StandardMarket, Update};
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Storage backend for standardized markets and their probability history.
+///
+/// Markets are addressed by `StandardMarket::key`, so the same key works with
+/// every backend.
+#[async_trait]
+pub trait MarketStore: Send + Sync {
+    async fn upsert_market(&self, market: &StandardMarket) -> Result<()>;
+    async fn get_market(&self, key: &str) -> Result<Option<StandardMarket>>;
+    /// Markets closing in `[from, to)`, ordered by close time.
+    async fn markets_closing_between(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<StandardMarket>>;
+    async fn markets_by_platform(&self, platform: &str) -> Result<Vec<StandardMarket>>;
+    async fn markets_by_category(&self, category: &str) -> Result<Vec<StandardMarket>>;
+    /// Adds probability updates for a stored market, replacing any at the same time.
+    async fn append_updates(&self, key: &str, updates: &[Update]) -> Result<()>;
+    /// Probability updates for a market, ordered by time.
+    async fn updates(&self, key: &str) -> Result<Vec<Update>>;
+}
@@ -0,0 +1,283 @@
+use super::error::Error;
+use super::repository::{index_prefix, sortable_seconds};
+use super::MarketsDb;
+use crate::types::{OutcomeSeries, OutcomeTick, Tick};
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+
+type Result<T> = std::result::Result<T, Error>;
+
+const TICKS_TREE: &str = "outcome_ticks";
+
+/// Append-only probability history, one series per (platform, market id, outcome).
+///
+/// Keys are the three series components, each `\0`-terminated, followed by the
+/// sortable tick timestamp, so a series is a contiguous, time-ordered range. A
+/// second tick at the same timestamp replaces the first.
+#[derive(Clone)]
+pub struct TimeSeriesStore {
+    ticks: sled::Tree,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Resolution {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Resolution::Minute => 60,
+            Resolution::Hour => 60 * 60,
+            Resolution::Day => 60 * 60 * 24,
+        }
+    }
+}
+
+/// OHLC of probability over one bucket. `volume` is the last volume seen in the bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// Drops ticks older than `max_age`, for one platform or, with `platform: None`, all of them.
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    pub platform: Option<String>,
+    pub max_age: TimeDelta,
+}
+
+impl TimeSeriesStore {
+    pub fn open(db: &MarketsDb) -> Result<Self> {
+        Ok(Self {
+            ticks: db.open_tree(TICKS_TREE)?,
+        })
+    }
+
+    /// Store backed by a throwaway database, removed on drop.
+    pub fn temporary() -> Result<Self> {
+        let db = sled::Config::new().temporary(true).open()?;
+        Self::open(&db)
+    }
+
+    pub fn append(&self, tick: &OutcomeTick) -> Result<()> {
+        let mut key = series_prefix(&tick.platform, &tick.market_id, &tick.outcome);
+        key.extend_from_slice(&sortable_seconds(tick.tick.timestamp));
+        self.ticks.insert(key, serde_json::to_vec(&tick.tick)?)?;
+        Ok(())
+    }
+
+    /// Ticks with timestamps in `[from, to)`, ordered by time.
+    pub fn range(
+        &self,
+        platform: &str,
+        market_id: &str,
+        outcome: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Tick>> {
+        let prefix = series_prefix(platform, market_id, outcome);
+        let mut start = prefix.clone();
+        start.extend_from_slice(&sortable_seconds(from));
+        let mut end = prefix;
+        end.extend_from_slice(&sortable_seconds(to));
+        self.ticks
+            .range(start..end)
+            .values()
+            .map(|value| Ok(serde_json::from_slice(&value?)?))
+            .collect()
+    }
+
+    /// Every outcome recorded for a market.
+    pub fn outcome_series(&self, platform: &str, market_id: &str) -> Result<OutcomeSeries> {
+        let mut prefix = index_prefix(platform);
+        prefix.extend_from_slice(&index_prefix(market_id));
+        let mut series = OutcomeSeries::new();
+        for entry in self.ticks.scan_prefix(&prefix) {
+            let (key, value) = entry?;
+            // Outcome sits between the market prefix and its `\0` + 8 byte timestamp.
+            let outcome = &key[prefix.len()..key.len() - 9];
+            series
+                .entry(String::from_utf8_lossy(outcome).into_owned())
+                .or_default()
+                .push(serde_json::from_slice(&value)?);
+        }
+        Ok(series)
+    }
+
+    pub fn candles(
+        &self,
+        platform: &str,
+        market_id: &str,
+        outcome: &str,
+        resolution: Resolution,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<Candle>> {
+        let ticks = self.range(platform, market_id, outcome, from, to)?;
+        Ok(downsample(&ticks, resolution))
+    }
+
+    /// Removes expired ticks and returns how many were dropped. Each series follows
+    /// the first policy that names its platform, else the first platform-wide one.
+    pub fn apply_retention(&self, policies: &[RetentionPolicy], now: i64) -> Result<usize> {
+        let mut removed = 0;
+        for entry in self.ticks.iter() {
+            let (key, value) = entry?;
+            let platform_end = key.iter().position(|byte| *byte == 0).unwrap_or(0);
+            let platform = String::from_utf8_lossy(&key[..platform_end]);
+            let policy = policies
+                .iter()
+                .find(|policy| policy.platform.as_deref() == Some(platform.as_ref()))
+                .or_else(|| policies.iter().find(|policy| policy.platform.is_none()));
+            let Some(policy) = policy else {
+                continue;
+            };
+            let tick: Tick = serde_json::from_slice(&value)?;
+            if tick.timestamp < now - policy.max_age.num_seconds() {
+                self.ticks.remove(key)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Buckets time-ordered ticks into candles aligned to multiples of the resolution.
+pub fn downsample(ticks: &[Tick], resolution: Resolution) -> Vec<Candle> {
+    let width = resolution.seconds();
+    let mut candles: Vec<Candle> = Vec::new();
+    for tick in ticks {
+        let start = tick.timestamp - tick.timestamp.rem_euclid(width);
+        match candles.last_mut() {
+            Some(candle) if candle.start == start => {
+                candle.high = candle.high.max(tick.probability);
+                candle.low = candle.low.min(tick.probability);
+                candle.close = tick.probability;
+                candle.volume = tick.volume;
+            }
+            _ => candles.push(Candle {
+                start,
+                open: tick.probability,
+                high: tick.probability,
+                low: tick.probability,
+                close: tick.probability,
+                volume: tick.volume,
+            }),
+        }
+    }
+    candles
+}
+
+fn series_prefix(platform: &str, market_id: &str, outcome: &str) -> Vec<u8> {
+    let mut prefix = index_prefix(platform);
+    prefix.extend_from_slice(&index_prefix(market_id));
+    prefix.extend_from_slice(&index_prefix(outcome));
+    prefix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(outcome: &str, timestamp: i64, probability: f64) -> OutcomeTick {
+        OutcomeTick {
+            platform: "manifold".to_string(),
+            market_id: "9Ccsjc0fmbIb9g50p7SB".to_string(),
+            outcome: outcome.to_string(),
+            tick: Tick {
+                timestamp,
+                probability,
+                volume: timestamp as f64,
+            },
+        }
+    }
+
+    #[test]
+    fn test_range_is_ordered_and_scoped() {
+        let store = TimeSeriesStore::temporary().unwrap();
+        for (timestamp, probability) in [(300, 0.7), (100, 0.5), (200, 0.6)] {
+            store.append(&tick("YES", timestamp, probability)).unwrap();
+        }
+        store.append(&tick("NO", 150, 0.5)).unwrap();
+
+        let ticks = store
+            .range("manifold", "9Ccsjc0fmbIb9g50p7SB", "YES", 100, 300)
+            .unwrap();
+        let timestamps: Vec<i64> = ticks.iter().map(|tick| tick.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 200]);
+
+        let series = store
+            .outcome_series("manifold", "9Ccsjc0fmbIb9g50p7SB")
+            .unwrap();
+        assert_eq!(series["YES"].len(), 3);
+        assert_eq!(series["NO"].len(), 1);
+    }
+
+    #[test]
+    fn test_downsample_ohlc() {
+        let ticks: Vec<Tick> = [(0, 0.5), (30, 0.7), (45, 0.4), (59, 0.6), (60, 0.9)]
+            .iter()
+            .map(|(timestamp, probability)| tick("YES", *timestamp, *probability).tick)
+            .collect();
+
+        let candles = downsample(&ticks, Resolution::Minute);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(
+            candles[0],
+            Candle {
+                start: 0,
+                open: 0.5,
+                high: 0.7,
+                low: 0.4,
+                close: 0.6,
+                volume: 59.0,
+            }
+        );
+        assert_eq!(candles[1].start, 60);
+        assert_eq!(downsample(&ticks, Resolution::Hour).len(), 1);
+    }
+
+    #[test]
+    fn test_retention() {
+        let store = TimeSeriesStore::temporary().unwrap();
+        store.append(&tick("YES", 0, 0.5)).unwrap();
+        store.append(&tick("YES", 5_000, 0.6)).unwrap();
+        let mut other = tick("YES", 0, 0.5);
+        other.platform = "metaculus".to_string();
+        store.append(&other).unwrap();
+
+        let policies = [
+            RetentionPolicy {
+                platform: Some("metaculus".to_string()),
+                max_age: TimeDelta::days(365),
+            },
+            RetentionPolicy {
+                platform: None,
+                max_age: TimeDelta::hours(1),
+            },
+        ];
+        assert_eq!(store.apply_retention(&policies, 6_000).unwrap(), 1);
+        assert_eq!(
+            store
+                .range("manifold", "9Ccsjc0fmbIb9g50p7SB", "YES", 0, i64::MAX)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            store
+                .range("metaculus", "9Ccsjc0fmbIb9g50p7SB", "YES", 0, i64::MAX)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
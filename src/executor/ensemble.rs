@@ -0,0 +1,511 @@
+use super::executor::{ask_forecast, market_kind, on_market, News, Promptor};
+use super::llm::LlmProvider;
+use crate::db::ledger::Calibration;
+use crate::model::forecast::{prompt_hash, Forecast};
+use crate::model::Market;
+use futures_util::future::join_all;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Kept off 0 and 1 so every forecast has finite log-odds.
+const LOG_ODDS_BOUND: f64 = 0.001;
+/// Brier score of always answering 50%, assumed for members without a track record.
+const UNINFORMED_BRIER: f64 = 0.25;
+/// Floor on the Brier score behind a weight, so a lucky streak cannot dominate.
+const MIN_WEIGHT_BRIER: f64 = 0.01;
+/// How long a member gets to answer before the ensemble goes on without it.
+const DEFAULT_MEMBER_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Rewordings of the superforecaster instructions, so members of an ensemble
+/// reason along different lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptVariant {
+    Superforecaster,
+    /// Anchors on the base rate of similar events before adjusting.
+    BaseRate,
+    /// Argues for the other outcome first, against overconfidence.
+    Contrarian,
+}
+
+impl PromptVariant {
+    /// Recorded as the forecast's prompt version.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PromptVariant::Superforecaster => "superforecaster",
+            PromptVariant::BaseRate => "base_rate",
+            PromptVariant::Contrarian => "contrarian",
+        }
+    }
+
+    /// Put ahead of the superforecaster instructions.
+    pub fn preamble(&self) -> Option<&'static str> {
+        match self {
+            PromptVariant::Superforecaster => None,
+            PromptVariant::BaseRate => Some(
+                "Before anything else, name the reference class of this question and how \
+                often events in it happened. Start from that rate and move away from it only \
+                as far as the evidence forces you to.",
+            ),
+            PromptVariant::Contrarian => Some(
+                "Before anything else, write the strongest case that the outcome will not \
+                happen as asked. Weigh it honestly against the case for it before settling \
+                on a probability.",
+            ),
+        }
+    }
+}
+
+/// One model answering with one prompt variant.
+#[derive(Clone)]
+pub struct Member {
+    pub llm: Arc<dyn LlmProvider>,
+    pub variant: PromptVariant,
+}
+
+/// How member forecasts combine into one probability.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Aggregation {
+    Mean,
+    Median,
+    /// Drops `trim` of the forecasts at each end before averaging, 0.1 for a 10%
+    /// trimmed mean.
+    TrimmedMean {
+        trim: f64,
+    },
+    /// Mean log-odds scaled by `factor`. Above 1 pushes away from 50%, making up for
+    /// averaging forecasts that each hold only part of the evidence.
+    ExtremizedLogOdds {
+        factor: f64,
+    },
+    /// Mean weighted by the inverse historical Brier score of each (model, prompt
+    /// version). Members missing from `weights` count as uninformed.
+    BrierWeighted {
+        weights: BTreeMap<(String, String), f64>,
+    },
+}
+
+impl Aggregation {
+    /// Weights from the ledger's calibration, pooled over categories.
+    pub fn brier_weighted(calibration: &[Calibration]) -> Self {
+        let mut pooled: BTreeMap<(String, String), (f64, usize)> = BTreeMap::new();
+        for row in calibration {
+            let (brier, count) = pooled
+                .entry((row.model.clone(), row.prompt_version.clone()))
+                .or_default();
+            *brier += row.brier * row.count as f64;
+            *count += row.count;
+        }
+        let weights = pooled
+            .into_iter()
+            .filter(|(_, (_, count))| *count > 0)
+            .map(|(member, (brier, count))| (member, brier_weight(brier / count as f64)))
+            .collect();
+        Aggregation::BrierWeighted { weights }
+    }
+
+    /// Recorded as the ensemble forecast's prompt version.
+    pub fn name(&self) -> String {
+        match self {
+            Aggregation::Mean => "ensemble_mean".to_string(),
+            Aggregation::Median => "ensemble_median".to_string(),
+            Aggregation::TrimmedMean { trim } => format!("ensemble_trimmed_mean_{}", trim),
+            Aggregation::ExtremizedLogOdds { factor } => format!("ensemble_extremized_{}", factor),
+            Aggregation::BrierWeighted { .. } => "ensemble_brier_weighted".to_string(),
+        }
+    }
+
+    /// Combines the members' probabilities of one outcome, `None` without any.
+    pub fn aggregate(&self, forecasts: &[Forecast]) -> Option<f64> {
+        if forecasts.is_empty() {
+            return None;
+        }
+        let mut probabilities: Vec<f64> = forecasts.iter().map(|f| f.probability).collect();
+        probabilities.sort_by(f64::total_cmp);
+        let probability = match self {
+            Aggregation::Mean => mean(&probabilities),
+            Aggregation::Median => median(&probabilities),
+            Aggregation::TrimmedMean { trim } => {
+                let cut = (probabilities.len() as f64 * trim.clamp(0.0, 0.5)) as usize;
+                if 2 * cut >= probabilities.len() {
+                    median(&probabilities)
+                } else {
+                    mean(&probabilities[cut..probabilities.len() - cut])
+                }
+            }
+            Aggregation::ExtremizedLogOdds { factor } => {
+                let log_odds: Vec<f64> = probabilities.iter().map(|p| logit(*p)).collect();
+                sigmoid(factor * mean(&log_odds))
+            }
+            Aggregation::BrierWeighted { weights } => {
+                let uninformed = brier_weight(UNINFORMED_BRIER);
+                let (weighted, total) =
+                    forecasts
+                        .iter()
+                        .fold((0.0, 0.0), |(weighted, total), forecast| {
+                            let member = (forecast.model.clone(), forecast.prompt_version.clone());
+                            let weight = weights.get(&member).copied().unwrap_or(uninformed);
+                            (weighted + weight * forecast.probability, total + weight)
+                        });
+                weighted / total
+            }
+        };
+        Some(probability)
+    }
+}
+
+/// Asks every member the same question in parallel and combines their answers.
+pub struct Ensemble {
+    members: Vec<Member>,
+    aggregation: Aggregation,
+    promptor: Promptor,
+    member_timeout: Duration,
+}
+
+/// The combined forecast, with its `dispersion` set, and the member forecasts
+/// it came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnsembleForecast {
+    pub forecast: Forecast,
+    pub members: Vec<Forecast>,
+}
+
+impl Ensemble {
+    pub fn new(members: Vec<Member>, aggregation: Aggregation) -> Self {
+        Self {
+            members,
+            aggregation,
+            promptor: Promptor {},
+            member_timeout: DEFAULT_MEMBER_TIMEOUT,
+        }
+    }
+
+    /// Gives each member `timeout` to answer instead of the default two minutes.
+    pub fn with_member_timeout(mut self, timeout: Duration) -> Self {
+        self.member_timeout = timeout;
+        self
+    }
+
+    /// Every variant on every model, in that order.
+    pub fn grid(llms: &[Arc<dyn LlmProvider>], variants: &[PromptVariant]) -> Vec<Member> {
+        llms.iter()
+            .flat_map(|llm| {
+                variants.iter().map(|variant| Member {
+                    llm: llm.clone(),
+                    variant: *variant,
+                })
+            })
+            .collect()
+    }
+
+    /// Forecasts `outcome` of `question` given `prompt`, the market and news context
+    /// an executor built, tying the forecasts to `market` when the question is one.
+    /// Members that fail, time out or answer another outcome are left out; it is an
+    /// error when none are left.
+    pub async fn forecast<M: Market + Sync>(
+        &self,
+        question: &str,
+        outcome: &str,
+        market: Option<&M>,
+        news: &[News],
+        prompt: &str,
+    ) -> anyhow::Result<EnsembleForecast> {
+        let kind = market_kind(market);
+        let answers = join_all(self.members.iter().map(|member| async move {
+            let instructions = self
+                .promptor
+                .instructions(member.variant, question, outcome, kind)
+                .await;
+            let answer = ask_forecast(
+                member.llm.as_ref(),
+                question,
+                news,
+                member.variant,
+                &instructions,
+                prompt,
+            );
+            match tokio::time::timeout(self.member_timeout, answer).await {
+                Ok(forecast) => forecast,
+                Err(_) => Err(anyhow::anyhow!(
+                    "{} took longer than {:?}",
+                    member.llm.model(),
+                    self.member_timeout
+                )),
+            }
+        }))
+        .await;
+        let mut members = Vec::new();
+        for answer in answers {
+            match answer.map(|forecast| aligned(forecast, outcome)) {
+                Ok(Some(forecast)) => members.push(match market {
+                    Some(market) => on_market(forecast, market),
+                    None => forecast,
+                }),
+                Ok(None) => tracing::debug!("ensemble member answered another outcome"),
+                Err(e) => tracing::warn!("ensemble member failed: {}", e),
+            }
+        }
+        let probability = self
+            .aggregation
+            .aggregate(&members)
+            .ok_or_else(|| anyhow::anyhow!("no ensemble member forecast {:?}", question))?;
+        let probabilities: Vec<f64> = members.iter().map(|member| member.probability).collect();
+        let hashes: Vec<&str> = members
+            .iter()
+            .map(|member| member.prompt_hash.as_str())
+            .collect();
+        let forecast = Forecast {
+            question: question.to_string(),
+            platform: market
+                .map(|market| market.platform().to_string())
+                .unwrap_or_default(),
+            platform_id: market.map(|market| market.platform_id()),
+            outcome: outcome.to_string(),
+            probability,
+            rationale: members
+                .iter()
+                .map(|member| {
+                    format!(
+                        "[{} {}] {}",
+                        member.model, member.prompt_version, member.rationale
+                    )
+                })
+                .collect::<Vec<String>>()
+                .join("\n\n"),
+            model: ensemble_model(&members),
+            prompt_version: self.aggregation.name(),
+            prompt_hash: prompt_hash(&hashes.join(","), prompt),
+            news_sources: news.iter().map(|article| article.url.clone()).collect(),
+            timestamp: chrono::Utc::now(),
+            market_probability: market.and_then(|market| market.outcome_probability(outcome)),
+            category: market
+                .map(|market| market.category().to_string())
+                .unwrap_or_default(),
+            dispersion: Some(std_dev(&probabilities)),
+        };
+        Ok(EnsembleForecast { forecast, members })
+    }
+}
+
+/// `forecast` restated as a probability of `outcome`, flipping a yes/no answer for
+/// the other side. `None` when it is about some other outcome.
+fn aligned(forecast: Forecast, outcome: &str) -> Option<Forecast> {
+    if forecast.outcome.eq_ignore_ascii_case(outcome) {
+        return Some(forecast);
+    }
+    let binary = |side: &str| side.eq_ignore_ascii_case("yes") || side.eq_ignore_ascii_case("no");
+    if binary(&forecast.outcome) && binary(outcome) {
+        return Some(Forecast {
+            outcome: outcome.to_string(),
+            probability: 1.0 - forecast.probability,
+            ..forecast
+        });
+    }
+    None
+}
+
+/// The distinct member models, as `ensemble(a,b)`.
+fn ensemble_model(members: &[Forecast]) -> String {
+    let mut models: Vec<&str> = members.iter().map(|member| member.model.as_str()).collect();
+    models.sort_unstable();
+    models.dedup();
+    format!("ensemble({})", models.join(","))
+}
+
+fn brier_weight(brier: f64) -> f64 {
+    1.0 / brier.max(MIN_WEIGHT_BRIER)
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Median of sorted `values`.
+fn median(values: &[f64]) -> f64 {
+    let middle = values.len() / 2;
+    if values.len() % 2 == 0 {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+/// Population standard deviation, the spread of the members' probabilities.
+fn std_dev(values: &[f64]) -> f64 {
+    let mean = mean(values);
+    (values
+        .iter()
+        .map(|value| (value - mean).powi(2))
+        .sum::<f64>()
+        / values.len() as f64)
+        .sqrt()
+}
+
+fn logit(probability: f64) -> f64 {
+    let probability = probability.clamp(LOG_ODDS_BOUND, 1.0 - LOG_ODDS_BOUND);
+    (probability / (1.0 - probability)).ln()
+}
+
+fn sigmoid(log_odds: f64) -> f64 {
+    1.0 / (1.0 + (-log_odds).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::llm::{ChatMessage, Reply, ScriptedProvider, Tool};
+    use crate::manifold::ManifoldMarket;
+
+    fn member(model: &str, prompt_version: &str, probability: f64) -> Forecast {
+        Forecast {
+            model: model.to_string(),
+            prompt_version: prompt_version.to_string(),
+            ..Forecast::fixture(probability)
+        }
+    }
+
+    #[test]
+    fn test_aggregation_rules() {
+        let members: Vec<Forecast> = [0.2, 0.6, 0.7, 0.8, 0.95]
+            .iter()
+            .map(|p| member("gpt-4o", "superforecaster", *p))
+            .collect();
+        let close = |aggregation: Aggregation, expected: f64| {
+            let probability = aggregation.aggregate(&members).unwrap();
+            assert!((probability - expected).abs() < 1e-9, "{:?}", aggregation);
+        };
+        close(Aggregation::Mean, 0.65);
+        close(Aggregation::Median, 0.7);
+        close(Aggregation::TrimmedMean { trim: 0.2 }, 0.7);
+        close(Aggregation::TrimmedMean { trim: 0.5 }, 0.7);
+        let log_odds = members.iter().map(|m| logit(m.probability)).sum::<f64>() / 5.0;
+        close(
+            Aggregation::ExtremizedLogOdds { factor: 1.0 },
+            sigmoid(log_odds),
+        );
+        let extremized = Aggregation::ExtremizedLogOdds { factor: 2.0 };
+        assert!(extremized.aggregate(&members).unwrap() > sigmoid(log_odds));
+        assert_eq!(Aggregation::Mean.aggregate(&[]), None);
+    }
+
+    #[test]
+    fn test_brier_weights() {
+        let row = |prompt_version: &str, brier: f64| Calibration {
+            model: "gpt-4o".to_string(),
+            prompt_version: prompt_version.to_string(),
+            category: "finance".to_string(),
+            count: 10,
+            brier,
+            log: 0.0,
+            relative_brier: None,
+            relative_log: None,
+            bins: Vec::new(),
+        };
+        let calibration = [row("base_rate", 0.05), row("contrarian", 0.2)];
+        let aggregation = Aggregation::brier_weighted(&calibration);
+        let members = [
+            member("gpt-4o", "base_rate", 0.9),
+            member("gpt-4o", "contrarian", 0.4),
+            member("llama", "base_rate", 0.1),
+        ];
+        // Weights 20, 5 and the uninformed 4.
+        let expected = (20.0 * 0.9 + 5.0 * 0.4 + 4.0 * 0.1) / 29.0;
+        let probability = aggregation.aggregate(&members).unwrap();
+        assert!((probability - expected).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_ensemble_forecast() {
+        let answer = |outcome: &str, probability: f64| {
+            format!(
+                r#"{{"outcome": "{}", "probability": {}, "rationale": "r"}}"#,
+                outcome, probability
+            )
+        };
+        let first: Arc<dyn LlmProvider> = Arc::new(ScriptedProvider::texts(&[
+            &answer("yes", 0.6),
+            &answer("no", 0.2),
+        ]));
+        let second: Arc<dyn LlmProvider> = Arc::new(ScriptedProvider::texts(&[
+            &answer("yes", 0.7),
+            &answer("Labour", 0.9),
+        ]));
+        let members = Ensemble::grid(
+            &[first, second],
+            &[PromptVariant::Superforecaster, PromptVariant::Contrarian],
+        );
+        assert_eq!(members.len(), 4);
+        let ensemble = Ensemble::new(members, Aggregation::Median);
+
+        let market: ManifoldMarket =
+            serde_json::from_str(include_str!("../../tests/fixtures/manifold_market.json"))
+                .unwrap();
+        let result = ensemble
+            .forecast("More launches?", "yes", Some(&market), &[], "prompt")
+            .await
+            .unwrap();
+        // The "no" answer counts as 80% yes, the off-topic one is left out.
+        let mut probabilities: Vec<f64> = result.members.iter().map(|m| m.probability).collect();
+        probabilities.sort_by(f64::total_cmp);
+        assert_eq!(probabilities.len(), 3);
+        assert!((probabilities[2] - 0.8).abs() < 1e-12);
+        assert_eq!(result.forecast.probability, 0.7);
+        assert_eq!(result.forecast.model, "ensemble(scripted)");
+        assert_eq!(result.forecast.prompt_version, "ensemble_median");
+        let dispersion = result.forecast.dispersion.unwrap();
+        assert!((dispersion - std_dev(&[0.6, 0.8, 0.7])).abs() < 1e-12);
+        assert!(result
+            .members
+            .iter()
+            .any(|member| member.prompt_version == "contrarian"));
+        assert_eq!(result.forecast.platform, "manifold");
+        assert_eq!(result.forecast.platform_id, Some(market.id.clone()));
+        assert_eq!(result.forecast.market_probability, market.probability);
+        assert!(result
+            .members
+            .iter()
+            .all(|member| member.platform_id == result.forecast.platform_id));
+    }
+
+    /// Never answers.
+    struct Stalled;
+
+    #[async_trait::async_trait]
+    impl LlmProvider for Stalled {
+        fn model(&self) -> &str {
+            "stalled"
+        }
+
+        async fn call_tools(&self, _: &[ChatMessage], _: &[Tool]) -> anyhow::Result<Reply> {
+            std::future::pending().await
+        }
+
+        async fn structured(
+            &self,
+            _: &[ChatMessage],
+            _: &str,
+            _: &serde_json::Value,
+        ) -> anyhow::Result<serde_json::Value> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ensemble_member_timeout() {
+        let answer = r#"{"outcome": "yes", "probability": 0.6, "rationale": "r"}"#;
+        let answering: Arc<dyn LlmProvider> = Arc::new(ScriptedProvider::texts(&[answer]));
+        let members = Ensemble::grid(&[answering, Arc::new(Stalled)], &[PromptVariant::BaseRate]);
+        let ensemble = Ensemble::new(members, Aggregation::Mean)
+            .with_member_timeout(Duration::from_millis(50));
+
+        let result = ensemble
+            .forecast::<ManifoldMarket>("More launches?", "yes", None, &[], "prompt")
+            .await
+            .unwrap();
+        assert_eq!(result.members.len(), 1);
+        assert_eq!(result.forecast.probability, 0.6);
+        assert_eq!(result.forecast.platform_id, None);
+    }
+}
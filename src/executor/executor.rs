@@ -1,25 +1,22 @@
+use super::ensemble::{Ensemble, PromptVariant};
+use super::llm::{ChatMessage, LlmProvider, OpenAiProvider};
 use crate::admin::listener::MarketUpdateRcv;
+use crate::api::metaculus::{ForecastPayload, Prediction, SubmittedForecast};
 use crate::api::{self, *};
-use crate::manifold::ManifoldMarket;
+use crate::manifold::{GroupIndex, ManifoldMarket};
+use crate::model::forecast::{prompt_hash, Forecast, ForecastAnswer};
+use crate::model::metaculus::{Question, Type};
+use crate::model::{Market as _, MarketKind};
 use crate::polymarket::PolymarketEvent;
 use crate::types::*;
 use async_openai::types::realtime::{ConversationItemCreateEvent, Item, ResponseCreateEvent};
-use async_openai::types::{CreateMessageRequestArgs, CreateRunRequestArgs};
-use async_openai::{
-    config::OpenAIConfig,
-    types::{
-        AssistantStreamEvent, CreateAssistantRequestArgs, CreateThreadRequest, MessageContent,
-        MessageDeltaContent, MessageRole, RunObject, RunStatus, SubmitToolOutputsRunRequest,
-        ToolsOutputs,
-    },
-    Client,
-};
 use axum::async_trait;
-use futures_util::StreamExt;
 use qdrant_client::qdrant::PointStruct;
 use qdrant_client::Qdrant;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, RwLock};
 use tokio::io::AsyncReadExt;
+use tokio::sync::OnceCell;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
@@ -194,7 +191,42 @@ Your answer will be used to use the tool so it must be very concise and make sur
         prompt.to_string()
     }
 
-    async fn superforecaster(&self, question: &str, outcome: &str) -> String {
+    /// The answer format a forecast of a `kind` market should follow.
+    pub fn response_format(&self, kind: MarketKind, question: &str, outcome: &str) -> String {
+        match kind {
+            MarketKind::Binary => format!(
+                "The question {}; has a likelihood (float)% for outcome of {}.",
+                question, outcome
+            ),
+            MarketKind::MultipleChoice => format!(
+                "The question {}; has a likelihood (float)% for answer (str).
+        Give one such line per answer; the likelihoods must sum to 100%.",
+                question
+            ),
+            MarketKind::Numeric => format!(
+                "The question {}; has a median estimate of (float),
+        with a 90% interval from (float) to (float).",
+                question
+            ),
+        }
+    }
+
+    /// The superforecaster instructions with the preamble of `variant`, if it has one.
+    pub async fn instructions(
+        &self,
+        variant: PromptVariant,
+        question: &str,
+        outcome: &str,
+        kind: MarketKind,
+    ) -> String {
+        let instructions = self.superforecaster(question, outcome, kind).await;
+        match variant.preamble() {
+            Some(preamble) => format!("{}\n\n{}", preamble, instructions),
+            None => instructions,
+        }
+    }
+
+    async fn superforecaster(&self, question: &str, outcome: &str, kind: MarketKind) -> String {
         format!(" You are a Superforecaster tasked with correctly predicting the likelihood of events.
         Use the following systematic process to develop an accurate prediction for the following
         question={} and outcome={} combination. 
@@ -224,7 +256,7 @@ Your answer will be used to use the tool so it must be very concise and make sur
 
         Give your response in the following format:
 
-        The question {}; has a likelihood (float)% for outcome of (str).", question, outcome, outcome, question).to_string()
+        {}", question, outcome, outcome, self.response_format(kind, question, outcome)).to_string()
     }
 }
 
@@ -232,8 +264,15 @@ pub type MarketBundle = Vec<crate::Market>;
 
 #[async_trait]
 pub trait Executor<M>: Send + Sync {
-    async fn init(&self, question: &str, outcome: &str, tags: Vec<String>) -> anyhow::Result<()>;
-    async fn execute(&self, market: M) -> anyhow::Result<()>;
+    /// Forecasts `outcome` of `question` from markets and news found for `tags`.
+    async fn init(
+        &self,
+        question: &str,
+        outcome: &str,
+        tags: Vec<String>,
+    ) -> anyhow::Result<Forecast>;
+    /// Acts on a market update, returning the forecast it led to, if any.
+    async fn execute(&self, market: M) -> anyhow::Result<Option<Forecast>>;
 }
 
 
@@ -272,6 +311,9 @@ pub trait Executor<M>: Send + Sync {
 pub struct PolymarketExecutor {
     platform: Arc<api::polymarket::PolymarketPlatform>,
     promptor: Promptor,
+    llm: Arc<dyn LlmProvider>,
+    ensemble: Option<Arc<Ensemble>>,
+    news_key: Option<String>,
     //ExecutorBuilder<Self>
 }
 
@@ -285,8 +327,30 @@ impl PolymarketExecutor {
         Self {
             platform,
             promptor,
+            llm: Arc::new(OpenAiProvider::from_env()),
+            ensemble: None,
+            news_key: std::env::var("TAVILIY_API_KEY").ok(),
         }
     }
+
+    /// Prompts `llm` instead of the provider configured by the environment.
+    pub fn with_llm(mut self, llm: Arc<dyn LlmProvider>) -> Self {
+        self.llm = llm;
+        self
+    }
+
+    /// Forecasts with `ensemble` instead of the one provider.
+    pub fn with_ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.ensemble = Some(Arc::new(ensemble));
+        self
+    }
+
+    /// Searches news with `news_key` instead of `TAVILIY_API_KEY`; `None` forecasts
+    /// without news.
+    pub fn with_news_key(mut self, news_key: Option<String>) -> Self {
+        self.news_key = news_key;
+        self
+    }
 }
 
 #[async_trait]
@@ -297,15 +361,27 @@ impl Executor<Market> for PolymarketExecutor {
         outcome: &str,
         tags: Vec<String>,
         //<'a>,
-    ) -> anyhow::Result<()> {
-        let platform = api::polymarket::PolymarketPlatform::from(PlatformBuilder::default());
-        let news = lookup_news(question, outcome).await.unwrap();
+    ) -> anyhow::Result<Forecast> {
+        let platform = &self.platform;
+        let mut news = lookup_news(self.news_key.as_deref(), question, outcome)
+            .await
+            .unwrap_or_default();
         //todo: Pare down news to only the relevant information
-        let trimmed_news = news.iter().take(8).collect::<Vec<&String>>();
+        news.truncate(8);
+        let trimmed_news = news
+            .iter()
+            .map(|article| &article.content)
+            .collect::<Vec<&String>>();
         // tracing::debug!("News: {:?}", news);
         tracing::debug!("Trimmed News: {:?}", trimmed_news);
         let mut trimmed_markets: Vec<serde_json::Value> = Vec::new();
-        let initial_events = platform.fetch_events(Some(100), 20).await.unwrap();
+        let initial_events = platform.fetch_events(Some(100), 20).await?;
+        let market = find_market(
+            initial_events
+                .iter()
+                .flat_map(|event| event.markets.iter().cloned()),
+            question,
+        );
         initial_events.iter().for_each(|event| {
             tracing::debug!("Initial event: {:?}", event);
             let market_summarized = parse_polymarket_event(event.clone()).unwrap();
@@ -316,108 +392,28 @@ impl Executor<Market> for PolymarketExecutor {
             .take(5)
             .collect::<Vec<&serde_json::Value>>();
         // tracing::debug!("Trimmed Market Data: {:?}", trimmed_market_data);
-        let query = [("limit", "1")];
-        let client = async_openai::Client::new();
         let prompt = self.promptor.prompts_polymarket_filter(
             trimmed_market_data,
             trimmed_news,
             question,
             outcome,
         );
-
-        let assistant_request = CreateAssistantRequestArgs::default()
-            .instructions(
-                self.promptor
-                    .superforecaster(question, outcome)
-                    .await
-                    .to_string(),
-            )
-            .model("gpt-4o")
-            .build()?;
-        let assistant = client.assistants().create(assistant_request).await?;
-        let assistant_id = assistant.id.clone();
-        let thread = client
-            .threads()
-            .create(CreateThreadRequest::default())
-            .await?;
-        let message = CreateMessageRequestArgs::default()
-            .role(MessageRole::User)
-            .content(prompt.clone())
-            .build()?;
-        let _message = client
-            .threads()
-            .messages(&thread.id)
-            .create(message)
-            .await?;
-        let run_request = CreateRunRequestArgs::default()
-            .assistant_id(assistant_id)
-            .build()?;
-        let mut run = client
-            .threads()
-            .runs(&thread.id)
-            .create(run_request)
-            .await?;
-        loop {
-            match run.status {
-                RunStatus::Completed => {
-                    let messages = client.threads().messages(&thread.id).list(&query).await?;
-                    for message_obj in messages.data {
-                        let message_contents = message_obj.content;
-                        for message_content in message_contents {
-                            match message_content {
-                                MessageContent::Text(text) => {
-                                    let text_data = text.text;
-                                    let annotations = text_data.annotations;
-                                    println!("{}", text_data.value);
-                                    println!("{annotations:?}");
-                                }
-                                MessageContent::ImageFile(_) | MessageContent::ImageUrl(_) => {
-                                    eprintln!("Images not supported on terminal");
-                                }
-                                MessageContent::Refusal(refusal) => {
-                                    println!("{refusal:?}");
-                                }
-                            }
-                        }
-                    }
-                    break;
-                }
-                RunStatus::Failed => {
-                    println!("> Run Failed: {:#?}", run);
-                    break;
-                }
-                RunStatus::Queued => {
-                    println!("> Run Queued");
-                }
-                RunStatus::Cancelling => {
-                    println!("> Run Cancelling");
-                }
-                RunStatus::Cancelled => {
-                    println!("> Run Cancelled");
-                    break;
-                }
-                RunStatus::Expired => {
-                    println!("> Run Expired");
-                    break;
-                }
-                RunStatus::RequiresAction => {
-                    println!("> Run Requires Action");
-                }
-                RunStatus::InProgress => {
-                    println!("> In Progress ...");
-                }
-                RunStatus::Incomplete => {
-                    println!("> Run Incomplete");
-                }
-            }
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            run = client.threads().runs(&thread.id).retrieve(&run.id).await?;
-        }
-        client.threads().delete(&thread.id).await?;
-        client.assistants().delete(&assistant.id).await?;
-        Ok(())
+        let forecast = ask(
+            &*self.llm,
+            self.ensemble.as_deref(),
+            market.as_ref(),
+            question,
+            outcome,
+            &news,
+            &prompt,
+        )
+        .await?;
+        Ok(Forecast {
+            platform: "polymarket".to_string(),
+            ..forecast
+        })
     }
-    async fn execute(&self, market: Market) -> anyhow::Result<()> {
+    async fn execute(&self, market: Market) -> anyhow::Result<Option<Forecast>> {
         tracing::info!("Polymarket Executor executing");
         let mut market_request = crate::admin::listener::MarketRequest::new();
         // for market in markets {
@@ -432,6 +428,13 @@ impl Executor<Market> for PolymarketExecutor {
 pub struct ManifoldExecutor {
     platform: Arc<api::manifold::ManifoldPlatform>,
     promptor: Promptor,
+    llm: Arc<dyn LlmProvider>,
+    /// Group slug by tag, for tags that name no group themselves.
+    group_aliases: HashMap<String, String>,
+    /// Manifold groups with `group_aliases` applied, crawled on the first `init`.
+    groups: OnceCell<GroupIndex>,
+    ensemble: Option<Arc<Ensemble>>,
+    news_key: Option<String>,
     //ExecutorBuilder<Self>
 }
 
@@ -440,20 +443,62 @@ impl ManifoldExecutor {
         Self {
             platform,
             promptor,
+            llm: Arc::new(OpenAiProvider::from_env()),
+            group_aliases: HashMap::new(),
+            groups: OnceCell::new(),
+            ensemble: None,
+            news_key: std::env::var("TAVILIY_API_KEY").ok(),
         }
     }
+
+    /// Resolves each tag to its group slug, e.g. from `Settings::manifold_groups`.
+    pub fn with_group_aliases(mut self, group_aliases: HashMap<String, String>) -> Self {
+        self.group_aliases = group_aliases;
+        self
+    }
+
+    /// Prompts `llm` instead of the provider configured by the environment.
+    pub fn with_llm(mut self, llm: Arc<dyn LlmProvider>) -> Self {
+        self.llm = llm;
+        self
+    }
+
+    /// Forecasts with `ensemble` instead of the one provider.
+    pub fn with_ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.ensemble = Some(Arc::new(ensemble));
+        self
+    }
+
+    /// Searches news with `news_key` instead of `TAVILIY_API_KEY`; `None` forecasts
+    /// without news.
+    pub fn with_news_key(mut self, news_key: Option<String>) -> Self {
+        self.news_key = news_key;
+        self
+    }
 }
 
 
 #[async_trait]
 impl Executor<Market> for ManifoldExecutor {
-    async fn init(&self, question: &str, outcome: &str, tags: Vec<String>) -> anyhow::Result<()> {
-        let platform = api::manifold::ManifoldPlatform::from(PlatformBuilder::default());
+    async fn init(
+        &self,
+        question: &str,
+        outcome: &str,
+        tags: Vec<String>,
+    ) -> anyhow::Result<Forecast> {
+        let platform = &self.platform;
+        let market = find_market(platform.fetch_markets_by_terms(question).await?, question);
         let qdrant = Arc::new(RwLock::new(
-            Qdrant::from_url("http://localhost:6334").build().unwrap(),
+            Qdrant::from_url("http://localhost:6334").build()?,
         ));
-        let news = lookup_news(question, outcome).await.unwrap();
-        let trimmed_news = news.iter().take(5).collect::<Vec<&String>>();
+        let mut news = lookup_news(self.news_key.as_deref(), question, outcome)
+            .await
+            .unwrap_or_default();
+        news.truncate(5);
+        let trimmed_news = news
+            .iter()
+            .map(|article| &article.content)
+            .collect::<Vec<&String>>();
         tracing::debug!("Trimmed News: {:?}", trimmed_news);
         // tracing::debug!("News: {:?}", news);
         let point_id = 1;
@@ -462,15 +507,23 @@ impl Executor<Market> for ManifoldExecutor {
         // let (tx, rx)  = tokio::sync::mpsc::channel(100);
         qdrant.read().unwrap().delete_collection(collection_name);
         let mut markets: Vec<serde_json::Value> = Vec::new();
-        for tag in tags {
-            let market_data = platform.fetch_markets_by_terms(&tag).await.unwrap();
-            let (markets_tx, markets_rx) = tokio::sync::mpsc::channel::<ManifoldMarket>(100);
-            market_data.iter().for_each(|m| {
-                let market_summarized = parse_manifold_market(m.clone()).unwrap();
-                // ctx.questions.push(market_summarized.clone().to_string());
-                markets.push(market_summarized);
+        // Tags resolve to Manifold topics; the rest are searched as text.
+        let groups = self
+            .groups
+            .get_or_try_init(|| async {
+                let mut groups = platform.fetch_group_index().await?;
+                for (tag, slug) in &self.group_aliases {
+                    groups.alias(tag, slug);
+                }
+                Ok::<_, api::PlatformError>(groups)
             })
-        }
+            .await?;
+        let market_data = platform.fetch_markets_by_tags(groups, &tags).await?;
+        market_data.iter().for_each(|m| {
+            let market_summarized = parse_manifold_market(m.clone()).unwrap();
+            // ctx.questions.push(market_summarized.clone().to_string());
+            markets.push(market_summarized);
+        });
 
         //         data.iter().for_each(|d| {
         //             let question_with_probability = serde_json::json!({
@@ -511,110 +564,28 @@ impl Executor<Market> for ManifoldExecutor {
         // })
         // tracing::debug!("Trimmed Market Data: {:?}", trimmed_market_data);
         // tracing::debug!("Market Data: {:?}", market_data);
-        let query = [("limit", "1")];
-        let client = async_openai::Client::new();
         let prompt =
             self.promptor
                 .prompts_manifold_filter(trimmed_markets, trimmed_news, question, outcome);
-
-        let assistant_request = CreateAssistantRequestArgs::default()
-            .instructions(
-                self.promptor
-                    .superforecaster(question, outcome)
-                    .await
-                    .to_string(),
-            )
-            .model("gpt-4o")
-            .build()?;
-        let assistant = client.assistants().create(assistant_request).await?;
-        let assistant_id = assistant.id.clone();
-        let thread = client
-            .threads()
-            .create(CreateThreadRequest::default())
-            .await?;
-        let message = CreateMessageRequestArgs::default()
-            .role(MessageRole::User)
-            .content(prompt.clone())
-            .build()?;
-        let _message = client
-            .threads()
-            .messages(&thread.id)
-            .create(message)
-            .await?;
-        let run_request = CreateRunRequestArgs::default()
-            .assistant_id(assistant_id)
-            .build()?;
-        let mut run = client
-            .threads()
-            .runs(&thread.id)
-            .create(run_request)
-            .await?;
-        loop {
-            match run.status {
-                RunStatus::Completed => {
-                    let messages = client.threads().messages(&thread.id).list(&query).await?;
-                    for message_obj in messages.data {
-                        let message_contents = message_obj.content;
-                        for message_content in message_contents {
-                            match message_content {
-                                MessageContent::Text(text) => {
-                                    let text_data = text.text;
-                                    let annotations = text_data.annotations;
-                                    println!("{}", text_data.value);
-                                    println!("{annotations:?}");
-                                }
-                                MessageContent::ImageFile(_) | MessageContent::ImageUrl(_) => {
-                                    eprintln!("Images not supported on terminal");
-                                }
-                                MessageContent::Refusal(refusal) => {
-                                    println!("{refusal:?}");
-                                }
-                            }
-                        }
-                    }
-                    break;
-                }
-                RunStatus::Failed => {
-                    println!("> Run Failed: {:#?}", run);
-                    break;
-                }
-                RunStatus::Queued => {
-                    println!("> Run Queued");
-                }
-                RunStatus::Cancelling => {
-                    println!("> Run Cancelling");
-                }
-                RunStatus::Cancelled => {
-                    println!("> Run Cancelled");
-                    break;
-                }
-                RunStatus::Expired => {
-                    println!("> Run Expired");
-                    break;
-                }
-                RunStatus::RequiresAction => {
-                    println!("> Run Requires Action");
-                }
-                RunStatus::InProgress => {
-                    println!("> In Progress ...");
-                }
-                RunStatus::Incomplete => {
-                    println!("> Run Incomplete");
-                }
-            }
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            run = client.threads().runs(&thread.id).retrieve(&run.id).await?;
-        }
-        client.threads().delete(&thread.id).await?;
-        client.assistants().delete(&assistant.id).await?;
-        Ok(())
+        let forecast = ask(
+            &*self.llm,
+            self.ensemble.as_deref(),
+            market.as_ref(),
+            question,
+            outcome,
+            &news,
+            &prompt,
+        )
+        .await?;
+        Ok(Forecast {
+            platform: "manifold".to_string(),
+            ..forecast
+        })
     }
 
-    async fn execute(&self, market: Market) -> anyhow::Result<()> {
+    async fn execute(&self, market: Market) -> anyhow::Result<Option<Forecast>> {
         let mut market_request = crate::admin::listener::MarketRequest::new();
         let platform = &self.platform;
-        let query = [("limit", "1")];
-        let client = async_openai::Client::new();
         // let prompt =
         //     self.promptor
         //         .prompts_manifold_filter(trimmed_markets, trimmed_news, question, outcome);
@@ -713,7 +684,7 @@ impl Executor<Market> for ManifoldExecutor {
 
 
         market_request.add_market(market);
-        Ok(())
+        Ok(None)
         // let pending_market = self.platform.
         // for question in questions {
         //  market_request.add_market(question);
@@ -735,6 +706,10 @@ impl Executor<Market> for ManifoldExecutor {
 pub struct MetaculusExecutor {
     provider: Arc<api::metaculus::MetaculusPlatform>,
     promptor: Promptor,
+    llm: Arc<dyn LlmProvider>,
+    ensemble: Option<Arc<Ensemble>>,
+    news_key: Option<String>,
+    live: bool,
 }
 
 // impl From<ExecutorBuilder<Self>> for MetaculusExecutor {
@@ -748,22 +723,231 @@ impl MetaculusExecutor {
         Self {
             provider,
             promptor,
+            llm: Arc::new(OpenAiProvider::from_env()),
+            ensemble: None,
+            news_key: std::env::var("TAVILIY_API_KEY").ok(),
+            live: false,
+        }
+    }
+
+    /// Prompts `llm` instead of the provider configured by the environment.
+    pub fn with_llm(mut self, llm: Arc<dyn LlmProvider>) -> Self {
+        self.llm = llm;
+        self
+    }
+
+    /// Forecasts with `ensemble` instead of the one provider.
+    pub fn with_ensemble(mut self, ensemble: Ensemble) -> Self {
+        self.ensemble = Some(Arc::new(ensemble));
+        self
+    }
+
+    /// Searches news with `news_key` instead of `TAVILIY_API_KEY`; `None` forecasts
+    /// without news.
+    pub fn with_news_key(mut self, news_key: Option<String>) -> Self {
+        self.news_key = news_key;
+        self
+    }
+
+    /// Sends forecasts to Metaculus. Off by default, so forecasts are only
+    /// validated and returned until the bot is cleared to enter a tournament.
+    pub fn with_live_submission(mut self, live: bool) -> Self {
+        self.live = live;
+        self
+    }
+
+    /// Forecasts the question of post `post_id` and submits the forecast, unless
+    /// live submission is off or the platform is in dry-run mode.
+    pub async fn forecast(&self, post_id: &str) -> anyhow::Result<SubmittedForecast> {
+        let market = self.provider.fetch_question_by_id(post_id).await?;
+        let question = market
+            .question
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("post {} has no question", post_id))?;
+        let kind = question_kind(&question);
+        let outcome = match (&question.question_type, &question.options) {
+            (Some(Type::multiple_choice), Some(options)) => options.join(", "),
+            (Some(Type::date), _) => "a date (YYYY-MM-DD)".to_string(),
+            (Some(Type::numeric), _) => "a value".to_string(),
+            _ => "yes".to_string(),
+        };
+        let news = lookup_news(self.news_key.as_deref(), market.title(), &outcome)
+            .await
+            .unwrap_or_default();
+        let summary = serde_json::json!({
+            "title": market.title(),
+            "type": question.question_type,
+            "options": question.options,
+            "scaling": question.scaling,
+            "number_of_forecasters": market.nr_forecasters,
+            "community_probability": question.community_probability(),
+            "community_median": question.community_percentile(0.5),
+        });
+        let prompt = self.promptor.prompts_metaculus_filter(
+            vec![&summary],
+            news.iter()
+                .take(5)
+                .map(|article| &article.content)
+                .collect(),
+            market.title(),
+            &outcome,
+        );
+        let instructions = self
+            .promptor
+            .superforecaster(market.title(), &outcome, kind)
+            .await;
+        let reply = self.llm.complete(&instructions, &prompt).await?;
+        let prediction = parse_prediction(&reply, &question)
+            .ok_or_else(|| anyhow::anyhow!("no forecast in reply {:?}", reply))?;
+        self.submit(question.id, &prediction).await
+    }
+
+    async fn submit(
+        &self,
+        question_id: u32,
+        prediction: &Prediction,
+    ) -> anyhow::Result<SubmittedForecast> {
+        if !self.live {
+            prediction.validate()?;
+            let payload = ForecastPayload::new(question_id, prediction);
+            tracing::info!("live submission off, not submitting {:?}", payload);
+            return Ok(SubmittedForecast {
+                payload,
+                sent: false,
+            });
+        }
+        let submitted = self
+            .provider
+            .submit_forecast(question_id, prediction)
+            .await?;
+        Ok(submitted)
+    }
+}
+
+fn question_kind(question: &Question) -> MarketKind {
+    match question.question_type {
+        Some(Type::multiple_choice) => MarketKind::MultipleChoice,
+        Some(Type::numeric) | Some(Type::date) => MarketKind::Numeric,
+        _ => MarketKind::Binary,
+    }
+}
+
+/// Reads the forecast out of a reply in the `Promptor::response_format` of the question.
+fn parse_prediction(reply: &str, question: &Question) -> Option<Prediction> {
+    match question.question_type.as_ref()? {
+        Type::binary => {
+            let probability = percent_after(reply, "likelihood")?;
+            Some(Prediction::Binary(probability.clamp(0.001, 0.999)))
+        }
+        Type::multiple_choice => {
+            let options = question.options.as_ref()?;
+            let mut probabilities = BTreeMap::new();
+            for line in reply.lines() {
+                let (Some(probability), Some(start)) =
+                    (percent_after(line, "likelihood"), line.find("for answer"))
+                else {
+                    continue;
+                };
+                let answer = line[start..].to_lowercase();
+                if let Some(option) = options
+                    .iter()
+                    .find(|option| answer.contains(&option.to_lowercase()))
+                {
+                    probabilities.insert(option.clone(), probability);
+                }
+            }
+            if probabilities.is_empty() {
+                return None;
+            }
+            // Every option keeps some probability, even when the reply skips it.
+            for option in options {
+                let probability = probabilities.entry(option.clone()).or_insert(0.0);
+                *probability = probability.max(0.01);
+            }
+            let total: f64 = probabilities.values().sum();
+            probabilities.values_mut().for_each(|p| *p /= total);
+            Some(Prediction::MultipleChoice(probabilities))
+        }
+        Type::numeric | Type::date => {
+            let median = value_after(reply, "median estimate of")?.0;
+            let (low, end) = value_after(reply, "interval from")?;
+            let start = reply.find("interval from")? + "interval from".len() + end;
+            let (high, _) = first_value(&reply[start..])?;
+            question
+                .cdf_from_percentiles(&[(0.05, low), (0.5, median), (0.95, high)])
+                .map(Prediction::Continuous)
+        }
+        _ => None,
+    }
+}
+
+/// The number right before the first `%` after `keyword`, as a fraction.
+fn percent_after(text: &str, keyword: &str) -> Option<f64> {
+    let rest = &text[text.find(keyword)? + keyword.len()..];
+    let digits = rest[..rest.find('%')?].trim_end();
+    let begin = digits
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !(c.is_ascii_digit() || *c == '.'))
+        .map(|(index, c)| index + c.len_utf8())
+        .unwrap_or(0);
+    digits[begin..]
+        .parse::<f64>()
+        .ok()
+        .map(|percent| percent / 100.0)
+}
+
+fn value_after(text: &str, keyword: &str) -> Option<(f64, usize)> {
+    first_value(&text[text.find(keyword)? + keyword.len()..])
+}
+
+/// The first number or `YYYY-MM-DD` date (as unix seconds) in `text`, with the
+/// offset just past it.
+fn first_value(text: &str) -> Option<(f64, usize)> {
+    let mut offset = 0;
+    for piece in text.split_inclusive(char::is_whitespace) {
+        offset += piece.len();
+        let token = piece
+            .trim()
+            .trim_matches(|c: char| matches!(c, ',' | ';' | ':' | '(' | ')' | '.'))
+            .replace(',', "");
+        if let Ok(value) = token.parse::<f64>() {
+            return Some((value, offset));
+        }
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(&token, "%Y-%m-%d") {
+            return Some((
+                date.and_hms_opt(0, 0, 0)?.and_utc().timestamp() as f64,
+                offset,
+            ));
         }
     }
+    None
 }
 
 #[async_trait]
 impl Executor<Market> for MetaculusExecutor {
-    async fn init(&self, question: &str, outcome: &str, tags: Vec<String>) -> anyhow::Result<()> {
-        let platform = api::metaculus::MetaculusPlatform::from(PlatformBuilder::default());
-        let news = lookup_news(question, outcome).await.unwrap();
-        let trimmed_news = news.iter().take(5).collect::<Vec<&String>>();
+    async fn init(
+        &self,
+        question: &str,
+        outcome: &str,
+        tags: Vec<String>,
+    ) -> anyhow::Result<Forecast> {
+        let platform = &self.provider;
+        let mut news = lookup_news(self.news_key.as_deref(), question, outcome)
+            .await
+            .unwrap_or_default();
+        news.truncate(5);
+        let trimmed_news = news
+            .iter()
+            .map(|article| &article.content)
+            .collect::<Vec<&String>>();
         tracing::debug!("Trimmed News: {:?}", trimmed_news);
         // tracing::debug!("News: {:?}", news);
         let mut questions_with_probability: Vec<serde_json::Value> = Vec::new();
+        let mut related = Vec::new();
 
         for tag in tags {
-            let data = platform.fetch_markets_by_terms(&tag).await.unwrap();
+            let data = platform.fetch_markets_by_terms(&tag).await?;
             data.iter().filter(|d| d.nr_forecasters >= 4).for_each(|d| {
                 let question_with_probability = serde_json::json!({
                     "title": d.title,
@@ -772,7 +956,9 @@ impl Executor<Market> for MetaculusExecutor {
                 });
                 questions_with_probability.push(question_with_probability);
             });
+            related.extend(data);
         }
+        let market = find_market(related, question);
 
         // // let question_with_probability = serde_json::json!({
         // //     "title": d.title,
@@ -803,111 +989,43 @@ impl Executor<Market> for MetaculusExecutor {
         // })
         // tracing::debug!("Trimmed Market Data: {:?}", trimmed_market_data);
         // tracing::debug!("Market Data: {:?}", market_data);
-        let query = [("limit", "1")];
-        let client = async_openai::Client::new();
         let prompt = self.promptor.prompts_metaculus_filter(
             trimmed_market_data.clone(),
             trimmed_news,
             question,
             outcome,
         );
-
-        let assistant_request = CreateAssistantRequestArgs::default()
-            .instructions(
-                self.promptor
-                    .superforecaster(question, outcome)
-                    .await
-                    .to_string(),
-            )
-            .model("gpt-4o")
-            .build()?;
-        let assistant = client.assistants().create(assistant_request).await?;
-        let assistant_id = assistant.id.clone();
-        let thread = client
-            .threads()
-            .create(CreateThreadRequest::default())
-            .await?;
-        let message = CreateMessageRequestArgs::default()
-            .role(MessageRole::User)
-            .content(prompt.clone())
-            .build()?;
-        let _message = client
-            .threads()
-            .messages(&thread.id)
-            .create(message)
-            .await?;
-        let run_request = CreateRunRequestArgs::default()
-            .assistant_id(assistant_id)
-            .build()?;
-        let mut run = client
-            .threads()
-            .runs(&thread.id)
-            .create(run_request)
-            .await?;
-        loop {
-            match run.status {
-                RunStatus::Completed => {
-                    let messages = client.threads().messages(&thread.id).list(&query).await?;
-                    for message_obj in messages.data {
-                        let message_contents = message_obj.content;
-                        for message_content in message_contents {
-                            match message_content {
-                                MessageContent::Text(text) => {
-                                    let text_data = text.text;
-                                    let annotations = text_data.annotations;
-                                    println!("{}", text_data.value);
-                                    println!("{annotations:?}");
-                                }
-                                MessageContent::ImageFile(_) | MessageContent::ImageUrl(_) => {
-                                    eprintln!("Images not supported on terminal");
-                                }
-                                MessageContent::Refusal(refusal) => {
-                                    println!("{refusal:?}");
-                                }
-                            }
-                        }
-                    }
-                    break;
-                }
-                RunStatus::Failed => {
-                    println!("> Run Failed: {:#?}", run);
-                    break;
-                }
-                RunStatus::Queued => {
-                    println!("> Run Queued");
-                }
-                RunStatus::Cancelling => {
-                    println!("> Run Cancelling");
-                }
-                RunStatus::Cancelled => {
-                    println!("> Run Cancelled");
-                    break;
-                }
-                RunStatus::Expired => {
-                    println!("> Run Expired");
-                    break;
-                }
-                RunStatus::RequiresAction => {
-                    println!("> Run Requires Action");
-                }
-                RunStatus::InProgress => {
-                    println!("> In Progress ...");
-                }
-                RunStatus::Incomplete => {
-                    println!("> Run Incomplete");
+        let forecast = ask(
+            &*self.llm,
+            self.ensemble.as_deref(),
+            market.as_ref(),
+            question,
+            outcome,
+            &news,
+            &prompt,
+        )
+        .await?;
+        // Only a binary question is fully described by the forecast of one outcome.
+        if let Some(question) = market.as_ref().and_then(|market| market.question.as_ref()) {
+            if question.question_type == Some(Type::binary) {
+                let prediction = Prediction::Binary(forecast.probability.clamp(0.001, 0.999));
+                match self.submit(question.id, &prediction).await {
+                    Ok(submitted) => tracing::info!("forecast submitted: {}", submitted.sent),
+                    Err(err) => tracing::warn!("could not submit forecast: {}", err),
                 }
             }
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            run = client.threads().runs(&thread.id).retrieve(&run.id).await?;
         }
-        client.threads().delete(&thread.id).await?;
-        client.assistants().delete(&assistant.id).await?;
-        Ok(())
+        Ok(Forecast {
+            platform: "metaculus".to_string(),
+            ..forecast
+        })
     }
 
-    async fn execute(&self, markets: Market) -> anyhow::Result<()> {
-        println!("Metaculus Executor executing");
-        unimplemented!()
+    // Metaculus questions are forecast by id through `MetaculusExecutor::forecast`;
+    // market ticks carry nothing to act on.
+    async fn execute(&self, markets: Market) -> anyhow::Result<Option<Forecast>> {
+        tracing::debug!("Metaculus executor skipping {:?}", markets);
+        Ok(None)
     }
 }
 
@@ -976,95 +1094,77 @@ fn parse_polymarket_event(event: PolymarketEvent) -> Result<serde_json::Value> {
     Ok(event_summarized)
 }
 
-fn parse_manifold_market(market: ManifoldMarket) -> Result<serde_json::Value> {
-    let probability: String = if let Some(probability) = market.probability {
-        probability.to_string()
-    } else {
-        "".to_string()
-    };
-    let pool: [String; 2] = if let Some(pool) = market.pool {
-        [format!("Yes: {}", pool.YES), format!("No: {}", pool.NO)]
-    } else {
-        ["0".to_string(), "0".to_string()]
-    };
-    let market_summarized = serde_json::json!({
-        "question": market.question,
-        "probability": probability,
-        "pool": pool,
-    });
-    Ok(market_summarized)
+/// The market among `markets` asking `question`, matched on its title.
+fn find_market<M: crate::model::Market>(
+    markets: impl IntoIterator<Item = M>,
+    question: &str,
+) -> Option<M> {
+    let question = question.trim();
+    markets
+        .into_iter()
+        .find(|market| market.title().trim().eq_ignore_ascii_case(question))
 }
 
-async fn handle_requires_action(
-    client: async_openai::Client<async_openai::config::OpenAIConfig>,
-    run_object: RunObject,
-) {
-    let mut tool_outputs: Vec<ToolsOutputs> = vec![];
-    if let Some(ref required_action) = run_object.required_action {
-        for tool in &required_action.submit_tool_outputs.tool_calls {
-            if tool.function.name == "lookup_market" {
-                tool_outputs.push(ToolsOutputs {
-                    tool_call_id: Some(tool.id.clone()),
-                    output: Some("57".into()),
-                })
-            }
-
-            // if tool.function.name == "get_rain_probability" {
-            //     tool_outputs.push(ToolsOutputs {
-            //         tool_call_id: Some(tool.id.clone()),
-            //         output: Some("0.06".into()),
-            //     })
-            // }
-        }
-
-        if let Err(e) = submit_tool_outputs(client, run_object, tool_outputs).await {
-            eprintln!("Error on submitting tool outputs: {e}");
-        }
+/// `forecast` tied to the market it is about, with the market's probability of the
+/// outcome at the time, so the ledger can settle it and score it against the market.
+pub(crate) fn on_market<M: crate::model::Market>(forecast: Forecast, market: &M) -> Forecast {
+    Forecast {
+        platform: market.platform().to_string(),
+        platform_id: Some(market.platform_id()),
+        market_probability: market.outcome_probability(&forecast.outcome),
+        category: market.category().to_string(),
+        ..forecast
     }
 }
 
-async fn submit_tool_outputs(
-    client: Client<OpenAIConfig>,
-    run_object: RunObject,
-    tool_outputs: Vec<ToolsOutputs>,
-) -> Result<()> {
-    let mut event_stream = client
-        .threads()
-        .runs(&run_object.thread_id)
-        .submit_tool_outputs_stream(
-            &run_object.id,
-            SubmitToolOutputsRunRequest {
-                tool_outputs,
-                stream: Some(true),
-            },
-        )
-        .await?;
+/// Kind of the market being forecast; a question without one is asked as yes/no.
+pub(crate) fn market_kind<M: crate::model::Market>(market: Option<&M>) -> MarketKind {
+    market.map_or(MarketKind::Binary, |market| market.kind())
+}
 
-    while let Some(event) = event_stream.next().await {
-        match event {
-            Ok(event) => {
-                if let AssistantStreamEvent::ThreadMessageDelta(delta) = event {
-                    if let Some(contents) = delta.delta.content {
-                        for content in contents {
-                            // only text is expected here and no images
-                            if let MessageDeltaContent::Text(text) = content {
-                                if let Some(text) = text.text {
-                                    if let Some(text) = text.value {
-                                        print!("{}", text);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            Err(e) => {
-                eprintln!("Error: {e}");
-            }
+fn parse_manifold_market(market: ManifoldMarket) -> Result<serde_json::Value> {
+    let kind = market.kind();
+    let market_summarized = match kind {
+        MarketKind::Binary => {
+            let pool: [String; 2] = if let Some(pool) = &market.pool {
+                [format!("Yes: {}", pool.YES), format!("No: {}", pool.NO)]
+            } else {
+                ["0".to_string(), "0".to_string()]
+            };
+            serde_json::json!({
+                "question": market.question,
+                "kind": kind,
+                "probability": market.probability,
+                "pool": pool,
+            })
         }
-    }
-
-    Ok(())
+        MarketKind::MultipleChoice => {
+            let answers: Vec<serde_json::Value> = market
+                .answers
+                .iter()
+                .map(|answer| {
+                    serde_json::json!({
+                        "answer": answer.text,
+                        "probability": answer.probability,
+                    })
+                })
+                .collect();
+            serde_json::json!({
+                "question": market.question,
+                "kind": kind,
+                "answers": answers,
+            })
+        }
+        MarketKind::Numeric => serde_json::json!({
+            "question": market.question,
+            "kind": kind,
+            "min": market.min,
+            "max": market.max,
+            "log_scale": market.isLogScale.unwrap_or(false),
+            "value": market.value,
+        }),
+    };
+    Ok(market_summarized)
 }
 
 // async fn looup_asknews(question: &str, outcome: &str) -> Result<Vec<String>> {
@@ -1075,28 +1175,108 @@ async fn submit_tool_outputs(
 //     let request = reqwest::Client::new().get()
 
 // }
-async fn lookup_news(question: &str, outcome: &str) -> Result<Vec<String>> {
-    let news: Vec<serde_json::Value> = Vec::new();
-    let key: String = std::env::var("TAVILIY_API_KEY").unwrap();
+/// A news article found for a question.
+#[derive(Debug, Clone)]
+pub struct News {
+    pub url: String,
+    pub content: String,
+}
+
+async fn lookup_news(key: Option<&str>, question: &str, outcome: &str) -> Result<Vec<News>> {
+    let Some(key) = key else {
+        tracing::warn!("no Tavily key, forecasting without news");
+        return Ok(Vec::new());
+    };
     let query = question.to_string().to_owned() + " " + outcome;
-    let taviliy = tavily::Tavily::new(&key);
+    let taviliy = tavily::Tavily::new(key);
     let response = taviliy.search(&query).await?;
 
     let results = response
         .results
         .iter()
-        .map(|r| {
-            if r.score > 0.5 {
-                r.content.clone()
-            } else {
-                "".to_string()
-            }
+        .filter(|r| r.score > 0.5)
+        .map(|r| News {
+            url: r.url.clone(),
+            content: r.content.clone(),
         })
-        .collect::<Vec<String>>();
+        .collect::<Vec<News>>();
 
     Ok(results)
 }
 
+/// Forecasts with `ensemble` when there is one, otherwise asks `llm` with the
+/// superforecaster instructions. The forecast is tied to `market`, if found.
+async fn ask<M: crate::model::Market + Sync>(
+    llm: &dyn LlmProvider,
+    ensemble: Option<&Ensemble>,
+    market: Option<&M>,
+    question: &str,
+    outcome: &str,
+    news: &[News],
+    prompt: &str,
+) -> anyhow::Result<Forecast> {
+    if let Some(ensemble) = ensemble {
+        let forecast = ensemble
+            .forecast(question, outcome, market, news, prompt)
+            .await?;
+        return Ok(forecast.forecast);
+    }
+    let variant = PromptVariant::Superforecaster;
+    let kind = market_kind(market);
+    let instructions = Promptor {}
+        .instructions(variant, question, outcome, kind)
+        .await;
+    let forecast = ask_forecast(llm, question, news, variant, &instructions, prompt).await?;
+    Ok(match market {
+        Some(market) => on_market(forecast, market),
+        None => forecast,
+    })
+}
+
+/// Asks `llm` for a forecast as structured output, falling back to the legacy text
+/// format for backends without JSON schema support. The platform is left for the
+/// caller to fill in.
+pub(crate) async fn ask_forecast(
+    llm: &dyn LlmProvider,
+    question: &str,
+    news: &[News],
+    variant: PromptVariant,
+    instructions: &str,
+    prompt: &str,
+) -> anyhow::Result<Forecast> {
+    let messages = [ChatMessage::system(instructions), ChatMessage::user(prompt)];
+    let structured: anyhow::Result<ForecastAnswer> =
+        super::llm::structured(llm, &messages, "forecast", &ForecastAnswer::schema()).await;
+    let answer = match structured {
+        Ok(answer) => answer,
+        Err(e) => {
+            tracing::debug!("no structured forecast ({}), reading the reply as text", e);
+            let reply = llm.chat(&messages).await?;
+            ForecastAnswer::from_legacy(&reply)
+                .ok_or_else(|| anyhow::anyhow!("no forecast in reply {:?}", reply))?
+        }
+    };
+    if !answer.is_valid() {
+        anyhow::bail!("invalid forecast {:?}", answer);
+    }
+    Ok(Forecast {
+        question: question.to_string(),
+        platform: String::new(),
+        platform_id: None,
+        outcome: answer.outcome,
+        probability: answer.probability,
+        rationale: answer.rationale,
+        model: llm.model().to_string(),
+        prompt_version: variant.name().to_string(),
+        prompt_hash: prompt_hash(instructions, prompt),
+        news_sources: news.iter().map(|article| article.url.clone()).collect(),
+        timestamp: chrono::Utc::now(),
+        market_probability: None,
+        category: String::new(),
+        dispersion: None,
+    })
+}
+
 fn filter_markets() -> Result<Vec<serde_json::Value>> {
     unimplemented!()
 }
@@ -1105,6 +1285,7 @@ fn filter_news() -> Result<Vec<serde_json::Value>> {
     unimplemented!()
 }
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use tracing_subscriber::prelude::*;
@@ -1122,7 +1303,170 @@ mod tests {
         // tracing::debug!("Result: {:?}", result);
     }
 
+    #[test]
+    fn test_parse_manifold_market_kinds() {
+        let market: ManifoldMarket = serde_json::from_str(include_str!(
+            "../../tests/fixtures/manifold_multiple_choice.json"
+        ))
+        .unwrap();
+        let summary = parse_manifold_market(market).unwrap();
+        assert_eq!(summary["kind"], "multiple_choice");
+        assert_eq!(summary["answers"][0]["answer"], "Labour");
+
+        let market: ManifoldMarket =
+            serde_json::from_str(include_str!("../../tests/fixtures/manifold_numeric.json"))
+                .unwrap();
+        let summary = parse_manifold_market(market).unwrap();
+        assert_eq!(summary["max"], 1000.0);
+        assert_eq!(summary["log_scale"], true);
+
+        // The kind is the found market's, whatever the outcome is named.
+        let choice: ManifoldMarket = serde_json::from_str(include_str!(
+            "../../tests/fixtures/manifold_multiple_choice.json"
+        ))
+        .unwrap();
+        let question = choice.question.clone();
+        let found = find_market([choice], &format!(" {} ", question.to_uppercase()));
+        assert_eq!(market_kind(found.as_ref()), MarketKind::MultipleChoice);
+        assert_eq!(market_kind::<ManifoldMarket>(None), MarketKind::Binary);
+        let format = Promptor {}.response_format(MarketKind::Numeric, "attendance", "120");
+        assert!(format.contains("median estimate"));
+    }
+
+    #[test]
+    fn test_on_market() {
+        let market: ManifoldMarket = serde_json::from_str(include_str!(
+            "../../tests/fixtures/manifold_multiple_choice.json"
+        ))
+        .unwrap();
+        let forecast = Forecast {
+            platform_id: None,
+            outcome: "Labour".to_string(),
+            ..Forecast::fixture(0.8)
+        };
+        let forecast = on_market(forecast, &market);
+        assert_eq!(forecast.platform_id, Some(market.id.clone()));
+        assert_eq!(forecast.market_probability, market.answers[0].probability);
+        assert_eq!(forecast.category, "uk-politics");
+    }
+
+    #[test]
+    fn test_parse_prediction() {
+        let market: crate::model::metaculus::MetaculusMarket =
+            serde_json::from_str(include_str!("../../tests/fixtures/metaculus_market.json"))
+                .unwrap();
+        let binary = market.question.unwrap();
+        let reply = "The question Nvidia over Apple?; has a likelihood 37.5% for outcome of yes.";
+        assert_eq!(
+            parse_prediction(reply, &binary),
+            Some(Prediction::Binary(0.375))
+        );
+
+        let choice = Question {
+            question_type: Some(Type::multiple_choice),
+            options: Some(
+                ["Labour", "Conservative", "Other"]
+                    .map(str::to_string)
+                    .to_vec(),
+            ),
+            ..binary.clone()
+        };
+        let reply = "The question Labour or Conservative?; has a likelihood 70% for answer Labour.
+        The question Labour or Conservative?; has a likelihood 30% for answer Conservative.";
+        let Some(Prediction::MultipleChoice(options)) = parse_prediction(reply, &choice) else {
+            panic!("expected a multiple-choice forecast");
+        };
+        assert!((options["Labour"] - 0.70 / 1.01).abs() < 1e-9);
+        assert!((options["Other"] - 0.01 / 1.01).abs() < 1e-9);
+        assert!(Prediction::MultipleChoice(options).validate().is_ok());
+
+        let market: crate::model::metaculus::MetaculusMarket =
+            serde_json::from_str(include_str!("../../tests/fixtures/metaculus_numeric.json"))
+                .unwrap();
+        let numeric = market.question.unwrap();
+        let reply = "The question How many launches?; has a median estimate of 30,
+        with a 90% interval from 10 to 80.";
+        let Some(prediction @ Prediction::Continuous(_)) = parse_prediction(reply, &numeric) else {
+            panic!("expected a continuous forecast");
+        };
+        assert!(prediction.validate().is_ok());
+        assert_eq!(parse_prediction("no idea", &numeric), None);
+    }
+
+    #[tokio::test]
+    async fn test_ask_forecast() {
+        use crate::executor::llm::ScriptedProvider;
+        let news = [News {
+            url: "https://example.com/launches".to_string(),
+            content: "Launch cadence doubled.".to_string(),
+        }];
+        let llm = ScriptedProvider::texts(&[
+            r#"{"outcome": "yes", "probability": 0.62, "rationale": "Cadence doubled."}"#,
+        ]);
+        let variant = PromptVariant::Superforecaster;
+        let forecast = ask_forecast(&llm, "More launches?", &news, variant, "system", "prompt")
+            .await
+            .unwrap();
+        assert_eq!(forecast.probability, 0.62);
+        assert_eq!(forecast.prompt_version, "superforecaster");
+        assert_eq!(forecast.model, "scripted");
+        assert_eq!(forecast.news_sources, vec!["https://example.com/launches"]);
+        assert_eq!(forecast.prompt_hash, prompt_hash("system", "prompt"));
+
+        // A backend without structured output answers in the legacy format.
+        let llm = ScriptedProvider::texts(&[
+            "I believe it is likely.",
+            "The question More launches?; has a likelihood 55% for outcome of yes.",
+        ]);
+        let forecast = ask_forecast(&llm, "More launches?", &[], variant, "system", "prompt")
+            .await
+            .unwrap();
+        assert_eq!(
+            (forecast.outcome.as_str(), forecast.probability),
+            ("yes", 0.55)
+        );
+        let reply = r#"{"outcome": "yes", "probability": 62, "rationale": ""}"#;
+        let llm = ScriptedProvider::texts(&[reply]);
+        let forecast = ask_forecast(&llm, "More launches?", &[], variant, "system", "prompt").await;
+        assert!(forecast.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metaculus_forecast_offline() {
+        let server = crate::server::mock::MockServer::start().await.unwrap();
+        let reply = "The question How many launches?; has a median estimate of 30,
+            with a 90% interval from 10 to 80.";
+        let executor = |llm: Arc<dyn LlmProvider>| {
+            let mut builder = PlatformBuilder::new();
+            builder.mock(&server.url());
+            builder.api_key("mock-token");
+            MetaculusExecutor::new(
+                Arc::new(api::metaculus::MetaculusPlatform::from(builder)),
+                Promptor {},
+            )
+            .with_llm(llm)
+            .with_news_key(None)
+        };
+        let llm = Arc::new(crate::executor::llm::ScriptedProvider::texts(&[reply]));
+        let offline = executor(llm.clone());
+
+        let submitted = offline.forecast("31342").await.unwrap();
+        assert!(!submitted.sent);
+        assert_eq!(submitted.payload.question, 31001);
+        let cdf = submitted.payload.continuous_cdf.unwrap();
+        assert_eq!(cdf.len(), crate::model::metaculus::CDF_SIZE);
+        let received = llm.received();
+        assert_eq!(received.len(), 1);
+        assert!(received[0][1].content.contains("community_median"));
+        assert!(offline.forecast("31342").await.is_err());
+
+        let llm = Arc::new(crate::executor::llm::ScriptedProvider::texts(&[reply]));
+        let live = executor(llm).with_live_submission(true);
+        assert!(live.forecast("31342").await.unwrap().sent);
+    }
+
     #[tokio::test]
+    #[ignore = "calls the live Tavily API"]
     async fn test_taviliy_request() {
         tracing_subscriber::registry()
             .with(
@@ -1132,7 +1476,9 @@ mod tests {
             .with(tracing_subscriber::fmt::layer())
             .init();
 
+        let key = std::env::var("TAVILIY_API_KEY").ok();
         let news = lookup_news(
+            key.as_deref(),
             "What is the probability of Joe Biden winning the 2024 US elections?",
             "Joe Biden winning the 2024 US elections",
         )
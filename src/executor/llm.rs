@@ -0,0 +1,437 @@
+use async_openai::config::OpenAIConfig;
+use async_openai::types::{
+    ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessageArgs,
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionTool,
+    ChatCompletionToolArgs, ChatCompletionToolType, CreateChatCompletionRequestArgs, FunctionCall,
+    FunctionObjectArgs, ResponseFormat, ResponseFormatJsonSchema,
+};
+use async_openai::Client;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+pub const OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub const DEFAULT_MODEL: &str = "gpt-4o";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+    Tool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    /// Calls an assistant message made, answered by the `Tool` messages after it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The call a `Tool` message answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+}
+
+impl ChatMessage {
+    fn new(role: Role, content: &str) -> Self {
+        Self {
+            role,
+            content: content.to_string(),
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+        }
+    }
+
+    pub fn system(content: &str) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: &str) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: &str) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// The output of the tool call `call_id`.
+    pub fn tool(call_id: &str, content: &str) -> Self {
+        Self {
+            tool_call_id: Some(call_id.to_string()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+/// A function the model may call, described by a JSON schema of its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tool {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// JSON encoded arguments, as the model wrote them.
+    pub arguments: String,
+}
+
+/// A reply that either answers or asks for tool calls first.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reply {
+    pub content: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Reply {
+    pub fn text(content: &str) -> Self {
+        Self {
+            content: Some(content.to_string()),
+            tool_calls: Vec::new(),
+        }
+    }
+}
+
+/// A chat model the executors prompt, so the backend can be swapped for a local
+/// server or a scripted stand-in.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Name of the model answering, as recorded with its forecasts.
+    fn model(&self) -> &str;
+
+    /// The next reply in a conversation, offering `tools` to call.
+    async fn call_tools(&self, messages: &[ChatMessage], tools: &[Tool]) -> anyhow::Result<Reply>;
+
+    /// The next reply in a conversation, as JSON matching `schema`.
+    async fn structured(
+        &self,
+        messages: &[ChatMessage],
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value>;
+
+    async fn chat(&self, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        let reply = self.call_tools(messages, &[]).await?;
+        reply
+            .content
+            .ok_or_else(|| anyhow::anyhow!("the model sent no content"))
+    }
+
+    /// One answer to `prompt` under the system `instructions`.
+    async fn complete(&self, instructions: &str, prompt: &str) -> anyhow::Result<String> {
+        self.chat(&[ChatMessage::system(instructions), ChatMessage::user(prompt)])
+            .await
+    }
+}
+
+/// `LlmProvider::structured`, decoded into `T`.
+pub async fn structured<T: DeserializeOwned>(
+    llm: &dyn LlmProvider,
+    messages: &[ChatMessage],
+    name: &str,
+    schema: &serde_json::Value,
+) -> anyhow::Result<T> {
+    Ok(serde_json::from_value(
+        llm.structured(messages, name, schema).await?,
+    )?)
+}
+
+/// Chat completions against OpenAI or any server speaking its API, such as
+/// llama.cpp or vLLM.
+pub struct OpenAiProvider {
+    client: Client<OpenAIConfig>,
+    model: String,
+}
+
+impl OpenAiProvider {
+    pub fn new(base_url: &str, api_key: &str, model: &str) -> Self {
+        let config = OpenAIConfig::new()
+            .with_api_base(base_url)
+            .with_api_key(api_key);
+        Self {
+            client: Client::with_config(config),
+            model: model.to_string(),
+        }
+    }
+
+    /// Reads `OPENAI_BASE_URL`, `OPENAI_API_KEY` and `OPENAI_MODEL`, defaulting to
+    /// OpenAI itself and `gpt-4o`. Local servers usually accept any key.
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: &str| std::env::var(name).unwrap_or(default.to_string());
+        Self::new(
+            &var("OPENAI_BASE_URL", OPENAI_BASE_URL),
+            &var("OPENAI_API_KEY", ""),
+            &var("OPENAI_MODEL", DEFAULT_MODEL),
+        )
+    }
+
+    fn request_messages(
+        messages: &[ChatMessage],
+    ) -> anyhow::Result<Vec<ChatCompletionRequestMessage>> {
+        messages
+            .iter()
+            .map(|message| {
+                let content = message.content.as_str();
+                Ok(match message.role {
+                    Role::System => ChatCompletionRequestSystemMessageArgs::default()
+                        .content(content)
+                        .build()?
+                        .into(),
+                    Role::User => ChatCompletionRequestUserMessageArgs::default()
+                        .content(content)
+                        .build()?
+                        .into(),
+                    Role::Assistant => {
+                        let mut assistant = ChatCompletionRequestAssistantMessageArgs::default();
+                        assistant.content(content);
+                        if !message.tool_calls.is_empty() {
+                            assistant.tool_calls(
+                                message
+                                    .tool_calls
+                                    .iter()
+                                    .map(|call| ChatCompletionMessageToolCall {
+                                        id: call.id.clone(),
+                                        r#type: ChatCompletionToolType::Function,
+                                        function: FunctionCall {
+                                            name: call.name.clone(),
+                                            arguments: call.arguments.clone(),
+                                        },
+                                    })
+                                    .collect::<Vec<_>>(),
+                            );
+                        }
+                        assistant.build()?.into()
+                    }
+                    Role::Tool => ChatCompletionRequestToolMessageArgs::default()
+                        .content(content)
+                        .tool_call_id(message.tool_call_id.clone().unwrap_or_default())
+                        .build()?
+                        .into(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    async fn call_tools(&self, messages: &[ChatMessage], tools: &[Tool]) -> anyhow::Result<Reply> {
+        let mut request = CreateChatCompletionRequestArgs::default();
+        request
+            .model(&self.model)
+            .messages(Self::request_messages(messages)?);
+        if !tools.is_empty() {
+            let tools = tools
+                .iter()
+                .map(|tool| {
+                    ChatCompletionToolArgs::default()
+                        .r#type(ChatCompletionToolType::Function)
+                        .function(
+                            FunctionObjectArgs::default()
+                                .name(&tool.name)
+                                .description(&tool.description)
+                                .parameters(tool.parameters.clone())
+                                .build()?,
+                        )
+                        .build()
+                })
+                .collect::<Result<Vec<ChatCompletionTool>, _>>()?;
+            request.tools(tools);
+        }
+        let response = self.client.chat().create(request.build()?).await?;
+        let message = response
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("the model sent no choices"))?
+            .message;
+        Ok(Reply {
+            content: message.content,
+            tool_calls: message
+                .tool_calls
+                .unwrap_or_default()
+                .into_iter()
+                .map(|call| ToolCall {
+                    id: call.id,
+                    name: call.function.name,
+                    arguments: call.function.arguments,
+                })
+                .collect(),
+        })
+    }
+
+    async fn structured(
+        &self,
+        messages: &[ChatMessage],
+        name: &str,
+        schema: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(&self.model)
+            .messages(Self::request_messages(messages)?)
+            .response_format(ResponseFormat::JsonSchema {
+                json_schema: ResponseFormatJsonSchema {
+                    description: None,
+                    name: name.to_string(),
+                    schema: Some(schema.clone()),
+                    strict: Some(true),
+                },
+            })
+            .build()?;
+        let response = self.client.chat().create(request).await?;
+        let content = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow::anyhow!("the model sent no content"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+/// Plays back queued replies in order and records every conversation it is sent,
+/// so executors run deterministically without a model.
+#[derive(Default)]
+pub struct ScriptedProvider {
+    replies: Mutex<VecDeque<Reply>>,
+    received: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl ScriptedProvider {
+    pub fn new<I: IntoIterator<Item = Reply>>(replies: I) -> Self {
+        Self {
+            replies: Mutex::new(replies.into_iter().collect()),
+            received: Mutex::default(),
+        }
+    }
+
+    /// Replies with each text in turn.
+    pub fn texts(replies: &[&str]) -> Self {
+        Self::new(replies.iter().map(|reply| Reply::text(reply)))
+    }
+
+    /// Conversations sent so far, oldest first.
+    pub fn received(&self) -> Vec<Vec<ChatMessage>> {
+        self.received.lock().expect("script lock poisoned").clone()
+    }
+
+    fn next(&self, messages: &[ChatMessage]) -> anyhow::Result<Reply> {
+        self.received
+            .lock()
+            .expect("script lock poisoned")
+            .push(messages.to_vec());
+        self.replies
+            .lock()
+            .expect("script lock poisoned")
+            .pop_front()
+            .ok_or_else(|| anyhow::anyhow!("the script has no replies left"))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ScriptedProvider {
+    fn model(&self) -> &str {
+        "scripted"
+    }
+
+    async fn call_tools(&self, messages: &[ChatMessage], _tools: &[Tool]) -> anyhow::Result<Reply> {
+        self.next(messages)
+    }
+
+    async fn structured(
+        &self,
+        messages: &[ChatMessage],
+        _name: &str,
+        _schema: &serde_json::Value,
+    ) -> anyhow::Result<serde_json::Value> {
+        let content = self
+            .next(messages)?
+            .content
+            .ok_or_else(|| anyhow::anyhow!("the scripted reply has no content"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::post, Json, Router};
+
+    #[tokio::test]
+    async fn test_scripted_provider() {
+        let llm = ScriptedProvider::new([
+            Reply {
+                content: None,
+                tool_calls: vec![ToolCall {
+                    id: "call-1".to_string(),
+                    name: "lookup_market".to_string(),
+                    arguments: "{}".to_string(),
+                }],
+            },
+            Reply::text("{\"probability\": 0.4}"),
+        ]);
+        let reply = llm
+            .call_tools(&[ChatMessage::user("hi")], &[])
+            .await
+            .unwrap();
+        assert_eq!(reply.tool_calls[0].name, "lookup_market");
+
+        #[derive(Deserialize)]
+        struct Estimate {
+            probability: f64,
+        }
+        let messages = [ChatMessage::tool("call-1", "57")];
+        let estimate: Estimate = structured(&llm, &messages, "estimate", &serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(estimate.probability, 0.4);
+        assert_eq!(llm.received()[1][0].tool_call_id.as_deref(), Some("call-1"));
+        assert!(llm.complete("system", "again").await.is_err());
+    }
+
+    /// A local OpenAI-compatible server answering every completion the same way.
+    #[tokio::test]
+    async fn test_openai_compatible_base_url() {
+        async fn completions(Json(request): Json<serde_json::Value>) -> Json<serde_json::Value> {
+            let last = request["messages"]
+                .as_array()
+                .unwrap()
+                .last()
+                .unwrap()
+                .clone();
+            let content = format!("echo: {}", last["content"].as_str().unwrap());
+            Json(serde_json::json!({
+                "id": "chatcmpl-local",
+                "object": "chat.completion",
+                "created": 0,
+                "model": request["model"],
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop",
+                }],
+            }))
+        }
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        let app = Router::new().route("/v1/chat/completions", post(completions));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let llm = OpenAiProvider::new(&base_url, "local", "llama");
+        let reply = llm.complete("Be brief.", "hello").await.unwrap();
+        assert_eq!(reply, "echo: hello");
+    }
+}
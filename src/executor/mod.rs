@@ -1 +1,4 @@
+pub mod ensemble;
 pub mod executor;
+pub mod llm;
+pub mod recorder;
@@ -0,0 +1,189 @@
+use super::executor::Executor;
+use crate::api::Platform;
+use crate::context::Collector;
+use crate::db::ledger::ForecastLedger;
+use crate::db::timeseries::TimeSeriesStore;
+use crate::model::forecast::Forecast;
+use crate::types::Market;
+use axum::async_trait;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::Sender;
+
+/// Writes every tick seen on the context channel to the time-series store.
+#[derive(Clone)]
+pub struct SeriesRecorder {
+    store: TimeSeriesStore,
+}
+
+impl SeriesRecorder {
+    pub fn new(store: TimeSeriesStore) -> Self {
+        Self { store }
+    }
+}
+
+#[async_trait]
+impl Executor<Market> for SeriesRecorder {
+    async fn init(
+        &self,
+        _question: &str,
+        _outcome: &str,
+        _tags: Vec<String>,
+    ) -> anyhow::Result<Forecast> {
+        anyhow::bail!("the series recorder does not forecast")
+    }
+
+    async fn execute(&self, market: Market) -> anyhow::Result<Option<Forecast>> {
+        if let Market::MarketPosition(tick) = market {
+            self.store.append(&tick)?;
+        }
+        Ok(None)
+    }
+}
+
+/// Settles the forecasts in a ledger as their markets on one platform resolve,
+/// checking every `period`.
+pub struct ResolutionWatcher<P> {
+    ledger: ForecastLedger,
+    platform: Arc<P>,
+    period: Duration,
+}
+
+impl<P> ResolutionWatcher<P>
+where
+    P: Platform + Sync,
+    P::Market: crate::model::Market,
+{
+    pub fn new(ledger: ForecastLedger, platform: Arc<P>, period: Duration) -> Self {
+        Self {
+            ledger,
+            platform,
+            period,
+        }
+    }
+
+    /// Checks every market with pending forecasts once and returns how many
+    /// forecasts settled. Markets that fail to load are retried next time.
+    pub async fn poll(&self) -> anyhow::Result<usize> {
+        let mut settled = 0;
+        for id in self.ledger.pending_markets(P::NAME)? {
+            match self.platform.fetch_question_by_id(&id).await {
+                Ok(market) => settled += self.ledger.settle(&market, chrono::Utc::now())?,
+                Err(e) => tracing::warn!("could not check {} market {}: {}", P::NAME, id, e),
+            }
+        }
+        Ok(settled)
+    }
+
+    /// Polls until no forecast on the platform is pending.
+    pub async fn watch(&self) -> anyhow::Result<()> {
+        loop {
+            self.poll_logged().await?;
+            if self.ledger.pending_markets(P::NAME)?.is_empty() {
+                return Ok(());
+            }
+            tokio::time::sleep(self.period).await;
+        }
+    }
+
+    async fn poll_logged(&self) -> anyhow::Result<()> {
+        let settled = self.poll().await?;
+        if settled > 0 {
+            tracing::info!("settled {} {} forecasts", settled, P::NAME);
+        }
+        Ok(())
+    }
+}
+
+/// Run by a context to settle forecasts recorded while it runs, so it keeps
+/// polling when nothing is pending. Nothing is published.
+#[async_trait]
+impl<P> Collector<Market> for ResolutionWatcher<P>
+where
+    P: Platform + Send + Sync + 'static,
+    P::Market: crate::model::Market + Send,
+{
+    async fn collect(&self, _tx: Sender<Market>) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(self.period);
+        loop {
+            interval.tick().await;
+            self.poll_logged().await?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::{manifold::ManifoldPlatform, polymarket::PolymarketPlatform, PlatformBuilder};
+    use crate::server::mock::MockServer;
+    use crate::types::{OutcomeTick, Tick};
+
+    #[tokio::test]
+    async fn test_records_positions() {
+        let store = TimeSeriesStore::temporary().unwrap();
+        let recorder = SeriesRecorder::new(store.clone());
+        let tick = OutcomeTick {
+            platform: "polymarket".to_string(),
+            market_id: "0x9c1a".to_string(),
+            outcome: "YES".to_string(),
+            tick: Tick {
+                timestamp: 1_700_000_000,
+                probability: 0.62,
+                volume: 1250342.12,
+            },
+        };
+        recorder
+            .execute(Market::MarketPosition(tick.clone()))
+            .await
+            .unwrap();
+
+        let ticks = store
+            .range("polymarket", "0x9c1a", "YES", 0, i64::MAX)
+            .unwrap();
+        assert_eq!(ticks, vec![tick.tick]);
+    }
+
+    #[tokio::test]
+    async fn test_resolution_watcher_polls_pending_markets() {
+        let server = MockServer::start().await.unwrap();
+        let ledger = ForecastLedger::temporary().unwrap();
+        let forecast = |platform: &str, id: &str| Forecast {
+            platform: platform.to_string(),
+            platform_id: Some(id.to_string()),
+            ..Forecast::fixture(0.7)
+        };
+        ledger
+            .record(&forecast("manifold", "9Ccsjc0fmbIb9g50p7SB"))
+            .unwrap();
+        ledger.record(&forecast("manifold", "missing")).unwrap();
+        let condition_id = "0x9c1a953fe92c8357f1b646ba25d983aa83e90c525992db14fb726fa895cb5763";
+        ledger
+            .record(&forecast("polymarket", condition_id))
+            .unwrap();
+
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        let manifold = ResolutionWatcher::new(
+            ledger.clone(),
+            Arc::new(ManifoldPlatform::from(builder)),
+            Duration::from_secs(60),
+        );
+        // Both markets are still open; the missing one is skipped, not fatal.
+        assert_eq!(manifold.poll().await.unwrap(), 0);
+        assert_eq!(ledger.pending_markets("manifold").unwrap().len(), 2);
+
+        let mut builder = PlatformBuilder::new();
+        builder.mock(&server.url());
+        let polymarket = ResolutionWatcher::new(
+            ledger.clone(),
+            Arc::new(PolymarketPlatform::from(builder)),
+            Duration::from_secs(60),
+        );
+        assert_eq!(polymarket.poll().await.unwrap(), 0);
+        assert_eq!(
+            ledger.pending_markets("polymarket").unwrap(),
+            vec![condition_id]
+        );
+    }
+}
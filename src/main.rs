@@ -1,15 +1,21 @@
 use crate::context::Context;
 use crate::executor::executor::{Executor, ManifoldExecutor};
+use crate::executor::recorder::ResolutionWatcher;
 use crate::types::{create_match, Market, Settings};
 use anyhow::Result;
-use api::Platform;
+use api::{Platform, PlatformError};
 use async_graphql::*;
 use axum::{
     extract::Query,
-    response::{Html, IntoResponse},
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
 };
 use context::StatefulList;
-use db::{manifold::ManifoldMarket, metaculus::MetaculusMarket, polymarket::PolymarketResult};
+use db::{
+    manifold::ManifoldMarket,
+    metaculus::MetaculusMarket,
+    polymarket::{PolymarketMarket, PolymarketToken},
+};
 use ratatui::widgets::{Block, List, ListItem};
 use ratatui::{
     backend::Backend,
@@ -57,15 +63,63 @@ async fn handler() -> Html<&'static str> {
     )
 }
 
-async fn manifold_markets_index(pagiation: Option<Query<Pagiation>>) -> impl IntoResponse {
+fn render_platform_error(error: PlatformError) -> Response {
+    tracing::error!("platform request failed: {}", error);
+    let status = match error {
+        PlatformError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        PlatformError::NotFound(_) => StatusCode::NOT_FOUND,
+        PlatformError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+        _ => StatusCode::BAD_GATEWAY,
+    };
+    let html = Html(format!(
+        r#"
+        <h1>Could not load markets</h1>
+        <p>{}</p>
+        "#,
+        escape_html(&error.to_string())
+    ));
+    (status, html).into_response()
+}
+
+/// Upstream errors echo remote response bodies, which must not render as markup.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builder for `P`, pointed at `mock_server` when there is one.
+fn platform_builder<P: Platform>(
+    mock_server: Option<&server::mock::MockServer>,
+) -> api::PlatformBuilder<P> {
+    let mut builder = api::PlatformBuilder::default();
+    if let Some(server) = mock_server {
+        builder.mock(&server.url());
+    }
+    builder
+}
+
+async fn manifold_markets_index(pagiation: Option<Query<Pagiation>>) -> Response {
     let pagiation = pagiation.unwrap_or_default();
     let offset = pagiation.offset.unwrap_or(0);
     let limit = pagiation.limit.unwrap_or(10);
-    let manifold = api::manifold::ManifoldPlatform::builder()
+    let manifold = match api::manifold::ManifoldPlatform::builder()
         .build()
         .fetch_questions()
         .await
-        .expect(">_< error");
+    {
+        Ok(markets) => markets,
+        Err(error) => return render_platform_error(error),
+    };
     tracing::debug!("manifold: {:#?}", &manifold);
     // match manifold.first() {
     //     Some(question) => {
@@ -76,7 +130,9 @@ async fn manifold_markets_index(pagiation: Option<Query<Pagiation>>) -> impl Int
     //     }
     // }
 
-    render_markets(manifold, offset, limit).await
+    render_markets(manifold, offset, limit)
+        .await
+        .into_response()
 
     // tracing::debug!("page: {:#?}", page);
     //    page
@@ -113,20 +169,25 @@ async fn render_markets(markets: Vec<ManifoldMarket>, offset: usize, limit: usiz
     Html(html)
 }
 
-async fn polymarket_markets_index(pagiation: Option<Query<Pagiation>>) -> impl IntoResponse {
+async fn polymarket_markets_index(pagiation: Option<Query<Pagiation>>) -> Response {
     let pagiation = pagiation.unwrap_or_default();
-    let questions = api::polymarket::PolymarketPlatform::builder()
+    let page = match api::polymarket::PolymarketPlatform::builder()
         .build()
-        .fetch_json()
+        .fetch_questions_page(None)
         .await
-        .expect(">_< error");
-    let result = PolymarketResult::from(questions);
-    render_polymarket(result, 0, 10).await
+    {
+        Ok(page) => page,
+        Err(error) => return render_platform_error(error),
+    };
+    render_polymarket(page.items, 0, 10).await.into_response()
 }
 
-async fn render_polymarket(results: PolymarketResult, offset: usize, limit: usize) -> Html<String> {
+async fn render_polymarket(
+    markets: Vec<PolymarketMarket>,
+    offset: usize,
+    limit: usize,
+) -> Html<String> {
     let mut html = String::new();
-    let markets = results.data;
     let page_length = markets.len() / limit;
     let page = 0;
     for i in 0..page_length {
@@ -143,17 +204,22 @@ async fn render_polymarket(results: PolymarketResult, offset: usize, limit: usiz
     Html(html)
 }
 
-async fn metaculus_markets_index(pagiation: Option<Query<Pagiation>>) -> impl IntoResponse {
+async fn metaculus_markets_index(pagiation: Option<Query<Pagiation>>) -> Response {
     let pagiation = pagiation.unwrap_or_default();
     let offset = pagiation.offset.unwrap_or(0);
     let limit = pagiation.limit.unwrap_or(10);
-    let metaculus_markets = api::metaculus::MetaculusPlatform::builder()
+    let metaculus_markets = match api::metaculus::MetaculusPlatform::builder()
         .build()
         .fetch_questions()
         .await
-        .expect(">_< error");
+    {
+        Ok(markets) => markets,
+        Err(error) => return render_platform_error(error),
+    };
 
-    render_metaculus_markets(metaculus_markets, offset, limit).await
+    render_metaculus_markets(metaculus_markets, offset, limit)
+        .await
+        .into_response()
 }
 
 async fn render_metaculus_markets(
@@ -205,20 +271,16 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
     let strat_config = Arc::new(RwLock::new(create_match()));
-    let question_schema = Schema::build(
-        model::question::QueryRoot,
-        model::question::MutationRoot,
-        model::question::SubscriptionRoot,
-    )
-    .data(model::question::QuestionStorage::default())
-    .finish();
+    let config = Arc::new(RwLock::new(Settings::new(create_match()).await));
+
+    let markets_db = config.read().unwrap().sled_config.open()?;
+    let repository = db::repository::MarketRepository::open(&markets_db)?;
+    let question_schema = db::build_schema(repository);
 
     tracing::debug!("connecting to graphql");
     // let executor =
     // ManifoldExecutor::new(Arc::new(api::manifold::ManifoldPlatform::from(PlatformBuilder::default())), Promptor{});
 
-    let config = Arc::new(RwLock::new(Settings::new(create_match()).await));
-
     let period = {
         let config_guard = config.read().unwrap();
         config_guard.period
@@ -228,23 +290,68 @@ async fn main() -> Result<()> {
         "questions_list: {:#?}",
         questions_list_rwlock.read().unwrap()
     );
-    let mut context: Context<Market> = Context::default();
+    // Kept alive for the whole run; platforms below point at it in mock mode.
+    let mock_server = if config.read().unwrap().mock {
+        Some(server::mock::MockServer::start().await?)
+    } else {
+        None
+    };
+    let mock = mock_server.as_ref();
+    let ledger = db::ledger::ForecastLedger::open(&markets_db)?;
+    let mut context: Context<Market> = Context::default().with_ledger(ledger.clone());
+    // The configured markets are asked as yes/no questions.
+    context.outcome = "yes".to_string();
     questions_list_rwlock
         .read()
         .unwrap()
         .iter()
         .for_each(|q| context.add_question(q.to_string()));
-    let executor = Box::new(ManifoldExecutor::new(
-            Arc::new(api::manifold::ManifoldPlatform::from(
-                api::PlatformBuilder::default(),
-            )),
-            executor::executor::Promptor {},
-        ));
+    let manifold = api::manifold::ManifoldPlatform::from(platform_builder(mock));
+    let group_aliases = config.read().unwrap().manifold_groups.clone();
+    let executor = Box::new(
+        ManifoldExecutor::new(Arc::new(manifold), executor::executor::Promptor {})
+            .with_group_aliases(group_aliases),
+    );
     // let executor = ExecutorMap::new(executor |market| match market {
     //     Market::NewMarket(m) => Some(m),
     //     Market::MarketPosition(p) => Some(p),
     // });
     context.add_executor(executor);
+    let series = db::timeseries::TimeSeriesStore::open(&markets_db)?;
+    context.add_executor(Box::new(executor::recorder::SeriesRecorder::new(series)));
+    context.add_collector(Box::new(context::MarketCollector::new(
+        Arc::new(api::manifold::ManifoldPlatform::from(platform_builder(
+            mock,
+        ))),
+        std::time::Duration::from_secs(period),
+    )));
+    // Settles the recorded forecasts as their markets resolve.
+    let poll = std::time::Duration::from_secs(period);
+    let platform = api::manifold::ManifoldPlatform::from(platform_builder(mock));
+    let watcher = ResolutionWatcher::new(ledger.clone(), Arc::new(platform), poll);
+    context.add_collector(Box::new(watcher));
+    let platform = api::polymarket::PolymarketPlatform::from(platform_builder(mock));
+    let watcher = ResolutionWatcher::new(ledger.clone(), Arc::new(platform), poll);
+    context.add_collector(Box::new(watcher));
+    let platform = api::metaculus::MetaculusPlatform::from(platform_builder(mock));
+    let watcher = ResolutionWatcher::new(ledger, Arc::new(platform), poll);
+    context.add_collector(Box::new(watcher));
+    // The mock server has no websocket, so live books only stream from the real CLOB.
+    if mock.is_none() {
+        let polymarket = api::polymarket::PolymarketPlatform::from(platform_builder(None));
+        match polymarket.fetch_questions_page(None).await {
+            Ok(page) => {
+                let tokens: Vec<PolymarketToken> = page
+                    .items
+                    .into_iter()
+                    .filter(|market| market.active && !market.closed.unwrap_or(false))
+                    .flat_map(|market| market.tokens.unwrap_or_default())
+                    .collect();
+                context.add_collector(Box::new(api::clob_stream::ClobCollector::new(&tokens)));
+            }
+            Err(err) => tracing::warn!("not streaming CLOB books: {}", err),
+        }
+    }
     if let Ok(mut set) = context.run().await {
         while let Some(res) = set.join_next().await {
             tracing::info!("res: {:?}", res);
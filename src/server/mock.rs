@@ -0,0 +1,429 @@
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use std::net::SocketAddr;
+use tokio::task::JoinHandle;
+
+const MANIFOLD_MARKET: &str = include_str!("../../tests/fixtures/manifold_market.json");
+const MANIFOLD_BETS: &str = include_str!("../../tests/fixtures/manifold_bets.json");
+const MANIFOLD_POSITIONS: &str = include_str!("../../tests/fixtures/manifold_positions.json");
+const MANIFOLD_GROUPS: &str = include_str!("../../tests/fixtures/manifold_groups.json");
+const POLYMARKET_MARKET: &str = include_str!("../../tests/fixtures/polymarket_market.json");
+const POLYMARKET_EVENTS: &str = include_str!("../../tests/fixtures/polymarket_events.json");
+const CLOB_MARKETS: &str = include_str!("../../tests/fixtures/polymarket_clob_markets.json");
+const CLOB_BOOK: &str = include_str!("../../tests/fixtures/polymarket_book.json");
+const CLOB_PRICES_HISTORY: &str =
+    include_str!("../../tests/fixtures/polymarket_prices_history.json");
+const METACULUS_MARKET: &str = include_str!("../../tests/fixtures/metaculus_market.json");
+const METACULUS_NUMERIC: &str = include_str!("../../tests/fixtures/metaculus_numeric.json");
+/// `createdTime` of bets the mock places, in milliseconds.
+const MOCK_TIME_MS: i64 = 1_730_419_200_000;
+
+/// Offline stand-in for the platform APIs, serving the recorded fixtures.
+///
+/// Each platform lives under its `Platform::NAME` (`/manifold`, `/polymarket`,
+/// `/gamma`, `/metaculus`) and the Polymarket CLOB under `/clob`, so pointing a
+/// builder at it is `PlatformBuilder::mock(&server.url())`.
+pub struct MockServer {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+    /// Serves on an ephemeral localhost port until dropped.
+    pub async fn start() -> std::io::Result<Self> {
+        Self::bind("127.0.0.1:0").await
+    }
+
+    pub async fn bind(addr: &str) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let handle = tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, router()).await {
+                tracing::error!("mock server stopped: {}", e);
+            }
+        });
+        tracing::info!("mock platform server listening on {}", addr);
+        Ok(Self { addr, handle })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+pub fn router() -> Router {
+    let manifold = Router::new()
+        .route("/markets", get(manifold_markets))
+        .route("/search-markets", get(manifold_search))
+        .route("/market/:id", get(manifold_market))
+        .route("/market/:id/positions", get(manifold_positions))
+        .route("/bets", get(manifold_bets))
+        .route("/bet", post(manifold_bet))
+        .route("/bet/cancel/:id", post(manifold_cancel))
+        .route("/market/:id/sell", post(manifold_sell))
+        .route("/groups", get(manifold_groups))
+        .route("/group/:slug", get(manifold_group));
+    let gamma = Router::new()
+        .route("/markets", get(gamma_markets))
+        .route("/markets/:id", get(gamma_market))
+        .route("/events", get(gamma_events));
+    let clob = Router::new()
+        .route("/markets", get(clob_markets))
+        .route("/markets/:condition_id", get(clob_market))
+        .route("/book", get(clob_book))
+        .route("/prices-history", get(clob_prices_history))
+        .route("/midpoint", get(clob_midpoint))
+        .route("/spread", get(clob_spread))
+        .route("/tick-size", get(clob_tick_size))
+        .route("/neg-risk", get(clob_neg_risk))
+        .route("/order", post(clob_order).delete(clob_cancel));
+    let metaculus = Router::new()
+        .route("/posts/", get(metaculus_posts))
+        .route("/posts/:id/", get(metaculus_post))
+        .route("/questions/forecast/", post(metaculus_forecast));
+
+    Router::new()
+        .nest("/manifold", manifold)
+        .nest("/polymarket", gamma.clone())
+        .nest("/gamma", gamma)
+        .nest("/clob", clob)
+        .nest("/metaculus", metaculus)
+}
+
+fn fixture(body: &'static str) -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], body).into_response()
+}
+
+fn list(body: &'static str) -> Response {
+    let item: serde_json::Value = serde_json::from_str(body).expect("fixture is valid JSON");
+    Json(vec![item]).into_response()
+}
+
+#[derive(Deserialize)]
+struct ManifoldPage {
+    before: Option<String>,
+}
+
+async fn manifold_markets(Query(page): Query<ManifoldPage>) -> Response {
+    // A single page: anything after the fixture market is empty.
+    match page.before {
+        Some(_) => Json(Vec::<serde_json::Value>::new()).into_response(),
+        None => list(MANIFOLD_MARKET),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifoldSearch {
+    topic_slug: Option<String>,
+}
+
+async fn manifold_search(Query(search): Query<ManifoldSearch>) -> Response {
+    match search.topic_slug {
+        Some(slug) if !MANIFOLD_MARKET.contains(&format!("\"{}\"", slug)) => {
+            Json(Vec::<serde_json::Value>::new()).into_response()
+        }
+        _ => list(MANIFOLD_MARKET),
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifoldGroupsPage {
+    before_time: Option<i64>,
+}
+
+/// Groups created before `beforeTime`, newest first, two to a page.
+async fn manifold_groups(Query(page): Query<ManifoldGroupsPage>) -> Response {
+    let mut groups: Vec<serde_json::Value> =
+        serde_json::from_str(MANIFOLD_GROUPS).expect("fixture is valid JSON");
+    groups.sort_by_key(|group| std::cmp::Reverse(group["createdTime"].as_i64()));
+    let before = page.before_time.unwrap_or(i64::MAX);
+    let page: Vec<serde_json::Value> = groups
+        .into_iter()
+        .filter(|group| {
+            group["createdTime"]
+                .as_i64()
+                .is_some_and(|time| time < before)
+        })
+        .take(2)
+        .collect();
+    Json(page).into_response()
+}
+
+async fn manifold_group(Path(slug): Path<String>) -> Response {
+    let groups: Vec<serde_json::Value> =
+        serde_json::from_str(MANIFOLD_GROUPS).expect("fixture is valid JSON");
+    match groups
+        .into_iter()
+        .find(|group| group["slug"] == slug.as_str())
+    {
+        Some(group) => Json(group).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn manifold_market(Path(id): Path<String>) -> Response {
+    if MANIFOLD_MARKET.contains(&format!("\"id\": \"{}\"", id)) {
+        fixture(MANIFOLD_MARKET)
+    } else {
+        StatusCode::NOT_FOUND.into_response()
+    }
+}
+
+async fn manifold_positions(Path(_id): Path<String>) -> Response {
+    fixture(MANIFOLD_POSITIONS)
+}
+
+async fn manifold_bets() -> Response {
+    fixture(MANIFOLD_BETS)
+}
+
+fn authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Key "))
+}
+
+/// Fills market orders in full; limit orders fill half and rest the other half.
+async fn manifold_bet(headers: HeaderMap, Json(order): Json<serde_json::Value>) -> Response {
+    if !authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let amount = order["amount"].as_f64().unwrap_or_default();
+    let filled = if order["limitProb"].is_null() {
+        amount
+    } else {
+        amount / 2.0
+    };
+    let shares = filled / 0.625;
+    Json(serde_json::json!({
+        "betId": "mock-bet",
+        "contractId": order["contractId"],
+        "answerId": order["answerId"],
+        "createdTime": MOCK_TIME_MS,
+        "amount": filled,
+        "orderAmount": amount,
+        "shares": shares,
+        "outcome": order["outcome"],
+        "probBefore": 0.6,
+        "probAfter": 0.65,
+        "limitProb": order["limitProb"],
+        "isFilled": filled == amount,
+        "isCancelled": false,
+        "fills": [{
+            "amount": filled,
+            "shares": shares,
+            "timestamp": MOCK_TIME_MS,
+            "matchedBetId": null,
+        }],
+    }))
+    .into_response()
+}
+
+async fn manifold_sell(
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(sale): Json<serde_json::Value>,
+) -> Response {
+    if !authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let shares = sale["shares"].as_f64().unwrap_or(80.0);
+    Json(serde_json::json!({
+        "betId": "mock-sale",
+        "contractId": id,
+        "createdTime": MOCK_TIME_MS,
+        "amount": -shares * 0.625,
+        "shares": -shares,
+        "outcome": sale["outcome"].as_str().unwrap_or("YES"),
+        "probBefore": 0.65,
+        "probAfter": 0.6,
+        "isFilled": true,
+    }))
+    .into_response()
+}
+
+async fn manifold_cancel(headers: HeaderMap, Path(id): Path<String>) -> Response {
+    if !authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(serde_json::json!({
+        "betId": id,
+        "contractId": "9Ccsjc0fmbIb9g50p7SB",
+        "createdTime": MOCK_TIME_MS,
+        "amount": 5.0,
+        "orderAmount": 10.0,
+        "shares": 8.0,
+        "outcome": "YES",
+        "probBefore": 0.6,
+        "probAfter": 0.65,
+        "limitProb": 0.5,
+        "isFilled": false,
+        "isCancelled": true,
+    }))
+    .into_response()
+}
+
+async fn gamma_markets() -> Response {
+    list(POLYMARKET_MARKET)
+}
+
+async fn gamma_market(Path(_id): Path<String>) -> Response {
+    fixture(POLYMARKET_MARKET)
+}
+
+async fn gamma_events() -> Response {
+    fixture(POLYMARKET_EVENTS)
+}
+
+async fn clob_markets() -> Response {
+    fixture(CLOB_MARKETS)
+}
+
+async fn clob_market(Path(condition_id): Path<String>) -> Response {
+    let markets: serde_json::Value =
+        serde_json::from_str(CLOB_MARKETS).expect("fixture is valid JSON");
+    markets["data"]
+        .as_array()
+        .and_then(|markets| {
+            markets
+                .iter()
+                .find(|market| market["condition_id"] == condition_id.as_str())
+        })
+        .map(|market| Json(market.clone()).into_response())
+        .unwrap_or_else(|| StatusCode::NOT_FOUND.into_response())
+}
+
+async fn clob_book() -> Response {
+    fixture(CLOB_BOOK)
+}
+
+async fn clob_prices_history() -> Response {
+    fixture(CLOB_PRICES_HISTORY)
+}
+
+async fn clob_midpoint() -> Response {
+    Json(serde_json::json!({ "mid": "0.62" })).into_response()
+}
+
+async fn clob_spread() -> Response {
+    Json(serde_json::json!({ "spread": "0.02" })).into_response()
+}
+
+async fn clob_tick_size() -> Response {
+    Json(serde_json::json!({ "minimum_tick_size": 0.01 })).into_response()
+}
+
+async fn clob_neg_risk() -> Response {
+    Json(serde_json::json!({ "neg_risk": false })).into_response()
+}
+
+/// Accepts any order carrying the L2 headers, without checking the signatures.
+async fn clob_order(headers: HeaderMap, Json(payload): Json<serde_json::Value>) -> Response {
+    if !headers.contains_key("poly_signature") || payload["order"]["signature"].is_null() {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    Json(serde_json::json!({
+        "success": true,
+        "errorMsg": "",
+        "orderID": "0xmock-order",
+        "status": "live",
+    }))
+    .into_response()
+}
+
+async fn clob_cancel(Json(body): Json<serde_json::Value>) -> Response {
+    Json(serde_json::json!({
+        "canceled": [body["orderID"]],
+        "not_canceled": {},
+    }))
+    .into_response()
+}
+
+async fn metaculus_posts() -> Response {
+    let market: serde_json::Value =
+        serde_json::from_str(METACULUS_MARKET).expect("fixture is valid JSON");
+    Json(serde_json::json!({
+        "results": [market],
+        "next": null,
+        "previous": null,
+    }))
+    .into_response()
+}
+
+fn metaculus_authorized(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("Token "))
+}
+
+async fn metaculus_post(headers: HeaderMap, Path(id): Path<String>) -> Response {
+    if !metaculus_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    match id.as_str() {
+        "28072" => fixture(METACULUS_MARKET),
+        "31342" => fixture(METACULUS_NUMERIC),
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Accepts a list of forecasts with an empty 201, like Metaculus.
+async fn metaculus_forecast(
+    headers: HeaderMap,
+    Json(forecasts): Json<Vec<serde_json::Value>>,
+) -> Response {
+    if !metaculus_authorized(&headers) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    if forecasts
+        .iter()
+        .any(|forecast| !forecast["question"].is_u64())
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    StatusCode::CREATED.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_fixtures() {
+        let server = MockServer::start().await.unwrap();
+        let client = reqwest::Client::new();
+
+        let markets: Vec<serde_json::Value> = client
+            .get(format!("{}/manifold/markets", server.url()))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(markets[0]["id"], "9Ccsjc0fmbIb9g50p7SB");
+
+        let missing = client
+            .get(format!("{}/manifold/market/missing", server.url()))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+    }
+}
@@ -1,15 +1,16 @@
-use clap::{Arg, ArgMatches, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
 use qdrant_client::{config::QdrantConfig, Qdrant};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 use toml::Value;
-type OutcomeSeries = HashMap<String, Vec<Tick>>;
+/// Ticks per outcome name, ordered by timestamp.
+pub type OutcomeSeries = HashMap<String, Vec<Tick>>;
 
 #[derive(Debug, Clone)]
 pub enum Market {
     NewMarket(MarketStandarized),
-    MarketPosition(Tick),
+    MarketPosition(OutcomeTick),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -41,8 +42,13 @@ impl FromStr for OutcomeType {
 impl From<serde_json::Value> for Tick {
     fn from(value: serde_json::Value) -> Self {
         let timestamp = value["timestamp"].as_i64().unwrap();
+        let probability = value["probability"].as_f64().unwrap_or_default();
         let volume = value["volume"].as_f64().unwrap();
-        Tick { timestamp, volume }
+        Tick {
+            timestamp,
+            probability,
+            volume,
+        }
     }
 }
 
@@ -57,8 +63,8 @@ pub struct MarketStandarized {
     pub outcome_type: Option<OutcomeType>,
     // pub pool: Option<BetPool>,
     // pub indicators: Option<Indicators>,
-    //TODO: add this when can handle multiple choice
-    // pub outcome_series: Vec<OutcomeSeries>,
+    #[serde(default)]
+    pub outcome_series: OutcomeSeries,
 }
 
 impl Default for MarketStandarized {
@@ -73,6 +79,7 @@ impl Default for MarketStandarized {
             outcome_type: None,
             // pool: None,
             // indicators: None,
+            outcome_series: OutcomeSeries::new(),
         }
     }
 }
@@ -116,6 +123,7 @@ impl From<serde_json::Value> for MarketStandarized {
             outcome_type: Some(outcome_type),
             // pool: Some(BetPool::from(pool)),
             //            indicators: Some(Indicators::from(indicators)),
+            outcome_series: OutcomeSeries::new(),
         }
     }
 }
@@ -170,12 +178,24 @@ pub enum StrategyType {
     MARKETMAKING,
 }
 
-#[derive(Deserialize, Debug, Serialize, Clone, Default, Copy)]
+/// Observation of one outcome at `timestamp` (unix seconds).
+#[derive(Deserialize, Debug, Serialize, Clone, Default, Copy, PartialEq)]
 pub struct Tick {
     pub timestamp: i64,
+    #[serde(default)]
+    pub probability: f64,
     pub volume: f64,
 }
 
+/// A `Tick` tagged with the series it belongs to.
+#[derive(Deserialize, Debug, Serialize, Clone, PartialEq)]
+pub struct OutcomeTick {
+    pub platform: String,
+    pub market_id: String,
+    pub outcome: String,
+    pub tick: Tick,
+}
+
 #[derive(Clone)]
 pub struct StrategyConfig {
     pub id: String,
@@ -202,6 +222,10 @@ pub struct Settings {
     pub markets: Vec<String>,
     pub period: u64,
     pub sled_config: sled::Config,
+    /// Serve platform APIs from `server::mock::MockServer` instead of the network.
+    pub mock: bool,
+    /// Manifold group slug by tag, for tags that name no group themselves.
+    pub manifold_groups: HashMap<String, String>,
 }
 
 impl Default for Settings {
@@ -210,6 +234,8 @@ impl Default for Settings {
             markets: vec![],
             period: 60,
             sled_config: sled::Config::default(),
+            mock: false,
+            manifold_groups: HashMap::new(),
         }
     }
 }
@@ -217,6 +243,7 @@ impl Default for Settings {
 impl Settings {
     pub async fn new(matches: Command) -> Settings {
         let matches = matches.get_matches();
+        let mock = matches.get_flag("mock");
         let path = matches.get_one::<String>("config").unwrap();
         let file: Option<String> = match fs::read_to_string(path) {
             Ok(file) => Some(file),
@@ -225,17 +252,24 @@ impl Settings {
 
         if let Some(file) = file {
             tracing::info!("Using config file at {}", path);
-            return Settings::create_from_file(file).await;
+            return Settings {
+                mock,
+                ..Settings::create_from_file(file).await
+            };
         }
 
         tracing::info!("Using command line arguments for settings...");
-        Settings::create_from_matches(matches)
+        Settings {
+            mock,
+            ..Settings::create_from_matches(matches)
+        }
     }
 
     async fn create_from_file(config_file: String) -> Settings {
         let parsed_toml = config_file.parse::<Value>().expect("Error parsing TOML");
         let table_names: Vec<&String> = parsed_toml.as_table().unwrap().keys().collect::<Vec<_>>();
         let mut question_list: Vec<String> = Vec::new();
+        let mut manifold_groups: HashMap<String, String> = HashMap::new();
         let rpredict_table = parsed_toml.get("rpredict").unwrap().as_table().unwrap();
         let period = rpredict_table.get("period").unwrap().as_integer().unwrap() as u64;
         let sled_table = parsed_toml
@@ -263,12 +297,23 @@ impl Settings {
                     let question = question.as_str().unwrap();
                     question_list.push(question.to_string());
                 }
+                let groups = platform_table.get("groups").and_then(Value::as_table);
+                if let Some(groups) = groups.filter(|_| table_name == "manifold") {
+                    for (tag, slug) in groups {
+                        let slug = slug
+                            .as_str()
+                            .expect("\x1b[31mErr:\x1b[0m Group slugs must be strings!");
+                        manifold_groups.insert(tag.to_string(), slug.to_string());
+                    }
+                }
             }
         }
         Settings {
             markets: question_list,
             period,
             sled_config,
+            mock: false,
+            manifold_groups,
         }
     }
     fn create_from_matches(matches: ArgMatches) -> Settings {
@@ -285,6 +330,8 @@ impl Settings {
             markets: questions_list,
             period,
             sled_config: sled::Config::default(),
+            mock: false,
+            manifold_groups: HashMap::new(),
         }
     }
 }
@@ -320,6 +367,12 @@ pub fn create_match() -> clap::Command {
                 .default_value("rpredict-cache")
                 .help("Database path"),
         )
+        .arg(
+            Arg::new("mock")
+                .long("mock")
+                .action(ArgAction::SetTrue)
+                .help("Serve platform APIs from bundled fixtures instead of the network"),
+        )
 }
 // impl Default for Context {
 //     fn default() -> Self {